@@ -0,0 +1,358 @@
+//envelope encryption for patients' health data: every value is encrypted with its own data key, which is
+//stored next to it wrapped by a master key from the KeyProvider, so rotating the master key only rewraps
+//the data keys. ENCRYPTION_KEY_FILE points at a local key file (meant for development), without it new
+//values are stored in plaintext. Values are bound to their table, column and row, so a sealed value copied
+//somewhere else in the database doesn't open
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use dotenvy::dotenv;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Serialize, Serializer};
+use sqlx::{
+    error::BoxDynError,
+    postgres::{PgTypeInfo, PgValueRef},
+    Decode, Postgres, Type,
+};
+use std::env;
+use std::fmt::{self, Display};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+const PREFIX: &str = "enc:v2:";
+//sealed without a location, still opened but resealed by `hackshetra23 encrypt`
+const UNBOUND_PREFIX: &str = "enc:v1:";
+const KEY_BYTES: usize = 32;
+const NONCE_BYTES: usize = 12;
+
+//every column holding sealed values, `hackshetra23 encrypt` goes through these
+pub const SEALED_COLUMNS: [(&str, &str); 8] = [
+    ("prescriptions", "prescription"),
+    ("appointments", "symptom"),
+    ("tokens", "symptom"),
+    ("emergency_appointments", "symptom"),
+    ("patients", "gender"),
+    ("patients", "weight"),
+    ("patients", "age"),
+    ("patients", "blood_group"),
+];
+
+pub trait KeyProvider: Send + Sync {
+    //the master key new data keys are wrapped with, and its ID
+    fn current(&self) -> (&str, &[u8; KEY_BYTES]);
+    //any master key values may still be wrapped with, the current one included
+    fn key(&self, id: &str) -> Option<&[u8; KEY_BYTES]>;
+}
+
+//one key per line as <id>:<hex key>, the first line is the current key and the ones below it are only
+//used to open values sealed before the last rotation
+pub struct KeyFile {
+    keys: Vec<(String, [u8; KEY_BYTES])>,
+}
+
+impl KeyFile {
+    pub fn load(path: &str) -> Result<KeyFile, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("Couldn't read key file {}: {}", path, e))?;
+        let mut keys = Vec::new();
+        for line in contents.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (id, key) = line
+                .split_once(':')
+                .ok_or_else(|| format!("Key file {} has a line without an ID", path))?;
+            if !acceptable_id(id) {
+                return Err(format!("Key file {} has an invalid key ID {}", path, id));
+            }
+            let key: [u8; KEY_BYTES] = hex::decode(key)
+                .ok()
+                .and_then(|key| key.try_into().ok())
+                .ok_or_else(|| format!("Key {} in {} isn't {} hex encoded bytes", id, path, KEY_BYTES))?;
+            keys.push((id.to_string(), key));
+        }
+        if keys.is_empty() {
+            return Err(format!("Key file {} has no keys", path));
+        }
+        Ok(KeyFile { keys })
+    }
+}
+
+impl KeyProvider for KeyFile {
+    fn current(&self) -> (&str, &[u8; KEY_BYTES]) {
+        let (id, key) = &self.keys[0];
+        (id, key)
+    }
+
+    fn key(&self, id: &str) -> Option<&[u8; KEY_BYTES]> {
+        self.keys.iter().find(|(key_id, _)| key_id == id).map(|(_, key)| key)
+    }
+}
+
+fn acceptable_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+//adds a new current key at the top of the key file, creating it when there is none yet, and returns its ID
+pub fn generate_key(path: &str) -> Result<String, String> {
+    let existing = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("Couldn't read key file {}: {}", path, e)),
+    };
+    let id = chrono::Utc::now().format("k%Y%m%d%H%M%S").to_string();
+    if existing.lines().any(|line| line.split(':').next() == Some(id.as_str())) {
+        return Err(format!("Key {} already exists, try again in a second", id));
+    }
+    let mut key = [0u8; KEY_BYTES];
+    OsRng.fill_bytes(&mut key);
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(path)
+        .map_err(|e| format!("Couldn't write key file {}: {}", path, e))?;
+    write!(file, "{}:{}\n{}", id, hex::encode(key), existing)
+        .map_err(|e| format!("Couldn't write key file {}: {}", path, e))?;
+    Ok(id)
+}
+
+//loaded once, a changed key file needs a restart; Err when one is configured but can't be used, so
+//nothing gets written in plaintext by mistake
+static PROVIDER: OnceLock<Result<Option<Box<dyn KeyProvider>>, String>> = OnceLock::new();
+
+fn provider() -> Result<Option<&'static dyn KeyProvider>, &'static str> {
+    let provider = PROVIDER.get_or_init(|| {
+        dotenv().ok();
+        match env::var("ENCRYPTION_KEY_FILE") {
+            Ok(path) if !path.is_empty() => {
+                let file = KeyFile::load(&path)?;
+                tracing::debug!("Sealing health data with key {}", file.current().0);
+                Ok(Some(Box::new(file) as Box<dyn KeyProvider>))
+            }
+            _ => {
+                tracing::warn!("ENCRYPTION_KEY_FILE isn't set, health data is stored in plaintext");
+                Ok(None)
+            }
+        }
+    });
+    match provider {
+        Ok(provider) => Ok(provider.as_deref()),
+        Err(e) => Err(e),
+    }
+}
+
+//loads the key provider up front, so a broken key file is noticed at startup instead of by the first request
+pub fn check() -> Result<(), &'static str> {
+    provider().map(|_| ())
+}
+
+fn encrypt(key: &[u8; KEY_BYTES], plain: &[u8], aad: &[u8]) -> Option<String> {
+    let cipher = Aes256Gcm::new_from_slice(key).ok()?;
+    let mut nonce = [0u8; NONCE_BYTES];
+    OsRng.fill_bytes(&mut nonce);
+    let mut sealed = nonce.to_vec();
+    sealed.extend(cipher.encrypt(Nonce::from_slice(&nonce), Payload { msg: plain, aad }).ok()?);
+    Some(STANDARD.encode(sealed))
+}
+
+fn decrypt(key: &[u8], sealed: &str, aad: &[u8]) -> Result<Vec<u8>, String> {
+    let sealed = STANDARD.decode(sealed).map_err(|_| String::from("Sealed value isn't base64"))?;
+    if sealed.len() < NONCE_BYTES {
+        return Err(String::from("Sealed value is too short"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_BYTES);
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| String::from("Invalid key length"))?;
+    cipher
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+        .map_err(|_| String::from("Sealed value doesn't decrypt, wrong key or tampered with"))
+}
+
+//where a sealed value lives, authenticated along with it
+pub fn location(table: &str, column: &str, id: i64) -> String {
+    format!("{}.{}.{}", table, column, id)
+}
+
+//enc:v2:<master key ID>:<wrapped data key>:<value>, the master key ID is authenticated along with the data
+//key and the location along with the value; also returns whether the value is bound to its location
+fn parse(stored: &str) -> Option<(bool, &str, &str, &str)> {
+    let (bound, rest) = match stored.strip_prefix(PREFIX) {
+        Some(rest) => (true, rest),
+        None => (false, stored.strip_prefix(UNBOUND_PREFIX)?),
+    };
+    let mut parts = rest.splitn(3, ':');
+    Some((bound, parts.next()?, parts.next()?, parts.next()?))
+}
+
+fn seal_with(provider: &dyn KeyProvider, plain: &str, location: &str) -> Option<String> {
+    let (id, master) = provider.current();
+    let mut data_key = [0u8; KEY_BYTES];
+    OsRng.fill_bytes(&mut data_key);
+    let wrapped = encrypt(master, &data_key, id.as_bytes())?;
+    let value = encrypt(&data_key, plain.as_bytes(), location.as_bytes())?;
+    Some(format!("{}{}:{}:{}", PREFIX, id, wrapped, value))
+}
+
+//what gets written to a sealed column at the given location, None when encryption is configured but broken
+pub fn seal(plain: &str, location: &str) -> Option<String> {
+    match provider() {
+        Ok(Some(provider)) => {
+            let sealed = seal_with(provider, plain, location);
+            if sealed.is_none() {
+                tracing::error!("Couldn't seal value");
+            }
+            sealed
+        }
+        Ok(None) => Some(plain.to_string()),
+        Err(e) => {
+            tracing::error!("Can't seal values: {}", e);
+            None
+        }
+    }
+}
+
+fn unwrap_data_key(provider: &dyn KeyProvider, id: &str, wrapped: &str) -> Result<Vec<u8>, String> {
+    let master = provider
+        .key(id)
+        .ok_or_else(|| format!("No master key {} in the key provider", id))?;
+    decrypt(master, wrapped, id.as_bytes())
+}
+
+fn open_with(provider: &dyn KeyProvider, id: &str, wrapped: &str, value: &str, aad: &str) -> Result<String, String> {
+    let data_key = unwrap_data_key(provider, id, wrapped)?;
+    let plain = decrypt(&data_key, value, aad.as_bytes())?;
+    String::from_utf8(plain).map_err(|_| String::from("Sealed value isn't UTF-8"))
+}
+
+//values written before encryption was turned on are returned as they are
+pub fn open(stored: &str, location: &str) -> Result<String, String> {
+    let Some((bound, id, wrapped, value)) = parse(stored) else {
+        return Ok(stored.to_string());
+    };
+    let provider = provider()?.ok_or_else(|| String::from("Value is sealed but ENCRYPTION_KEY_FILE isn't set"))?;
+    open_with(provider, id, wrapped, value, if bound { location } else { "" })
+}
+
+//seals a plaintext or unbound value, or rewraps the data key of one sealed with an older master key; None
+//when the value is already sealed with the current one
+pub fn reseal(stored: &str, location: &str) -> Result<Option<String>, String> {
+    let provider = provider()?.ok_or_else(|| String::from("ENCRYPTION_KEY_FILE isn't set"))?;
+    reseal_with(provider, stored, location)
+}
+
+fn reseal_with(provider: &dyn KeyProvider, stored: &str, location: &str) -> Result<Option<String>, String> {
+    let (current, master) = provider.current();
+    let plain = match parse(stored) {
+        None => stored.to_string(),
+        Some((false, id, wrapped, value)) => open_with(provider, id, wrapped, value, "")?,
+        Some((true, id, _, _)) if id == current => return Ok(None),
+        Some((true, id, wrapped, value)) => {
+            let data_key = unwrap_data_key(provider, id, wrapped)?;
+            let wrapped = encrypt(master, &data_key, current.as_bytes())
+                .ok_or_else(|| String::from("Couldn't wrap data key"))?;
+            return Ok(Some(format!("{}{}:{}:{}", PREFIX, current, wrapped, value)));
+        }
+    };
+    seal_with(provider, &plain, location)
+        .map(Some)
+        .ok_or_else(|| String::from("Couldn't seal value"))
+}
+
+//a column written with seal(), opened when it's read out of the database; it has to be selected as
+//<location>|<stored value> so the location can be checked
+#[derive(Clone, Copy)]
+pub struct Sealed<T>(pub T);
+
+impl<T> Deref for Sealed<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Display> Display for Sealed<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<T: Serialize> Serialize for Sealed<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<T> Type<Postgres> for Sealed<T> {
+    fn type_info() -> PgTypeInfo {
+        <String as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl<'r, T: FromStr> Decode<'r, Postgres> for Sealed<T> {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        let selected = <String as Decode<Postgres>>::decode(value)?;
+        let (location, stored) = selected
+            .split_once('|')
+            .ok_or("Sealed column was selected without its location")?;
+        let plain = open(stored, location)?;
+        match plain.parse() {
+            Ok(value) => Ok(Sealed(value)),
+            Err(_) => Err(format!("Sealed value isn't a valid {}", std::any::type_name::<T>()).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> KeyFile {
+        KeyFile { keys: vec![(String::from("new"), [2u8; KEY_BYTES]), (String::from("old"), [1u8; KEY_BYTES])] }
+    }
+
+    fn open_at(provider: &KeyFile, stored: &str, location: &str) -> Result<String, String> {
+        let (bound, id, wrapped, value) = parse(stored).ok_or("not sealed")?;
+        open_with(provider, id, wrapped, value, if bound { location } else { "" })
+    }
+
+    #[test]
+    fn sealed_value_only_opens_where_it_was_sealed() {
+        let provider = keys();
+        let here = location("patients", "blood_group", 1);
+        let sealed = seal_with(&provider, "AB+", &here).unwrap();
+        assert_eq!(open_at(&provider, &sealed, &here).unwrap(), "AB+");
+        assert!(open_at(&provider, &sealed, &location("patients", "blood_group", 2)).is_err());
+        assert!(open_at(&provider, &sealed, &location("patients", "gender", 1)).is_err());
+    }
+
+    #[test]
+    fn reseal_binds_unbound_values_and_rewraps_old_keys() {
+        let provider = keys();
+        let here = location("appointments", "symptom", 7);
+        let (_, old) = provider.keys[1].clone();
+        let old_only = KeyFile { keys: vec![(String::from("old"), old)] };
+        let data_key = [3u8; KEY_BYTES];
+        let unbound = format!(
+            "{}old:{}:{}",
+            UNBOUND_PREFIX,
+            encrypt(&old, &data_key, b"old").unwrap(),
+            encrypt(&data_key, b"fever", &[]).unwrap()
+        );
+        assert_eq!(open_at(&provider, &unbound, "anywhere").unwrap(), "fever");
+        let bound = reseal_with(&provider, &unbound, &here).unwrap().unwrap();
+        assert!(bound.starts_with("enc:v2:new:"));
+        assert_eq!(open_at(&provider, &bound, &here).unwrap(), "fever");
+        assert!(reseal_with(&provider, &bound, &here).unwrap().is_none());
+        let sealed_old = seal_with(&old_only, "fever", &here).unwrap();
+        let rewrapped = reseal_with(&provider, &sealed_old, &here).unwrap().unwrap();
+        assert_eq!(open_at(&provider, &rewrapped, &here).unwrap(), "fever");
+        assert!(open_at(&provider, &rewrapped, &location("tokens", "symptom", 7)).is_err());
+    }
+}
@@ -2,16 +2,85 @@
 use argon_hash_password;
 use chrono::NaiveDate;
 use dotenvy::dotenv;
+use hmac::{Hmac, Mac};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use rand::{rngs::OsRng, Rng, RngCore};
+use sha2::Sha256;
 use sqlx::{postgres::PgPoolOptions, postgres::PgRow, Pool, Postgres, Row};
 use std::env;
 use tracing;
 
+use crate::crypto::{self, Sealed};
 use crate::db_structs::*;
+use crate::totp;
+
+//number of HMAC bytes printed on a prescription as its verification code
+const PRESCRIPTION_CODE_BYTES: usize = 10;
+//how long after a visit the doctor can still look at the patient's records
+const RECENT_VISIT_DAYS: i32 = 30;
+//how close a misspelt search has to be to count as a match, between 0 and 1
+const SEARCH_SIMILARITY: f32 = 0.4;
+const EARTH_RADIUS_KM: f64 = 6371.0;
+const KM_PER_DEGREE: f64 = 111.045;
+const DEFAULT_RADIUS_KM: f64 = 10.0;
+//how far ahead to look for a doctor's next free slot
+const NEXT_SLOT_DAYS: i32 = 14;
+const MAX_SLOT_SEARCH_DAYS: i64 = 60;
+//mailed tokens for verifying an email and resetting a password
+const AUTH_TOKEN_BYTES: usize = 32;
+const VERIFY_EMAIL_VALIDITY: &str = "24 hours";
+const RESET_PASSWORD_VALIDITY: &str = "1 hour";
+const LOGIN_CHALLENGE_VALIDITY: &str = "5 minutes";
+//texted codes for logging in with a phone number, and how many tries one gets
+const PHONE_OTP_VALIDITY: &str = "10 minutes";
+const PHONE_OTP_ATTEMPTS: i32 = 5;
+//verification documents are sent as base64 in JSON, which has to fit in axum's 2MB request body limit
+const MAX_DOCUMENT_BYTES: usize = 1_500_000;
+const DOCUMENT_TYPES: [&str; 3] = ["application/pdf", "image/jpeg", "image/png"];
+//wrong passwords in a row before a login gets locked, the lock doubles from LOCKOUT_BASE with each further one
+const LOCKOUT_AFTER: i32 = 5;
+const LOCKOUT_BASE: &str = "1 minute";
+const LOCKOUT_MAX: &str = "1 day";
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+//what a deleted patient is shown as in the records that are kept
+const DELETED_PATIENT_NAME: &str = "Deleted patient";
+//rows `hackshetra23 encrypt` reads at a time
+const RESEAL_BATCH: i64 = 500;
 
 pub struct Database {
     jwt_secret: Vec<u8>,
     connection: Pool<Postgres>,
+    //REQUIRE_EMAIL_VERIFICATION, logins with an unverified email can't log in
+    require_email_verification: bool,
+}
+
+//why a signup didn't go through, so the email being taken can be told apart from bad input
+pub enum SignupError {
+    Duplicate,
+    Failed,
+}
+
+//why a login was refused, a locked account says for how many more seconds
+pub enum LoginError {
+    Invalid,
+    Locked(i64),
+}
+
+//a read or write of patients' health data, recorded in Audit_Log by record_access
+pub struct MedicalAccess<'a> {
+    pub request_id: &'a str,
+    pub actor_id: i64,
+    pub actor_role: &'a str,
+    //resource.read, resource.create...
+    pub action: &'a str,
+    pub resource_id: Option<i64>,
+}
+
+//a password login either finishes or still needs the second factor for the challenge
+pub enum LoginStep {
+    Token(String),
+    Challenge(String),
 }
 
 pub async fn init() -> Option<Database> {
@@ -31,6 +100,10 @@ pub async fn init() -> Option<Database> {
             return Some(Database {
                 connection: pool,
                 jwt_secret: sec.as_bytes().to_vec(),
+                require_email_verification: matches!(
+                    env::var("REQUIRE_EMAIL_VERIFICATION").as_deref(),
+                    Ok("true") | Ok("1")
+                ),
             });
         }
         Err(e) => {
@@ -41,6 +114,120 @@ pub async fn init() -> Option<Database> {
     }
 }
 
+//great circle distance in km from the given point to clinic c's coordinates (haversine)
+fn distance_sql(lat: f64, lng: f64) -> String {
+    format!(
+        "({EARTH_RADIUS_KM} * 2 * asin(sqrt(power(sin(radians(c.latitude - {lat}) / 2), 2)
+        + cos(radians({lat})) * cos(radians(c.latitude)) * power(sin(radians(c.longitude - {lng}) / 2), 2))))"
+    )
+}
+
+//cheap bounding box around the point so the haversine only runs on nearby rows and can use the index
+fn bounding_box_sql(lat: f64, lng: f64, radius: f64) -> String {
+    let latdelta = radius / KM_PER_DEGREE;
+    let lngdelta = radius / (KM_PER_DEGREE * lat.to_radians().cos().abs().max(0.01));
+    format!(
+        "c.latitude between {} and {} and c.longitude between {} and {}",
+        lat - latdelta,
+        lat + latdelta,
+        lng - lngdelta,
+        lng + lngdelta
+    )
+}
+
+//every slot of doctor d at clinic c that isn't booked, between the two SQL date expressions (inclusive) and not in the past,
+//as slot_id and slot (the timestamp it starts at); slots repeat every day so only their time of day matters
+fn free_slots_sql(from: &str, to: &str) -> String {
+    format!(
+        "select s.id as slot_id, day::date + s.time_start::time as slot from doctor_slots s
+        cross join generate_series(({from})::timestamp, ({to})::timestamp, interval '1 day') day
+        where s.doctor_id = d.id and s.clinic_id = c.id and day::date + s.time_start::time > localtimestamp
+        and not exists (select 1 from appointments a where a.slot_id = s.id
+            and a.appointment_date::date = day::date and a.status <> 'cancelled')"
+    )
+}
+
+//earliest free slot of doctor d at clinic c, see free_slots_sql
+fn next_slot_sql(from: &str, to: &str) -> String {
+    format!("(select min(slot) from ({}) f)", free_slots_sql(from, to))
+}
+
+//average rating and number of ratings for doctor d, leaving out hidden reviews
+fn rating_sql() -> &'static str {
+    "(select avg(r.rating)::double precision from reviews r where r.doctor_id = d.id and not r.hidden) as rating,
+    (select count(*) from reviews r where r.doctor_id = d.id and not r.hidden) as rating_count"
+}
+
+//only doctors an admin has verified show up in listings for patients
+fn verified_sql() -> &'static str {
+    "d.verification_status = 'verified'"
+}
+
+//names of all the specialities doctor d practises, comma separated
+fn specialities_sql() -> &'static str {
+    "(select coalesce(string_agg(s.name, ', ' order by s.name), '') from doctor_specialities ds
+    join specialities s on s.id = ds.speciality_id where ds.doctor_id = d.id)"
+}
+
+//the login column pointing at the profile of a role, admins are their own login row
+fn profile_column(role: &str) -> Option<&'static str> {
+    match role {
+        "patient" => Some("patient_id"),
+        "doctor" => Some("doctor_id"),
+        "receptionist" => Some("receptionist_id"),
+        "admin" => Some("id"),
+        _ => None,
+    }
+}
+
+fn unique_violation(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .and_then(|e| e.code())
+        .is_some_and(|code| code == "23505")
+}
+
+//quotes doubled up so user supplied text can go inside an SQL string literal
+pub fn escape(text: &str) -> String {
+    text.replace('\'', "''")
+}
+
+//an SQL string literal holding the sealed value for row id of one of crypto::SEALED_COLUMNS
+fn sealed(text: &str, table: &str, column: &str, id: i64) -> Option<String> {
+    crypto::seal(text, &crypto::location(table, column, id)).map(|sealed| format!("'{}'", escape(&sealed)))
+}
+
+//selects one of crypto::SEALED_COLUMNS prefixed with its location, the way Sealed<T> expects it
+fn sealed_column(table: &str, alias: &str, column: &str) -> String {
+    format!("'{}.{}.' || {}.id || '|' || {}.{} as {}", table, column, alias, alias, column, column)
+}
+
+//the text around search matches is doctor supplied, so ts_headline marks matches with control characters
+//and everything else is HTML escaped before the marks become <b></b>
+fn highlight_html(headline: &str) -> String {
+    let mut html = String::with_capacity(headline.len());
+    for c in headline.chars() {
+        match c {
+            '\u{2}' => html.push_str("<b>"),
+            '\u{3}' => html.push_str("</b>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+//comma separated ids for use in an SQL "in" clause
+fn id_list(ids: &[i64]) -> String {
+    ids.iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
 impl Database {
     async fn get_query_result<ResultStruct, DB>(&self, query: &String) -> Vec<ResultStruct>
     where
@@ -62,370 +249,3056 @@ impl Database {
         }
     }
 
+    //the id a row is going to get, taken up front so its sealed values can be bound to it
+    async fn next_id(&self, table: &str) -> Option<i64> {
+        let query = format!("
+                    select nextval(pg_get_serial_sequence('{}', 'id')) as id;
+                            ", table);
+        match sqlx::query(&query).fetch_one(&self.connection).await {
+            Ok(row) => row.try_get("id").ok(),
+            Err(e) => {
+                tracing::error!("Error while taking an id for {}: {}", table, e);
+                None
+            }
+        }
+    }
+
     //tries to find patient/doctor logging in with credentials and gives JWT if successful
-    pub async fn login(&self, email: &String, password: &String) -> Option<String> {
-        let query = format!(
+    fn login_sql(condition: &str) -> String {
+        format!(
             "
-                    select salt, password as hashedpass, isdoctor from login where email = '{}';
+                    select coalesce(salt, '') as salt, coalesce(password, '') as hashedpass, isdoctor, role, disabled, email_verified, totp_enabled, id as login_id,
+                    (case role when 'patient' then patient_id when 'doctor' then doctor_id
+                    when 'receptionist' then receptionist_id else id end)::bigint as id,
+                    ceil(greatest(0, extract(epoch from locked_until - now())))::bigint as locked_for
+                    from login where {};
                 ",
-            email
-        );
+            condition
+        )
+    }
+
+    //with 2FA on the password only gets a challenge, which /login/2fa exchanges for the JWT
+    pub async fn login(&self, email: &str, password: &str, user_agent: &str) -> Result<LoginStep, LoginError> {
+        let query = Self::login_sql(&format!("email = '{}'", escape(email)));
         match sqlx::query_as::<_, LoginTable>(&query)
             .fetch_one(&self.connection)
             .await
         {
             Ok(result) => {
+                if result.disabled {
+                    tracing::error!("Login for {} is disabled", email);
+                    return Err(LoginError::Invalid);
+                }
+                //the password isn't even checked while locked, so guesses during the lock tell nothing
+                if result.locked_for > 0 {
+                    tracing::error!("Login for {} is locked for {}s", email, result.locked_for);
+                    return Err(LoginError::Locked(result.locked_for));
+                }
+                if self.require_email_verification && !result.email_verified {
+                    tracing::error!("Email {} hasn't been verified yet", email);
+                    return Err(LoginError::Invalid);
+                }
                 let Ok(check) = argon_hash_password::check_password_matches_hash(
                     password,
                     &result.hashedpass,
                     &result.salt,
                 ) else {
                     tracing::debug!("Couldn't check password matches hash");
-                    return None;
+                    return Err(LoginError::Invalid);
                 };
-                if check {
-                    let mut tablename = "patients";
-                    if result.isdoctor {
-                        tablename = "doctors";
-                    }
-                    let query = format!(
-                        "
-                                    select id from {} where email = '{}';
-                                ",
-                        tablename, email
-                    );
-                    let Ok(queryres) = sqlx::query(&query)
-                        .fetch_one(&self.connection)
-                        .await else {
-                            tracing::error!("Error while checking login details in database");
-                            return None;
-                        };
-                    let Ok(id): Result<i64, _> = queryres.try_get("id") else {
-                        tracing::error!("Error while retrieving id from query result");
-                        return None;
-                    };
-                    let jwt = InternalJWT {
-                        isdoctor: result.isdoctor,
-                        id: id.to_string(),
-                        exp: 1000000,
-                    };
-                    let Ok(token) = encode(
-                        &Header::default(),
-                        &jwt,
-                        &EncodingKey::from_secret(&self.jwt_secret),
-                    ) else {
-                        tracing::debug!("Error while trying to encode JWT");
-                        return None;
-                    };
-                    Some(token)
-                } else {
-                    None
+                if !check {
+                    self.failed_login(result.login_id).await;
+                    return Err(LoginError::Invalid);
                 }
+                self.finish_login(&result, user_agent).await
             }
             Err(_) => {
                 tracing::debug!("No such user found!");
-                None
+                Err(LoginError::Invalid)
             }
         }
     }
 
-    pub fn verify_jwt(&self, jwt: &str) -> Option<JWT> {
-        let binding = match String::from(jwt)
-            .split("Bearer")
-            .collect::<Vec<&str>>()
-            .get(1)
-        {
-            Some(x) => x.to_string(),
-            None => jwt.to_string(),
+    //second step of a login with 2FA, the challenge can be retried until it expires but wrong codes count towards the lockout
+    pub async fn login_second_factor(&self, challenge: &str, code: &str, user_agent: &str) -> Result<String, LoginError> {
+        let Some(hash) = self.auth_token_hash("login_challenge", challenge.trim()) else {
+            return Err(LoginError::Invalid);
         };
-        let mut validation = Validation::default();
-        validation.validate_exp = false;
-        let token = binding.trim().to_string();
-        tracing::debug!("jwt : '{}'", token);
-        match decode::<InternalJWT>(
-            &token,
-            &DecodingKey::from_secret(&self.jwt_secret),
-            &validation,
-        ) {
-            Ok(token) => {
-                let Ok(id): Result<i64, _> = token.claims.id.parse() else {
-                    tracing::error!("Could not parse id while verifiying JWT");
-                    return None;
-                };
-                let res = JWT {
-                    isdoctor: token.claims.isdoctor,
-                    id,
-                };
-                Some(res)
+        let query = Self::login_sql(&format!(
+            "id = (select login_id from auth_tokens where token_hash = '{}' and purpose = 'login_challenge' and used_at is null and expires_at > now())",
+            hash
+        ));
+        let result = match sqlx::query_as::<_, LoginTable>(&query).fetch_optional(&self.connection).await {
+            Ok(Some(result)) => result,
+            Ok(None) => {
+                tracing::error!("Login challenge is invalid, used or expired");
+                return Err(LoginError::Invalid);
             }
-            Err(x) => {
-                tracing::debug!("{}", x);
-                None
+            Err(e) => {
+                tracing::error!("Error while looking up login challenge: {}", e);
+                return Err(LoginError::Invalid);
+            }
+        };
+        if result.disabled || !result.totp_enabled {
+            return Err(LoginError::Invalid);
+        }
+        if result.locked_for > 0 {
+            return Err(LoginError::Locked(result.locked_for));
+        }
+        if !self.second_factor_matches(result.login_id, code).await {
+            self.failed_login(result.login_id).await;
+            return Err(LoginError::Invalid);
+        }
+        let query = format!("update auth_tokens set used_at = now() where token_hash = '{}' and used_at is null;", hash);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) if res.rows_affected() == 1 => (),
+            _ => {
+                tracing::error!("Login challenge was used concurrently");
+                return Err(LoginError::Invalid);
             }
         }
+        self.start_session(&result, user_agent).await
     }
 
-    //get time slots for a doctor
-    pub async fn view_doctor_timeslots(&self, doctor_id: i64, date: &String) -> Vec<Timeslots> {
-        let query = format!("
-                    select TO_CHAR(s.time_start::timestamp, 'HH24:MI:SS') as time_start,
-                    (CASE WHEN EXISTS (select 1 from appointments x where x.doctor_id = {} and x.slot_id = s.id and TO_CHAR(x.appointment_date, 'YYYY-MM-DD') = '{}') THEN false
-                    ELSE true END) as available, s.id as slot_id
-                    from doctor_slots s
-                    where s.doctor_id = {}
-                            ", doctor_id, date, doctor_id);
-        self.get_query_result::<Timeslots, Postgres>(&query)
-            .await
+    //once the first factor checked out, 2FA still needs the code for the challenge
+    async fn finish_login(&self, result: &LoginTable, user_agent: &str) -> Result<LoginStep, LoginError> {
+        if result.totp_enabled {
+            return match self.new_auth_token(&format!("id = {}", result.login_id), "login_challenge").await {
+                Some(challenge) => Ok(LoginStep::Challenge(challenge)),
+                None => Err(LoginError::Invalid),
+            };
+        }
+        self.start_session(result, user_agent).await.map(LoginStep::Token)
     }
 
-    pub async fn view_prescriptions(&self, patient_id: i64) -> Vec<Prescriptions> {
+    //texted codes are hashed along with the phone, there are too few of them to tell apart otherwise
+    fn phone_otp_hash(&self, phone: &str, code: &str) -> Option<String> {
+        self.auth_token_hash("phone_otp", &format!("{}:{}", phone, code.trim()))
+    }
+
+    //any earlier code for the phone stops working, the code is made whether or not the phone has a login
+    //so it can be used for signing up too
+    pub async fn create_phone_otp(&self, phone: &str) -> Option<String> {
+        let code = format!("{:06}", OsRng.gen_range(0..1_000_000));
+        let hash = self.phone_otp_hash(phone, &code)?;
         let query = format!("
-                    select d.name as docname, TO_CHAR(p.appointment_date, 'YYYY-MM-DD') as date,
-                    p.prescription from Prescriptions p
-                    join Doctors d on d.id = p.patient_id
-                    where p.patient_id = {}
-                    order by p.appointment_date desc;
-                    ;", patient_id);
-        self.get_query_result::<Prescriptions, Postgres>(&query)
-            .await
+                    with expired as (update phone_otps set used_at = now() where phone = '{0}' and used_at is null)
+                    insert into phone_otps(phone, code_hash, expires_at) values ('{0}', '{1}', now() + interval '{2}');
+                            ", escape(phone), hash, PHONE_OTP_VALIDITY);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(_) => Some(code),
+            Err(e) => {
+                tracing::error!("Error while creating phone code: {}", e);
+                None
+            }
+        }
     }
 
-    pub async fn add_new_prescription(
-        &self,
-        doctor_id: i32,
-        patient_id: i32,
-        prescription: &String,
-        date: &String,
-    ) -> bool {
-        let Ok(naivedate) = NaiveDate::parse_from_str(date, "%Y-%m-%d") else {
-            tracing::error!("Couldn't parse date into NaiveDateTime");
+    //every try counts, after PHONE_OTP_ATTEMPTS wrong ones a new code has to be asked for
+    async fn use_phone_otp(&self, phone: &str, code: &str) -> bool {
+        let Some(hash) = self.phone_otp_hash(phone, code) else {
             return false;
         };
         let query = format!("
-                    insert into Prescriptions(patient_id, doctor_id, prescription, appointment_date) values ({}, {}, '{}', '{}');
-                            ", patient_id, doctor_id, prescription, naivedate);
-        match sqlx::query(&query).execute(&self.connection).await {
-            Ok(_) => return true,
-            Err(_) => return false,
+                    update phone_otps set attempts = attempts + 1,
+                    used_at = case when code_hash = '{1}' then now() else used_at end
+                    where used_at is null and attempts < {2} and expires_at > now() and id = (
+                        select id from phone_otps where phone = '{0}' order by id desc limit 1
+                    )
+                    returning used_at is not null as matched;
+                            ", escape(phone), hash, PHONE_OTP_ATTEMPTS);
+        match sqlx::query(&query).fetch_optional(&self.connection).await {
+            Ok(Some(row)) => row.try_get("matched").unwrap_or(false),
+            Ok(None) => {
+                tracing::error!("No usable code for {}, it expired, was used or had too many tries", phone);
+                false
+            }
+            Err(e) => {
+                tracing::error!("Error while checking phone code: {}", e);
+                false
+            }
         }
     }
 
-    pub async fn view_prev_appointments(&self, patient_id: i64) -> Vec<PrevAppointments> {
+    //gives the same JWT (or 2FA challenge) as a password login
+    pub async fn phone_login(&self, phone: &str, code: &str, user_agent: &str) -> Result<LoginStep, LoginError> {
+        let query = Self::login_sql(&format!("phone = '{}' and role = 'patient'", escape(phone)));
+        let result = match sqlx::query_as::<_, LoginTable>(&query).fetch_optional(&self.connection).await {
+            Ok(Some(result)) => result,
+            Ok(None) => {
+                tracing::error!("No patient login for {}", phone);
+                return Err(LoginError::Invalid);
+            }
+            Err(e) => {
+                tracing::error!("Error while looking up phone login: {}", e);
+                return Err(LoginError::Invalid);
+            }
+        };
+        if result.disabled {
+            tracing::error!("Login for {} is disabled", phone);
+            return Err(LoginError::Invalid);
+        }
+        if result.locked_for > 0 {
+            return Err(LoginError::Locked(result.locked_for));
+        }
+        if !self.use_phone_otp(phone, code).await {
+            self.failed_login(result.login_id).await;
+            return Err(LoginError::Invalid);
+        }
+        self.finish_login(&result, user_agent).await
+    }
+
+    //the patient and their login are created together like with an email signup, and they're logged in straight away
+    pub async fn phone_signup(&self, name: &str, phone: &str, code: &str, user_agent: &str) -> Result<String, SignupError> {
+        if !self.use_phone_otp(phone, code).await {
+            return Err(SignupError::Failed);
+        }
+        let Ok(mut tx) = self.connection.begin().await else {
+            tracing::error!("Couldn't start transaction for phone signup");
+            return Err(SignupError::Failed);
+        };
         let query = format!("
-                    select d.name as docname, TO_CHAR(a.appointment_date, 'YYYY-MM-DD') as date, a.type as phyorvirt, a.status as appstatus, a.prescription_id as prescription_id, p.name as appname
-                    from appointments a
-                    join doctors d on d.id = a.doctor_id
-                    join specialities p on p.id = a.appointment_type
-                    where a.patient_id = {}
-                    order by date desc
-                    ;", patient_id);
-        self.get_query_result::<PrevAppointments, Postgres>(&query)
-            .await
+                    with p as (insert into patients(name, phone) values ('{0}', '{1}') returning id)
+                    insert into login(phone, isdoctor, role, patient_id) select '{1}', false, 'patient', id from p;
+                            ", escape(name), escape(phone));
+        match sqlx::query(&query).execute(&mut tx).await {
+            Ok(_) => (),
+            Err(e) if unique_violation(&e) => {
+                tracing::error!("A login with phone {} already exists", phone);
+                return Err(SignupError::Duplicate);
+            }
+            Err(e) => {
+                tracing::error!("Error while inserting phone signup: {}", e);
+                return Err(SignupError::Failed);
+            }
+        }
+        if let Err(e) = tx.commit().await {
+            tracing::error!("Error while committing phone signup: {}", e);
+            return Err(SignupError::Failed);
+        }
+        let query = Self::login_sql(&format!("phone = '{}'", escape(phone)));
+        let Ok(result) = sqlx::query_as::<_, LoginTable>(&query).fetch_one(&self.connection).await else {
+            tracing::error!("Couldn't find the login that was just created for {}", phone);
+            return Err(SignupError::Failed);
+        };
+        self.start_session(&result, user_agent).await.map_err(|_| SignupError::Failed)
     }
 
-    pub async fn view_same_city_doctors(&self, city: String) -> Vec<DoctorInfo> {
+    async fn start_session(&self, result: &LoginTable, user_agent: &str) -> Result<String, LoginError> {
+        let user_agent: String = user_agent.chars().take(255).collect();
         let query = format!("
-                    select d.id as docid, d.name as docname, s.name as specname, d.address as address
-                    from doctors d
-                    join specialities s on s.id = d.speciality_id
-                    where d.city = '{}'
-                    ;", city);
-        self.get_query_result::<DoctorInfo, Postgres>(&query).await
+                        with reset as (update login set failed_logins = 0, locked_until = null where id = {0})
+                        insert into sessions(login_id, user_agent) values ({0}, '{1}') returning id;
+                    ", result.login_id, escape(&user_agent));
+        let sid: i64 = match sqlx::query(&query).fetch_one(&self.connection).await {
+            Ok(row) => row.try_get("id").map_err(|_| LoginError::Invalid)?,
+            Err(e) => {
+                tracing::error!("Error while starting session: {}", e);
+                return Err(LoginError::Invalid);
+            }
+        };
+        let jwt = InternalJWT {
+            isdoctor: result.isdoctor,
+            id: result.id.to_string(),
+            exp: 1000000,
+            role: Some(result.role.clone()),
+            sid: Some(sid),
+        };
+        let Ok(token) = encode(
+            &Header::default(),
+            &jwt,
+            &EncodingKey::from_secret(&self.jwt_secret),
+        ) else {
+            tracing::debug!("Error while trying to encode JWT");
+            return Err(LoginError::Invalid);
+        };
+        Ok(token)
     }
 
-    pub async fn view_patient_info(&self, patient_id: i64) -> Vec<PatientInfo> {
+    //the code is used up, a later step has to come before another code is accepted
+    async fn totp_matches(&self, login_id: i64, code: &str) -> bool {
+        let query = format!("select totp_secret, totp_last_step from login where id = {} and totp_secret is not null;", login_id);
+        let Ok(Some(row)) = sqlx::query(&query).fetch_optional(&self.connection).await else {
+            tracing::error!("Login {} has no TOTP secret", login_id);
+            return false;
+        };
+        let secret: String = row.try_get("totp_secret").unwrap_or_default();
+        let last_step: Option<i64> = row.try_get("totp_last_step").unwrap_or_default();
+        let Some(step) = totp::matching_step(&secret, code, last_step) else {
+            return false;
+        };
+        //only moves forward, so two requests with the same code can't both get through
+        let query = format!("
+                    update login set totp_last_step = {0}
+                    where id = {1} and (totp_last_step is null or totp_last_step < {0});
+                            ", step, login_id);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(e) => {
+                tracing::error!("Error while using TOTP code: {}", e);
+                false
+            }
+        }
+    }
+
+    //a TOTP code or one of the unused recovery codes, either is used up by this
+    async fn second_factor_matches(&self, login_id: i64, code: &str) -> bool {
+        if self.totp_matches(login_id, code).await {
+            return true;
+        }
+        let Some(hash) = self.auth_token_hash("recovery_code", &code.trim().to_lowercase()) else {
+            return false;
+        };
+        let query = format!("
+                    update recovery_codes set used_at = now() where login_id = {} and code_hash = '{}' and used_at is null;
+                            ", login_id, hash);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(e) => {
+                tracing::error!("Error while using recovery code: {}", e);
+                false
+            }
+        }
+    }
+
+    //counts a wrong password, locking the login once there are LOCKOUT_AFTER in a row
+    async fn failed_login(&self, login_id: i64) {
         let query = format!(
             "
-                    select name, email, phone, age, gender, blood_group, weight
-                    from patients
-                    where id = {}
-                    ;",
-            patient_id
+                update login set failed_logins = failed_logins + 1,
+                locked_until = case when failed_logins + 1 >= {LOCKOUT_AFTER}
+                then now() + least(interval '{LOCKOUT_MAX}', interval '{LOCKOUT_BASE}' * power(2, least(failed_logins + 1 - {LOCKOUT_AFTER}, 20)))
+                else locked_until end
+                where id = {login_id};
+            "
         );
-        self.get_query_result::<PatientInfo, Postgres>(&query).await
+        if let Err(e) = sqlx::query(&query).execute(&self.connection).await {
+            tracing::error!("Error while counting failed login: {}", e);
+        }
     }
 
-    pub async fn view_doctor_prices(&self, city: &String, apptype: &String) -> Vec<DoctorPrices> {
-        let iscityspecified = match city.is_empty() {
-            false => format!("and d.city = '{}'", city),
-            true => String::new(),
+    //the login behind a JWT still exists with the same role, hasn't been disabled and its session hasn't been revoked,
+    //logins an admin requires 2FA for only get past this without it when setting it up
+    pub async fn account_active(&self, role: &str, id: i64, sid: Option<i64>, setting_up_2fa: bool) -> bool {
+        let Some(column) = profile_column(role) else {
+            return false;
         };
-        let isapptypespecified = match apptype.is_empty() {
-            false => format!("and t.name = '{}'", apptype),
-            true => String::new(),
+        let Some(sid) = sid else {
+            tracing::error!("JWT was issued before sessions, it has to be renewed by logging in again");
+            return false;
         };
+        let two_factor = if setting_up_2fa { "" } else { "and (totp_enabled or not totp_required)" };
+        let query = format!("
+                    update sessions set last_seen_at = now()
+                    where id = {} and revoked_at is null
+                    and login_id = (select id from login where {} = {} and role = '{}' and not disabled {});
+                            ", sid, column, id, role, two_factor);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(e) => {
+                tracing::error!("Error while checking session: {}", e);
+                false
+            }
+        }
+    }
 
-        let query = format!(
-            "
-                    select d.id as docid, d.name as docname, d.city as city, d.address as address, t.name as apptype, t.id as appid, p.price, spec.name as specname
-                    from doctors d
-                    join appointment_types t on d.speciality_id = t.speciality_id
-                    join specialities spec on spec.id = t.speciality_id
-                    join appointment_prices p on d.id = p.doctor_id and t.id = p.appointment_type
-                    where 1=1 {} {};
-                    ",
-            isapptypespecified, iscityspecified
-        );
-        self.get_query_result::<DoctorPrices, Postgres>(&query)
-            .await
+    async fn password_matches(&self, sid: i64, password: &str) -> bool {
+        let query = format!("
+                    select l.salt, l.password from login l join sessions s on s.login_id = l.id where s.id = {};
+                            ", sid);
+        let Ok(row) = sqlx::query(&query).fetch_one(&self.connection).await else {
+            tracing::error!("No login found for session {}", sid);
+            return false;
+        };
+        let (Ok(salt), Ok(hash)): (Result<String, _>, Result<String, _>) = (row.try_get("salt"), row.try_get("password")) else {
+            tracing::error!("Error while retrieving password hash");
+            return false;
+        };
+        matches!(argon_hash_password::check_password_matches_hash(password, &hash, &salt), Ok(true))
     }
 
-    pub async fn view_doctor_prices_emergency(&self, city: &String, apptype: &String) -> Vec<DoctorPrices> {
-        let iscityspecified = match city.is_empty() {
-            false => format!("and d.city = '{}'", city),
-            true => String::new(),
+    //logs out every other device, the one the password was changed from stays logged in
+    pub async fn change_password(&self, sid: i64, old_password: &str, new_password: &str) -> bool {
+        if !self.password_matches(sid, old_password).await {
+            tracing::error!("Old password doesn't match");
+            return false;
+        }
+        let Ok((hash, salt)) = argon_hash_password::create_hash_and_salt(new_password) else {
+            tracing::error!("Hash and salt were not able to be created, password change error");
+            return false;
         };
-        let isapptypespecified = match apptype.is_empty() {
-            false => format!("and t.name = '{}'", apptype),
-            true => String::new(),
+        let Ok(mut tx) = self.connection.begin().await else {
+            tracing::error!("Couldn't start transaction for password change");
+            return false;
         };
+        let query = format!("
+                    update login set password = '{}', salt = '{}' where id = (select login_id from sessions where id = {});
+                            ", hash, salt, sid);
+        if let Err(e) = sqlx::query(&query).execute(&mut tx).await {
+            tracing::error!("Error while changing password: {}", e);
+            return false;
+        }
+        let query = format!("
+                    update sessions set revoked_at = now()
+                    where login_id = (select login_id from sessions where id = {0}) and id <> {0} and revoked_at is null;
+                            ", sid);
+        if let Err(e) = sqlx::query(&query).execute(&mut tx).await {
+            tracing::error!("Error while revoking sessions: {}", e);
+            return false;
+        }
+        tx.commit().await.is_ok()
+    }
 
-        let query = format!(
-            "
-                    select d.id as docid, d.name as docname, d.city as city, d.address as address, t.name as apptype, t.id as appid, 2*p.price as price, spec.name as specname
-                    from doctors d
-                    join appointment_types t on d.speciality_id = t.speciality_id
-                    join specialities spec on spec.id = t.speciality_id
-                    join doctors_emergency e on e.doctor_id = d.id
-                    join appointment_prices p on d.id = p.doctor_id and t.id = p.appointment_type
-                    where 1=1 {} {};
-                    ",
-            isapptypespecified, iscityspecified
-        );
-        self.get_query_result::<DoctorPrices, Postgres>(&query)
+    //the new email has to be verified again, it changes on the doctor/patient/receptionist record too
+    pub async fn change_email(&self, jwt: &JWT, password: &str, email: &str) -> Result<(), SignupError> {
+        let Some(sid) = jwt.sid else {
+            return Err(SignupError::Failed);
+        };
+        if !self.password_matches(sid, password).await {
+            tracing::error!("Password doesn't match, not changing email");
+            return Err(SignupError::Failed);
+        }
+        let mut queries = vec![
+            format!("
+                    update login set email = '{}', email_verified = false where id = (select login_id from sessions where id = {});
+                            ", escape(email), sid),
+            //tokens mailed to the old address mustn't verify the new one
+            format!("
+                    update auth_tokens set used_at = now() where login_id = (select login_id from sessions where id = {}) and used_at is null;
+                            ", sid),
+        ];
+        let table = match jwt.role.as_str() {
+            "patient" => Some("patients"),
+            "doctor" => Some("doctors"),
+            "receptionist" => Some("receptionists"),
+            _ => None,
+        };
+        if let Some(table) = table {
+            queries.push(format!("update {} set email = '{}' where id = {};", table, escape(email), jwt.id));
+        }
+        let Ok(mut tx) = self.connection.begin().await else {
+            tracing::error!("Couldn't start transaction for email change");
+            return Err(SignupError::Failed);
+        };
+        for query in queries {
+            match sqlx::query(&query).execute(&mut tx).await {
+                Ok(_) => (),
+                Err(e) if unique_violation(&e) => {
+                    tracing::error!("Email {} is already registered", email);
+                    return Err(SignupError::Duplicate);
+                }
+                Err(e) => {
+                    tracing::error!("Error while changing email: {}", e);
+                    return Err(SignupError::Failed);
+                }
+            }
+        }
+        tx.commit().await.map_err(|e| {
+            tracing::error!("Error while committing email change: {}", e);
+            SignupError::Failed
+        })
+    }
+
+    pub async fn view_sessions(&self, sid: i64) -> Vec<Sessions> {
+        let query = format!("
+                    select id, user_agent, TO_CHAR(created_at, 'YYYY-MM-DD HH24:MI:SS') as created_at,
+                    TO_CHAR(last_seen_at, 'YYYY-MM-DD HH24:MI:SS') as last_seen_at, id = {0} as current
+                    from sessions
+                    where login_id = (select login_id from sessions where id = {0}) and revoked_at is null
+                    order by last_seen_at desc;
+                            ", sid);
+        self.get_query_result::<Sessions, Postgres>(&query)
+            .await
+    }
+
+    pub async fn revoke_session(&self, sid: i64, session_id: i64) -> bool {
+        let query = format!("
+                    update sessions set revoked_at = now()
+                    where id = {} and login_id = (select login_id from sessions where id = {}) and revoked_at is null;
+                            ", session_id, sid);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(e) => {
+                tracing::error!("Error while revoking session: {}", e);
+                false
+            }
+        }
+    }
+
+    pub async fn revoke_other_sessions(&self, sid: i64) -> bool {
+        let query = format!("
+                    update sessions set revoked_at = now()
+                    where login_id = (select login_id from sessions where id = {0}) and id <> {0} and revoked_at is null;
+                            ", sid);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(_) => true,
+            Err(e) => {
+                tracing::error!("Error while revoking sessions: {}", e);
+                false
+            }
+        }
+    }
+
+    pub async fn view_two_factor(&self, sid: i64) -> Option<TwoFactorStatus> {
+        let query = format!("
+                    select l.totp_enabled as enabled, l.totp_required as required,
+                    (select count(*) from recovery_codes r where r.login_id = l.id and r.used_at is null) as recovery_codes_left
+                    from login l where l.id = (select login_id from sessions where id = {});
+                            ", sid);
+        match sqlx::query_as::<_, TwoFactorStatus>(&query).fetch_one(&self.connection).await {
+            Ok(status) => Some(status),
+            Err(e) => {
+                tracing::error!("Error while retrieving 2FA status: {}", e);
+                None
+            }
+        }
+    }
+
+    //a new secret replaces any earlier one that wasn't confirmed, 2FA that is on has to be turned off first
+    pub async fn setup_totp(&self, sid: i64) -> Option<TotpSetup> {
+        let secret = totp::new_secret();
+        let query = format!("
+                    update login set totp_secret = '{}', totp_last_step = null
+                    where id = (select login_id from sessions where id = {}) and not totp_enabled
+                    returning coalesce(email, phone) as email;
+                            ", secret, sid);
+        let email: String = match sqlx::query(&query).fetch_optional(&self.connection).await {
+            Ok(Some(row)) => row.try_get("email").ok()?,
+            Ok(None) => {
+                tracing::error!("2FA is already on for session {}", sid);
+                return None;
+            }
+            Err(e) => {
+                tracing::error!("Error while setting up 2FA: {}", e);
+                return None;
+            }
+        };
+        let uri = totp::provisioning_uri(&secret, &email);
+        let qr = totp::qr_svg(&uri)?;
+        Some(TotpSetup { secret, uri, qr })
+    }
+
+    async fn session_login(&self, sid: i64) -> Option<i64> {
+        let query = format!("select login_id::bigint as login_id from sessions where id = {};", sid);
+        match sqlx::query(&query).fetch_one(&self.connection).await {
+            Ok(row) => row.try_get("login_id").ok(),
+            Err(e) => {
+                tracing::error!("Error while retrieving login of session: {}", e);
+                None
+            }
+        }
+    }
+
+    //throws away the earlier recovery codes of the login
+    async fn new_recovery_codes(&self, tx: &mut sqlx::Transaction<'_, Postgres>, login_id: i64) -> Option<Vec<String>> {
+        let codes = totp::recovery_codes();
+        let mut hashes = Vec::new();
+        for code in &codes {
+            hashes.push(format!("({}, '{}')", login_id, self.auth_token_hash("recovery_code", code)?));
+        }
+        let query = format!("delete from recovery_codes where login_id = {};", login_id);
+        if let Err(e) = sqlx::query(&query).execute(&mut *tx).await {
+            tracing::error!("Error while removing recovery codes: {}", e);
+            return None;
+        }
+        let query = format!("insert into recovery_codes(login_id, code_hash) values {};", hashes.join(", "));
+        if let Err(e) = sqlx::query(&query).execute(&mut *tx).await {
+            tracing::error!("Error while adding recovery codes: {}", e);
+            return None;
+        }
+        Some(codes)
+    }
+
+    //turns 2FA on once a code from the authenticator shows it was set up right, gives the recovery codes
+    pub async fn enable_totp(&self, sid: i64, code: &str) -> Option<Vec<String>> {
+        let login_id = self.session_login(sid).await?;
+        if !self.totp_matches(login_id, code).await {
+            tracing::error!("TOTP code doesn't match, not turning on 2FA");
+            return None;
+        }
+        let Ok(mut tx) = self.connection.begin().await else {
+            tracing::error!("Couldn't start transaction for turning on 2FA");
+            return None;
+        };
+        let query = format!("update login set totp_enabled = true where id = {} and not totp_enabled;", login_id);
+        match sqlx::query(&query).execute(&mut tx).await {
+            Ok(res) if res.rows_affected() == 1 => (),
+            Ok(_) => {
+                tracing::error!("2FA is already on for login {}", login_id);
+                return None;
+            }
+            Err(e) => {
+                tracing::error!("Error while turning on 2FA: {}", e);
+                return None;
+            }
+        }
+        let codes = self.new_recovery_codes(&mut tx, login_id).await?;
+        tx.commit().await.ok()?;
+        Some(codes)
+    }
+
+    //needs a current TOTP code, not a recovery code, so a leaked recovery code can't be turned into new ones
+    pub async fn regenerate_recovery_codes(&self, sid: i64, code: &str) -> Option<Vec<String>> {
+        let login_id = self.session_login(sid).await?;
+        if !self.totp_matches(login_id, code).await {
+            tracing::error!("TOTP code doesn't match, not regenerating recovery codes");
+            return None;
+        }
+        let Ok(mut tx) = self.connection.begin().await else {
+            tracing::error!("Couldn't start transaction for recovery codes");
+            return None;
+        };
+        let codes = self.new_recovery_codes(&mut tx, login_id).await?;
+        tx.commit().await.ok()?;
+        Some(codes)
+    }
+
+    //not possible while an admin requires 2FA for the login
+    pub async fn disable_totp(&self, sid: i64, password: &str, code: &str) -> bool {
+        if !self.password_matches(sid, password).await {
+            tracing::error!("Password doesn't match, not turning off 2FA");
+            return false;
+        }
+        let Some(login_id) = self.session_login(sid).await else {
+            return false;
+        };
+        if !self.second_factor_matches(login_id, code).await {
+            tracing::error!("Second factor doesn't match, not turning off 2FA");
+            return false;
+        }
+        let query = format!("
+                    with r as (delete from recovery_codes where login_id = {0})
+                    update login set totp_enabled = false, totp_secret = null, totp_last_step = null
+                    where id = {0} and not totp_required;
+                            ", login_id);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(e) => {
+                tracing::error!("Error while turning off 2FA: {}", e);
+                false
+            }
+        }
+    }
+
+    pub fn verify_jwt(&self, jwt: &str) -> Option<JWT> {
+        let binding = match String::from(jwt)
+            .split("Bearer")
+            .collect::<Vec<&str>>()
+            .get(1)
+        {
+            Some(x) => x.to_string(),
+            None => jwt.to_string(),
+        };
+        let mut validation = Validation::default();
+        validation.validate_exp = false;
+        let token = binding.trim().to_string();
+        tracing::debug!("jwt : '{}'", token);
+        match decode::<InternalJWT>(
+            &token,
+            &DecodingKey::from_secret(&self.jwt_secret),
+            &validation,
+        ) {
+            Ok(token) => {
+                let Ok(id): Result<i64, _> = token.claims.id.parse() else {
+                    tracing::error!("Could not parse id while verifiying JWT");
+                    return None;
+                };
+                let role = match token.claims.role {
+                    Some(role) => role,
+                    None if token.claims.isdoctor => String::from("doctor"),
+                    None => String::from("patient"),
+                };
+                let res = JWT {
+                    isdoctor: token.claims.isdoctor,
+                    id,
+                    role,
+                    sid: token.claims.sid,
+                };
+                Some(res)
+            }
+            Err(x) => {
+                tracing::debug!("{}", x);
+                None
+            }
+        }
+    }
+
+    //get time slots for a doctor
+    pub async fn view_doctor_timeslots(&self, doctor_id: i64, date: &String) -> Vec<Timeslots> {
+        let query = format!("
+                    select TO_CHAR(s.time_start::timestamp, 'HH24:MI:SS') as time_start,
+                    (CASE WHEN EXISTS (select 1 from appointments x where x.doctor_id = {} and x.slot_id = s.id and TO_CHAR(x.appointment_date, 'YYYY-MM-DD') = '{}') THEN false
+                    ELSE true END) as available, s.id as slot_id, c.id as clinic_id, c.name as clinic
+                    from doctor_slots s
+                    join doctor_clinics dc on dc.doctor_id = s.doctor_id and dc.clinic_id = s.clinic_id
+                    join clinics c on c.id = s.clinic_id
+                    where s.doctor_id = {}
+                    order by c.id, s.time_start
+                            ", doctor_id, date, doctor_id);
+        self.get_query_result::<Timeslots, Postgres>(&query)
+            .await
+    }
+
+    //prescriptions issued to a patient, optionally only by one doctor and/or within a date range (YYYY-MM-DD, inclusive)
+    pub async fn view_prescriptions(
+        &self,
+        patient_id: i64,
+        by_doctor: Option<i64>,
+        from: Option<&str>,
+        to: Option<&str>,
+    ) -> Vec<Prescriptions> {
+        let mut filters = String::new();
+        if let Some(doctor_id) = by_doctor {
+            filters.push_str(&format!(" and p.doctor_id = {}", doctor_id));
+        }
+        for (date, op) in [(from, ">="), (to, "<=")] {
+            let Some(date) = date else {
+                continue;
+            };
+            let Ok(naivedate) = NaiveDate::parse_from_str(date, "%Y-%m-%d") else {
+                tracing::error!("Couldn't parse date into NaiveDateTime");
+                return Vec::new();
+            };
+            filters.push_str(&format!(" and p.appointment_date::date {} '{}'", op, naivedate));
+        }
+        let query = format!("
+                    select p.id, p.doctor_id, d.name as docname, TO_CHAR(p.appointment_date, 'YYYY-MM-DD') as date,
+                    {} from Prescriptions p
+                    join Doctors d on d.id = p.doctor_id
+                    where p.patient_id = {}{}
+                    order by p.appointment_date desc;
+                    ", sealed_column("prescriptions", "p", "prescription"), patient_id, filters);
+        self.get_query_result::<Prescriptions, Postgres>(&query)
+            .await
+    }
+
+    //a doctor may look at a patient's records while they have an upcoming or ongoing visit with them,
+    //or had one in the last RECENT_VISIT_DAYS days
+    pub async fn doctor_treats_patient(&self, doctor_id: i64, patient_id: i64) -> bool {
+        let query = format!("
+                    select exists (
+                        select 1 from appointments where doctor_id = {doctor_id} and patient_id = {patient_id}
+                        and (status in ('scheduled', 'ongoing') or (status <> 'cancelled' and appointment_date > now() - interval '{RECENT_VISIT_DAYS} days'))
+                        union all
+                        select 1 from tokens where doctor_id = {doctor_id} and patient_id = {patient_id}
+                        and (status in ('scheduled', 'ongoing') or (status <> 'cancelled' and appointment_date > now() - interval '{RECENT_VISIT_DAYS} days'))
+                        union all
+                        select 1 from emergency_appointments where doctor_id = {doctor_id} and patient_id = {patient_id}
+                        and appointment_date > now() - interval '{RECENT_VISIT_DAYS} days'
+                    ) as allowed;
+                    ");
+        match sqlx::query(&query).fetch_one(&self.connection).await {
+            Ok(row) => row.try_get("allowed").unwrap_or(false),
+            Err(e) => {
+                tracing::error!("Error while checking doctor's access to patient: {}", e);
+                false
+            }
+        }
+    }
+
+    //everything needed to print a prescription, only if it was issued to this patient
+    pub async fn view_prescription_document(&self, prescription_id: i64, patient_id: i64) -> Option<PrescriptionDocument> {
+        let query = format!("
+                    select p.id, p.patient_id, p.doctor_id, d.name as docname, d.registration_number, d.registration_council,
+                    d.address, d.city, d.phone as docphone, pt.name as patname, {}, {}, {},
+                    TO_CHAR(p.appointment_date, 'YYYY-MM-DD') as date, {}
+                    from prescriptions p
+                    join doctors d on d.id = p.doctor_id
+                    join patients pt on pt.id = p.patient_id
+                    where p.id = {} and p.patient_id = {};
+                            ", sealed_column("patients", "pt", "age"),
+                    sealed_column("patients", "pt", "gender"),
+                    sealed_column("patients", "pt", "weight"),
+                    sealed_column("prescriptions", "p", "prescription"),
+                    prescription_id, patient_id);
+        match sqlx::query_as::<_, PrescriptionDocument>(&query)
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(doc) => Some(doc),
+            Err(e) => {
+                tracing::error!("Error while fetching prescription {}: {}", prescription_id, e);
+                None
+            }
+        }
+    }
+
+    //HMAC over the parts of a prescription that a pharmacy would care about being tampered with
+    fn prescription_mac(&self, id: i64, patient_id: i32, doctor_id: i32, date: &str, prescription: &str) -> Option<Hmac<Sha256>> {
+        let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(&self.jwt_secret) else {
+            tracing::error!("Couldn't create HMAC from secret");
+            return None;
+        };
+        mac.update(format!("{}:{}:{}:{}:{}", id, patient_id, doctor_id, date, prescription).as_bytes());
+        Some(mac)
+    }
+
+    //what is stored for a mailed token, so the tokens themselves can't be read out of the database
+    fn auth_token_hash(&self, purpose: &str, token: &str) -> Option<String> {
+        let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(&self.jwt_secret) else {
+            tracing::error!("Couldn't create HMAC from secret");
+            return None;
+        };
+        mac.update(format!("{}:{}", purpose, token).as_bytes());
+        Some(hex::encode(mac.finalize().into_bytes()))
+    }
+
+    //makes a token for the login with this email, None when there's no such login or it has nothing to verify
+    pub async fn create_auth_token(&self, email: &str, purpose: &str) -> Option<String> {
+        self.new_auth_token(&format!("email = '{}'", escape(email)), purpose)
+            .await
+    }
+
+    //login is the condition picking the login out, phone logins have no email to go by
+    async fn new_auth_token(&self, login: &str, purpose: &str) -> Option<String> {
+        let (condition, validity) = match purpose {
+            "verify_email" => ("not email_verified", VERIFY_EMAIL_VALIDITY),
+            "reset_password" => ("not disabled", RESET_PASSWORD_VALIDITY),
+            "login_challenge" => ("totp_enabled and not disabled", LOGIN_CHALLENGE_VALIDITY),
+            other => {
+                tracing::error!("No such token purpose {}", other);
+                return None;
+            }
+        };
+        let mut bytes = [0u8; AUTH_TOKEN_BYTES];
+        OsRng.fill_bytes(&mut bytes);
+        let token = hex::encode(bytes);
+        let hash = self.auth_token_hash(purpose, &token)?;
+        let query = format!("
+                    insert into auth_tokens(login_id, purpose, token_hash, expires_at)
+                    select id, '{}', '{}', now() + interval '{}' from login where {} and {};
+                            ", purpose, hash, validity, login, condition);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) if res.rows_affected() == 1 => Some(token),
+            Ok(_) => {
+                tracing::debug!("No login with {} needing a {} token", login, purpose);
+                None
+            }
+            Err(e) => {
+                tracing::error!("Error while creating token: {}", e);
+                None
+            }
+        }
+    }
+
+    //marks an unused, unexpired token as used and gives back the login it was for
+    fn use_auth_token_sql(&self, purpose: &str, token: &str) -> Option<String> {
+        let hash = self.auth_token_hash(purpose, token.trim())?;
+        Some(format!("
+                    update auth_tokens set used_at = now()
+                    where token_hash = '{}' and purpose = '{}' and used_at is null and expires_at > now()
+                    returning login_id::bigint as login_id;
+                            ", hash, purpose))
+    }
+
+    pub async fn verify_email(&self, token: &str) -> bool {
+        let Some(query) = self.use_auth_token_sql("verify_email", token) else {
+            return false;
+        };
+        let Ok(mut tx) = self.connection.begin().await else {
+            tracing::error!("Couldn't start transaction for email verification");
+            return false;
+        };
+        let login_id: i64 = match sqlx::query(&query).fetch_optional(&mut tx).await {
+            Ok(Some(row)) => row.try_get("login_id").unwrap_or_default(),
+            Ok(None) => {
+                tracing::error!("Verification token is invalid, used or expired");
+                return false;
+            }
+            Err(e) => {
+                tracing::error!("Error while using verification token: {}", e);
+                return false;
+            }
+        };
+        let query = format!("update login set email_verified = true where id = {};", login_id);
+        if let Err(e) = sqlx::query(&query).execute(&mut tx).await {
+            tracing::error!("Error while verifying email: {}", e);
+            return false;
+        }
+        tx.commit().await.is_ok()
+    }
+
+    //any other reset tokens still out for the login stop working too, and every device is logged out
+    pub async fn reset_password_with_token(&self, token: &str, password: &str) -> bool {
+        let Some(query) = self.use_auth_token_sql("reset_password", token) else {
+            return false;
+        };
+        let Ok((hash, salt)) = argon_hash_password::create_hash_and_salt(password) else {
+            tracing::error!("Hash and salt were not able to be created, password reset error");
+            return false;
+        };
+        let Ok(mut tx) = self.connection.begin().await else {
+            tracing::error!("Couldn't start transaction for password reset");
+            return false;
+        };
+        let login_id: i64 = match sqlx::query(&query).fetch_optional(&mut tx).await {
+            Ok(Some(row)) => row.try_get("login_id").unwrap_or_default(),
+            Ok(None) => {
+                tracing::error!("Reset token is invalid, used or expired");
+                return false;
+            }
+            Err(e) => {
+                tracing::error!("Error while using reset token: {}", e);
+                return false;
+            }
+        };
+        let query = format!("
+                    update login set password = '{}', salt = '{}' where id = {} and not disabled;
+                            ", hash, salt, login_id);
+        match sqlx::query(&query).execute(&mut tx).await {
+            Ok(res) if res.rows_affected() == 1 => (),
+            Ok(_) => {
+                tracing::error!("Login {} is disabled, not resetting password", login_id);
+                return false;
+            }
+            Err(e) => {
+                tracing::error!("Error while resetting password: {}", e);
+                return false;
+            }
+        }
+        let query = format!("
+                    update auth_tokens set used_at = now() where login_id = {} and purpose = 'reset_password' and used_at is null;
+                            ", login_id);
+        if let Err(e) = sqlx::query(&query).execute(&mut tx).await {
+            tracing::error!("Error while expiring reset tokens: {}", e);
+            return false;
+        }
+        let query = format!("
+                    update sessions set revoked_at = now() where login_id = {} and revoked_at is null;
+                            ", login_id);
+        if let Err(e) = sqlx::query(&query).execute(&mut tx).await {
+            tracing::error!("Error while revoking sessions: {}", e);
+            return false;
+        }
+        tx.commit().await.is_ok()
+    }
+
+    pub fn prescription_code(&self, doc: &PrescriptionDocument) -> Option<String> {
+        let mac = self.prescription_mac(
+            doc.id,
+            doc.patient_id,
+            doc.doctor_id,
+            &doc.date,
+            doc.prescription.as_deref().map_or("", String::as_str),
+        )?;
+        let bytes = mac.finalize().into_bytes();
+        Some(hex::encode_upper(&bytes[..PRESCRIPTION_CODE_BYTES]))
+    }
+
+    //checks a printed verification code, only giving out enough to match it against the paper copy
+    pub async fn verify_prescription(&self, prescription_id: i64, code: &str) -> Option<PrescriptionVerification> {
+        let Ok(given) = hex::decode(code.trim()) else {
+            tracing::debug!("Verification code is not valid hex");
+            return None;
+        };
+        if given.len() != PRESCRIPTION_CODE_BYTES {
+            tracing::debug!("Verification code has wrong length");
+            return None;
+        }
+        let query = format!("
+                    select p.id, p.patient_id, p.doctor_id, d.name as docname, d.registration_number, d.registration_council,
+                    d.address, d.city, d.phone as docphone, pt.name as patname, {}, {}, {},
+                    TO_CHAR(p.appointment_date, 'YYYY-MM-DD') as date, {}
+                    from prescriptions p
+                    join doctors d on d.id = p.doctor_id
+                    join patients pt on pt.id = p.patient_id
+                    where p.id = {};
+                            ", sealed_column("patients", "pt", "age"),
+                    sealed_column("patients", "pt", "gender"),
+                    sealed_column("patients", "pt", "weight"),
+                    sealed_column("prescriptions", "p", "prescription"),
+                    prescription_id);
+        let Ok(doc) = sqlx::query_as::<_, PrescriptionDocument>(&query)
+            .fetch_one(&self.connection)
+            .await else {
+                tracing::debug!("No prescription {} to verify", prescription_id);
+                return None;
+            };
+        let mac = self.prescription_mac(
+            doc.id,
+            doc.patient_id,
+            doc.doctor_id,
+            &doc.date,
+            doc.prescription.as_deref().map_or("", String::as_str),
+        )?;
+        if mac.verify_truncated_left(&given).is_err() {
+            tracing::debug!("Verification code doesn't match prescription {}", prescription_id);
+            return None;
+        }
+        let initials = doc
+            .patname
+            .split_whitespace()
+            .filter_map(|part| part.chars().next())
+            .map(|c| format!("{}.", c))
+            .collect::<String>();
+        Some(PrescriptionVerification {
+            valid: true,
+            docname: doc.docname,
+            registration_number: doc.registration_number,
+            date: doc.date,
+            patient_initials: initials,
+        })
+    }
+
+    //creates a prescription for a specific appointment/token/emergency and links it back in one transaction
+    pub async fn add_new_prescription(&self, input: &PrescriptionInfoInput) -> bool {
+        let source = input.source.as_str();
+        let source_id = input.source_id;
+        let tablename = match source {
+            "appointment" => "appointments",
+            "token" => "tokens",
+            "emergency" => "emergency_appointments",
+            _ => {
+                tracing::error!("Unknown prescription source {}", source);
+                return false;
+            }
+        };
+        if !self.doctor_verified(input.doctor_id).await {
+            tracing::error!("Doctor {} isn't verified, can't prescribe", input.doctor_id);
+            return false;
+        }
+        let Some(id) = self.next_id("prescriptions").await else {
+            return false;
+        };
+        let Some(prescription) = sealed(&input.prescription, "prescriptions", "prescription", id) else {
+            return false;
+        };
+        let Ok(mut tx) = self.connection.begin().await else {
+            tracing::error!("Couldn't start transaction for new prescription");
+            return false;
+        };
+        let query = format!("
+                    insert into Prescriptions(id, patient_id, doctor_id, prescription, appointment_date, refills_allowed)
+                    select {}, patient_id, doctor_id, {}, appointment_date, {} from {}
+                    where id = {} and doctor_id = {} and patient_id = {} and prescription_id is null
+                    returning id;
+                            ", id, prescription, input.refills.max(0), tablename, source_id, input.doctor_id, input.patient_id);
+        match sqlx::query(&query).fetch_optional(&mut tx).await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                tracing::error!("No {} {} without a prescription found for this doctor and patient", source, source_id);
+                return false;
+            }
+            Err(e) => {
+                tracing::error!("Error while inserting prescription: {}", e);
+                return false;
+            }
+        }
+        //prescribing is how a doctor closes an appointment, so the patient can review it afterwards;
+        //tokens are closed by the queue
+        let fulfilled = if tablename == "appointments" { ", status = 'fulfilled'" } else { "" };
+        let query = format!("
+                    update {} set prescription_id = {}{} where id = {};
+                            ", tablename, id, fulfilled, source_id);
+        if let Err(e) = sqlx::query(&query).execute(&mut tx).await {
+            tracing::error!("Error while linking prescription: {}", e);
+            return false;
+        }
+        if !input.drugs.is_empty() {
+            let query = format!("
+                    insert into Prescription_Drugs(prescription_id, drug_id) select {}, id from drugs where id in ({});
+                            ", id, id_list(&input.drugs));
+            if let Err(e) = sqlx::query(&query).execute(&mut tx).await {
+                tracing::error!("Error while adding drugs to prescription: {}", e);
+                return false;
+            }
+        }
+        tx.commit().await.is_ok()
+    }
+
+    //refills always count against the original prescription, even when asked for from an earlier refill
+    fn request_refill_sql(patient_id: i64, prescription_id: i64, note: &str) -> String {
+        format!("
+                    insert into Refill_Requests(prescription_id, patient_id, doctor_id, status, note)
+                    select o.id, o.patient_id, o.doctor_id, 'pending', '{}' from prescriptions p
+                    join prescriptions o on o.id = coalesce(p.parent_id, p.id)
+                    where p.id = {} and p.patient_id = {}
+                    and o.refills_allowed > (select count(*) from refill_requests r where r.prescription_id = o.id and r.status = 'approved')
+                    and not exists (select 1 from refill_requests r where r.prescription_id = o.id and r.status = 'pending')
+                    returning id;
+                            ", escape(note), prescription_id, patient_id)
+    }
+
+    pub async fn request_refill(&self, patient_id: i64, prescription_id: i64, note: &str) -> bool {
+        let query = Self::request_refill_sql(patient_id, prescription_id, note);
+        match sqlx::query(&query).fetch_optional(&self.connection).await {
+            Ok(Some(_)) => true,
+            Ok(None) => {
+                tracing::error!("Prescription {} can't be refilled for patient {}", prescription_id, patient_id);
+                false
+            }
+            Err(e) => {
+                tracing::error!("Error while requesting refill: {}", e);
+                false
+            }
+        }
+    }
+
+    pub async fn view_refill_queue(&self, doctor_id: i64) -> Vec<RefillQueue> {
+        let query = format!("
+                    select r.id, r.patient_id, pt.name as patname, r.prescription_id, {}, r.note,
+                    TO_CHAR(r.requested_at, 'YYYY-MM-DD HH24:MI:SS') as requested_at,
+                    (select count(*) from refill_requests x where x.prescription_id = p.id and x.status = 'approved') as refills_used,
+                    p.refills_allowed
+                    from refill_requests r
+                    join prescriptions p on p.id = r.prescription_id
+                    join patients pt on pt.id = r.patient_id
+                    where r.doctor_id = {} and r.status = 'pending'
+                    order by r.requested_at;
+                            ", sealed_column("prescriptions", "p", "prescription"), doctor_id);
+        self.get_query_result::<RefillQueue, Postgres>(&query)
+            .await
+    }
+
+    pub async fn view_patient_refills(&self, patient_id: i64) -> Vec<PatientRefills> {
+        let query = format!("
+                    select r.id, r.prescription_id, d.name as docname, r.status, r.reason, r.new_prescription_id,
+                    TO_CHAR(r.requested_at, 'YYYY-MM-DD HH24:MI:SS') as requested_at
+                    from refill_requests r
+                    join doctors d on d.id = r.doctor_id
+                    where r.patient_id = {}
+                    order by r.requested_at desc;
+                            ", patient_id);
+        self.get_query_result::<PatientRefills, Postgres>(&query)
+            .await
+    }
+
+    //issues the refill as a new prescription linked to the original, copying its drugs
+    pub async fn approve_refill(&self, doctor_id: i64, refill_id: i64) -> bool {
+        if !self.doctor_verified(doctor_id).await {
+            tracing::error!("Doctor {} isn't verified, can't prescribe", doctor_id);
+            return false;
+        }
+        let Ok(mut tx) = self.connection.begin().await else {
+            tracing::error!("Couldn't start transaction for refill approval");
+            return false;
+        };
+        //lock the original so two approvals can't both squeeze under the limit
+        let query = format!("
+                    select o.id, {} from refill_requests r
+                    join prescriptions o on o.id = r.prescription_id
+                    where r.id = {} and r.doctor_id = {} and r.status = 'pending'
+                    and o.refills_allowed > (select count(*) from refill_requests x where x.prescription_id = o.id and x.status = 'approved')
+                    for update of r, o;
+                            ", sealed_column("prescriptions", "o", "prescription"), refill_id, doctor_id);
+        let (original, text): (i64, Option<Sealed<String>>) = match sqlx::query(&query).fetch_optional(&mut tx).await {
+            Ok(Some(row)) => match (row.try_get("id"), row.try_get("prescription")) {
+                (Ok(id), Ok(text)) => (id, text),
+                (Err(e), _) | (_, Err(e)) => {
+                    tracing::error!("Error while retrieving prescription: {}", e);
+                    return false;
+                }
+            },
+            Ok(None) => {
+                tracing::error!("No pending refill {} within its limit for doctor {}", refill_id, doctor_id);
+                return false;
+            }
+            Err(e) => {
+                tracing::error!("Error while checking refill: {}", e);
+                return false;
+            }
+        };
+        //the text is sealed again for the new row rather than copied
+        let Some(id) = self.next_id("prescriptions").await else {
+            return false;
+        };
+        let prescription = match text {
+            Some(text) => match sealed(&text, "prescriptions", "prescription", id) {
+                Some(prescription) => prescription,
+                None => return false,
+            },
+            None => String::from("null"),
+        };
+        let query = format!("
+                    insert into Prescriptions(id, patient_id, doctor_id, prescription, appointment_date, parent_id)
+                    select {}, patient_id, doctor_id, {}, now(), id from prescriptions where id = {};
+                            ", id, prescription, original);
+        if let Err(e) = sqlx::query(&query).execute(&mut tx).await {
+            tracing::error!("Error while inserting refill prescription: {}", e);
+            return false;
+        }
+        let query = format!("
+                    insert into Prescription_Drugs(prescription_id, drug_id) select {}, drug_id from prescription_drugs where prescription_id = {};
+                            ", id, original);
+        if let Err(e) = sqlx::query(&query).execute(&mut tx).await {
+            tracing::error!("Error while copying drugs to refill: {}", e);
+            return false;
+        }
+        let query = format!("
+                    update refill_requests set status = 'approved', new_prescription_id = {}, decided_at = now() where id = {};
+                            ", id, refill_id);
+        if let Err(e) = sqlx::query(&query).execute(&mut tx).await {
+            tracing::error!("Error while approving refill: {}", e);
+            return false;
+        }
+        tx.commit().await.is_ok()
+    }
+
+    pub async fn decline_refill(&self, doctor_id: i64, refill_id: i64, reason: &str) -> bool {
+        let query = format!("
+                    update refill_requests set status = 'declined', reason = '{}', decided_at = now()
+                    where id = {} and doctor_id = {} and status = 'pending';
+                            ", escape(reason), refill_id, doctor_id);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(_) => false,
+        }
+    }
+
+    //autocomplete over the drug catalog by brand or generic name
+    fn search_drugs_sql(prefix: &str) -> String {
+        let prefix = escape(prefix);
+        format!("
+                    select id, name, generic_name, drug_class, form, strength from drugs
+                    where name ilike '{}%' or generic_name ilike '{}%'
+                    order by name
+                    limit 10;
+                            ", prefix, prefix)
+    }
+
+    pub async fn search_drugs(&self, prefix: &str) -> Vec<Drugs> {
+        let query = Self::search_drugs_sql(prefix);
+        self.get_query_result::<Drugs, Postgres>(&query)
+            .await
+    }
+
+    //interactions between the given drugs themselves and with anything prescribed to the patient in the
+    //last 30 days, plus matches against the patient's recorded allergies; Err when the check couldn't run,
+    //which must not be taken as the drugs being safe
+    pub async fn check_drugs(&self, patient_id: i64, drugs: &[i64]) -> Result<Vec<DrugWarning>, sqlx::Error> {
+        if drugs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let ids = id_list(drugs);
+        let query = format!("
+                    with given as (select id, name, generic_name, drug_class from drugs where id in ({ids})),
+                    current as (
+                        select d.id, d.name, d.generic_name, d.drug_class from prescription_drugs pd
+                        join prescriptions p on p.id = pd.prescription_id
+                        join drugs d on d.id = pd.drug_id
+                        where p.patient_id = {patient_id} and p.appointment_date > now() - interval '30 days'
+                    ),
+                    others as (select * from given union select * from current)
+                    select distinct 'interaction' as kind, g.name as drug, o.name as conflict, i.severity, i.description
+                    from given g
+                    join others o on o.id <> g.id and (o.id not in ({ids}) or g.id < o.id)
+                    join drug_interactions i on
+                        (lower(i.generic_a) = lower(g.generic_name) and lower(i.generic_b) = lower(o.generic_name))
+                        or (lower(i.generic_b) = lower(g.generic_name) and lower(i.generic_a) = lower(o.generic_name))
+                    union all
+                    select 'allergy' as kind, g.name as drug, a.allergen as conflict, 'major' as severity,
+                    'Patient has a recorded allergy to ' || a.allergen as description
+                    from given g
+                    join patient_allergies a on a.patient_id = {patient_id}
+                    and lower(a.allergen) in (lower(g.name), lower(g.generic_name), lower(coalesce(g.drug_class, '')))
+                    ;");
+        sqlx::query_as::<_, DrugWarning>(&query)
+            .fetch_all(&self.connection)
+            .await
+            .map_err(|e| {
+                tracing::error!("Error while checking drugs: {}", e);
+                e
+            })
+    }
+
+    pub async fn view_allergies(&self, patient_id: i64) -> Vec<Allergies> {
+        let query = format!("
+                    select id, allergen from patient_allergies where patient_id = {} order by allergen;
+                            ", patient_id);
+        self.get_query_result::<Allergies, Postgres>(&query)
+            .await
+    }
+
+    pub async fn add_allergy(&self, patient_id: i64, allergen: &str) -> bool {
+        let query = format!("
+                    insert into patient_allergies(patient_id, allergen) values ({}, '{}');
+                            ", patient_id, escape(allergen.trim()));
+        sqlx::query(&query).execute(&self.connection).await.is_ok()
+    }
+
+    pub async fn remove_allergy(&self, patient_id: i64, allergy_id: i64) -> bool {
+        let query = format!("
+                    delete from patient_allergies where id = {} and patient_id = {};
+                            ", allergy_id, patient_id);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(_) => false,
+        }
+    }
+
+    pub async fn view_prev_appointments(&self, patient_id: i64) -> Vec<PrevAppointments> {
+        let query = format!("
+                    select d.name as docname, TO_CHAR(a.appointment_date, 'YYYY-MM-DD') as date, a.type as phyorvirt, a.status as appstatus, a.prescription_id as prescription_id, {}, t.name as appname
+                    from appointments a
+                    join doctors d on d.id = a.doctor_id
+                    join appointment_types t on t.id = a.appointment_type
+                    left join prescriptions pr on pr.id = a.prescription_id
+                    where a.patient_id = {}
+                    order by date desc
+                    ;", sealed_column("prescriptions", "pr", "prescription"), patient_id);
+        self.get_query_result::<PrevAppointments, Postgres>(&query)
+            .await
+    }
+
+    pub async fn view_same_city_doctors(&self, city: String) -> Vec<DoctorInfo> {
+        let query = format!("
+                    select d.id as docid, d.name as docname, {} as specname, c.address as address, c.id as clinic_id, c.name as clinic, {}
+                    from doctors d
+                    join doctor_clinics dc on dc.doctor_id = d.id
+                    join clinics c on c.id = dc.clinic_id
+                    where c.city = '{}' and {}
+                    ;", specialities_sql(), rating_sql(), escape(&city), verified_sql());
+        self.get_query_result::<DoctorInfo, Postgres>(&query).await
+    }
+
+    pub async fn view_doctor_profile(&self, doctor_id: i64) -> Option<DoctorProfile> {
+        let query = format!("
+                    select d.id as docid, d.name as docname, {} as specname, d.city, d.address, d.phone, d.gender,
+                    d.qualifications, d.registration_number, d.registration_council, d.years_of_experience,
+                    d.languages, d.bio, d.consultation_modes, d.photo_url, {}
+                    from doctors d
+                    where d.id = {} and {};
+                            ", specialities_sql(), rating_sql(), doctor_id, verified_sql());
+        match sqlx::query_as::<_, DoctorProfile>(&query)
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(profile) => Some(profile),
+            Err(e) => {
+                tracing::error!("Error while fetching profile of doctor {}: {}", doctor_id, e);
+                None
+            }
+        }
+    }
+
+    //only the fields that were sent get changed
+    fn doctor_profile_sql(input: &DoctorProfileInput) -> Option<String> {
+        let mut updates: Vec<String> = Vec::new();
+        let text_fields = [
+            ("name", &input.name),
+            ("city", &input.city),
+            ("address", &input.address),
+            ("phone", &input.phone),
+            ("gender", &input.gender),
+            ("qualifications", &input.qualifications),
+            ("registration_number", &input.registration_number),
+            ("registration_council", &input.registration_council),
+            ("bio", &input.bio),
+            ("photo_url", &input.photo_url),
+        ];
+        for (column, value) in text_fields {
+            if let Some(value) = value {
+                updates.push(format!("{} = '{}'", column, escape(value)));
+            }
+        }
+        let array_fields = [
+            ("languages", &input.languages),
+            ("consultation_modes", &input.consultation_modes),
+        ];
+        for (column, values) in array_fields {
+            if let Some(values) = values {
+                let values = values
+                    .iter()
+                    .map(|v| format!("'{}'", escape(v)))
+                    .collect::<Vec<String>>()
+                    .join(",");
+                updates.push(format!("{} = ARRAY[{}]::VARCHAR(255)[]", column, values));
+            }
+        }
+        if let Some(years) = input.years_of_experience {
+            updates.push(format!("years_of_experience = {}", years));
+        }
+        if updates.is_empty() {
+            tracing::error!("Nothing to update for doctor {}", input.doctor_id);
+            return None;
+        }
+        //the verification was of the old registration, a changed one has to be verified again
+        if input.registration_number.is_some() || input.registration_council.is_some() {
+            let new_value = |column: &str, value: &Option<String>| match value {
+                Some(value) => format!("'{}'", escape(value)),
+                None => column.to_string(),
+            };
+            let changed = format!(
+                "(registration_number, registration_council) is distinct from ({}, {})",
+                new_value("registration_number", &input.registration_number),
+                new_value("registration_council", &input.registration_council)
+            );
+            updates.push(format!(
+                "verification_status = case when {} then 'pending_verification' else verification_status end",
+                changed
+            ));
+            updates.push(format!("verified_at = case when {} then null else verified_at end", changed));
+        }
+        Some(format!("
+                    update doctors set {} where id = {};
+                            ", updates.join(", "), input.doctor_id))
+    }
+
+    pub async fn update_doctor_profile(&self, input: &DoctorProfileInput) -> bool {
+        let Some(query) = Self::doctor_profile_sql(input) else {
+            return false;
+        };
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(e) => {
+                tracing::error!("Error while updating doctor profile: {}", e);
+                false
+            }
+        }
+    }
+
+    pub async fn view_doctor_specialities(&self, doctor_id: i64) -> Vec<Specialities> {
+        let query = format!("
+                    select s.id, s.name, s.description as desc
+                    from doctor_specialities ds
+                    join specialities s on s.id = ds.speciality_id
+                    where ds.doctor_id = {}
+                    order by s.name;
+                            ", doctor_id);
+        self.get_query_result::<Specialities, Postgres>(&query)
+            .await
+    }
+
+    pub async fn add_doctor_speciality(&self, doctor_id: i64, speciality_id: i64) -> bool {
+        let query = format!("
+                    insert into doctor_specialities(doctor_id, speciality_id) values ({}, {});
+                            ", doctor_id, speciality_id);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(e) => {
+                tracing::error!("Error while adding speciality: {}", e);
+                false
+            }
+        }
+    }
+
+    //prices for appointment types of the speciality go with it, a doctor has to keep at least one speciality
+    pub async fn remove_doctor_speciality(&self, doctor_id: i64, speciality_id: i64) -> bool {
+        let Ok(mut tx) = self.connection.begin().await else {
+            tracing::error!("Couldn't start transaction");
+            return false;
+        };
+        let query = format!("
+                    delete from doctor_specialities
+                    where doctor_id = {0} and speciality_id = {1}
+                    and (select count(*) from doctor_specialities where doctor_id = {0}) > 1;
+                            ", doctor_id, speciality_id);
+        match sqlx::query(&query).execute(&mut tx).await {
+            Ok(res) if res.rows_affected() == 1 => (),
+            Ok(_) => return false,
+            Err(e) => {
+                tracing::error!("Error while removing speciality: {}", e);
+                return false;
+            }
+        }
+        let query = format!("
+                    delete from appointment_prices p
+                    using appointment_types t
+                    where t.id = p.appointment_type and p.doctor_id = {} and t.speciality_id = {};
+                            ", doctor_id, speciality_id);
+        if let Err(e) = sqlx::query(&query).execute(&mut tx).await {
+            tracing::error!("Error while removing prices: {}", e);
+            return false;
+        }
+        tx.commit().await.is_ok()
+    }
+
+    //only appointment types belonging to one of the doctor's specialities can be priced
+    pub async fn set_appointment_price(&self, doctor_id: i64, apptype: i64, price: i32) -> bool {
+        let query = format!("
+                    insert into appointment_prices(doctor_id, appointment_type, price)
+                    select {0}, t.id, {2}
+                    from appointment_types t
+                    join doctor_specialities ds on ds.speciality_id = t.speciality_id and ds.doctor_id = {0}
+                    where t.id = {1}
+                    on conflict (doctor_id, appointment_type) do update set price = excluded.price;
+                            ", doctor_id, apptype, price);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(e) => {
+                tracing::error!("Error while setting price: {}", e);
+                false
+            }
+        }
+    }
+
+    pub async fn remove_appointment_price(&self, doctor_id: i64, apptype: i64) -> bool {
+        let query = format!("
+                    delete from appointment_prices where doctor_id = {} and appointment_type = {};
+                            ", doctor_id, apptype);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(e) => {
+                tracing::error!("Error while removing price: {}", e);
+                false
+            }
+        }
+    }
+
+    pub async fn view_patient_info(&self, patient_id: i64) -> Vec<PatientInfo> {
+        let query = format!(
+            "
+                    select name, email, phone, {}, {}, {}, {}
+                    from patients
+                    where id = {}
+                    ;",
+            sealed_column("patients", "patients", "age"),
+            sealed_column("patients", "patients", "gender"),
+            sealed_column("patients", "patients", "blood_group"),
+            sealed_column("patients", "patients", "weight"),
+            patient_id
+        );
+        self.get_query_result::<PatientInfo, Postgres>(&query).await
+    }
+
+    //doctors offering an appointment type, filtered, sorted and paginated with a keyset cursor so pages
+    //stay consistent while new doctors sign up
+    pub async fn view_doctor_prices(&self, filter: &FindFilter) -> Option<FindPage> {
+        let mut filters = String::new();
+        if !filter.city.is_empty() {
+            filters.push_str(&format!(" and c.city = '{}'", escape(&filter.city)));
+        }
+        if !filter.apptype.is_empty() {
+            filters.push_str(&format!(" and t.name = '{}'", escape(&filter.apptype)));
+        }
+        if let Some(speciality) = filter.speciality {
+            filters.push_str(&format!(" and t.speciality_id = {}", speciality));
+        }
+        if let Some(min) = filter.min_price {
+            filters.push_str(&format!(" and p.price >= {}", min));
+        }
+        if let Some(max) = filter.max_price {
+            filters.push_str(&format!(" and p.price <= {}", max));
+        }
+        if let Some(mode) = filter.mode.as_deref().filter(|m| !m.is_empty()) {
+            filters.push_str(&format!(" and '{}' = any(d.consultation_modes)", escape(mode)));
+        }
+        if let Some(language) = filter.language.as_deref().filter(|l| !l.is_empty()) {
+            filters.push_str(&format!(" and '{}' ilike any(d.languages)", escape(language)));
+        }
+        if let Some(gender) = filter.gender.as_deref().filter(|g| !g.is_empty()) {
+            filters.push_str(&format!(" and d.gender = '{}'", escape(gender)));
+        }
+        if filter.emergency {
+            filters.push_str(" and exists (select 1 from doctors_emergency e where e.doctor_id = d.id and e.available)");
+        }
+        let distance = match (filter.lat, filter.lng) {
+            (Some(lat), Some(lng)) => distance_sql(lat, lng),
+            _ => String::from("null::double precision"),
+        };
+        //everything is sorted ascending on sortkey, ties broken by docid, clinic_id and appid for the cursor
+        let sortkey = match filter.sort.as_deref().unwrap_or("price") {
+            "price" => "price::double precision",
+            "distance" if filter.lat.is_some() && filter.lng.is_some() => "coalesce(distance, 'infinity')",
+            "next_available" => "coalesce(extract(epoch from next_slot)::double precision, 'infinity')",
+            //best rated first, unrated doctors last
+            "rating" => "-coalesce(rating, 0)",
+            other => {
+                tracing::error!("Can't sort by {}", other);
+                return None;
+            }
+        };
+        let base = format!(
+            "
+                    select d.id as docid, d.name as docname, c.city as city, c.address as address, c.id as clinic_id, c.name as clinic,
+                    t.name as apptype, t.id as appid, p.price, spec.name as specname,
+                    {} as distance, {} as next_slot, {}
+                    from doctors d
+                    join doctor_clinics dc on dc.doctor_id = d.id
+                    join clinics c on c.id = dc.clinic_id
+                    join doctor_specialities ds on ds.doctor_id = d.id
+                    join appointment_types t on t.speciality_id = ds.speciality_id
+                    join specialities spec on spec.id = t.speciality_id
+                    join appointment_prices p on d.id = p.doctor_id and t.id = p.appointment_type
+                    where {} {}
+                    ",
+            distance,
+            next_slot_sql("current_date", &format!("current_date + {}", NEXT_SLOT_DAYS)),
+            rating_sql(),
+            verified_sql(),
+            filters
+        );
+        let query = format!("select count(*) as num from ({}) b", base);
+        let total = match sqlx::query_as::<_, TokenNumberPrimary>(&query)
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(tn) => tn.num,
+            Err(e) => {
+                tracing::error!("Error while counting doctors: {}", e);
+                return None;
+            }
+        };
+        let after = match filter.cursor.as_deref().filter(|c| !c.is_empty()) {
+            Some(cursor) => {
+                let parts: Vec<&str> = cursor.split('_').collect();
+                let [key, docid, clinic_id, appid] = parts[..] else {
+                    tracing::error!("Malformed cursor {}", cursor);
+                    return None;
+                };
+                let (Ok(key), Ok(docid), Ok(clinic_id), Ok(appid)) =
+                    (key.parse::<f64>(), docid.parse::<i64>(), clinic_id.parse::<i64>(), appid.parse::<i64>())
+                else {
+                    tracing::error!("Malformed cursor {}", cursor);
+                    return None;
+                };
+                format!(
+                    "where (sortkey, docid, clinic_id, appid) > ('{}'::double precision, {}, {}, {})",
+                    key, docid, clinic_id, appid
+                )
+            }
+            None => String::new(),
+        };
+        let limit = filter.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+        //one extra row tells us whether there is another page
+        let query = format!(
+            "
+                    select * from (
+                        select docid, docname, city, address, clinic_id, clinic, apptype, appid, price, specname, distance,
+                        TO_CHAR(next_slot, 'YYYY-MM-DD HH24:MI:SS') as next_available, rating, rating_count, {} as sortkey
+                        from ({}) b
+                    ) x
+                    {}
+                    order by sortkey, docid, clinic_id, appid
+                    limit {};
+                    ",
+            sortkey,
+            base,
+            after,
+            limit + 1
+        );
+        let mut results = self.get_query_result::<FindResults, Postgres>(&query).await;
+        let mut next_cursor = None;
+        if results.len() > limit as usize {
+            results.truncate(limit as usize);
+            next_cursor = results
+                .last()
+                .map(|last| format!("{}_{}_{}_{}", last.sortkey, last.docid, last.clinic_id, last.appid));
+        }
+        Some(FindPage {
+            results,
+            total,
+            next_cursor,
+        })
+    }
+
+    //doctors ordered by their earliest free slot in the date range at each of their clinics, along with that slot so it can be booked
+    pub async fn view_next_available(&self, filter: &NextAvailableFilter) -> Vec<NextAvailable> {
+        let today = chrono::Local::now().date_naive();
+        let parse = |date: &Option<String>, default: NaiveDate| match date.as_deref().filter(|d| !d.is_empty()) {
+            Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d").ok(),
+            None => Some(default),
+        };
+        let Some(from) = parse(&filter.from, today) else {
+            tracing::error!("Couldn't parse date into NaiveDateTime");
+            return Vec::new();
+        };
+        let Some(to) = parse(&filter.to, from + chrono::Duration::days(NEXT_SLOT_DAYS as i64 - 1)) else {
+            tracing::error!("Couldn't parse date into NaiveDateTime");
+            return Vec::new();
+        };
+        if to < from || (to - from).num_days() > MAX_SLOT_SEARCH_DAYS {
+            tracing::error!("Date range {} to {} is invalid or too long", from, to);
+            return Vec::new();
+        }
+        let mut filters = String::new();
+        if !filter.city.is_empty() {
+            filters.push_str(&format!(" and c.city = '{}'", escape(&filter.city)));
+        }
+        if let Some(speciality) = filter.speciality {
+            filters.push_str(&format!(
+                " and exists (select 1 from doctor_specialities ds where ds.doctor_id = d.id and ds.speciality_id = {})",
+                speciality
+            ));
+        }
+        let (price, pricejoin) = match filter.apptype {
+            Some(apptype) => (
+                "p.price",
+                format!("join appointment_prices p on p.doctor_id = d.id and p.appointment_type = {}", apptype),
+            ),
+            None => ("null::int", String::new()),
+        };
+        let query = format!(
+            "
+                    select d.id as docid, d.name as docname, c.city, c.address, c.id as clinic_id, c.name as clinic, {} as specname, {} as price,
+                    n.slot_id, TO_CHAR(n.slot, 'YYYY-MM-DD') as date, TO_CHAR(n.slot, 'HH24:MI:SS') as time_start
+                    from doctors d
+                    join doctor_clinics dc on dc.doctor_id = d.id
+                    join clinics c on c.id = dc.clinic_id
+                    {}
+                    cross join lateral ({} order by slot limit 1) n
+                    where {} {}
+                    order by n.slot, d.id, c.id
+                    limit {};
+                    ",
+            specialities_sql(),
+            price,
+            pricejoin,
+            free_slots_sql(&format!("'{}'", from), &format!("'{}'", to)),
+            verified_sql(),
+            filters,
+            DEFAULT_PAGE_SIZE
+        );
+        self.get_query_result::<NextAvailable, Postgres>(&query)
+            .await
+    }
+
+    pub async fn view_doctor_prices_emergency(&self, city: &str, apptype: &str) -> Vec<DoctorPrices> {
+        let iscityspecified = match city.is_empty() {
+            false => format!("and c.city = '{}'", escape(city)),
+            true => String::new(),
+        };
+        let isapptypespecified = match apptype.is_empty() {
+            false => format!("and t.name = '{}'", escape(apptype)),
+            true => String::new(),
+        };
+
+        let query = format!(
+            "
+                    select d.id as docid, d.name as docname, c.city as city, c.address as address, c.id as clinic_id, c.name as clinic,
+                    t.name as apptype, t.id as appid, 2*p.price as price, spec.name as specname, {}
+                    from doctors d
+                    join doctor_clinics dc on dc.doctor_id = d.id
+                    join clinics c on c.id = dc.clinic_id
+                    join doctor_specialities ds on ds.doctor_id = d.id
+                    join appointment_types t on t.speciality_id = ds.speciality_id
+                    join specialities spec on spec.id = t.speciality_id
+                    join doctors_emergency e on e.doctor_id = d.id
+                    join appointment_prices p on d.id = p.doctor_id and t.id = p.appointment_type
+                    where {} {} {};
+                    ",
+            rating_sql(), verified_sql(), isapptypespecified, iscityspecified
+        );
+        self.get_query_result::<DoctorPrices, Postgres>(&query)
+            .await
+    }
+
+    //ranked search over doctor name, speciality, appointment types offered and their clinics' names, cities and addresses;
+    //every word is matched as a prefix ("cardio" finds Cardiology) and trigram similarity catches typos
+    pub async fn search_doctors(&self, text: &str) -> Vec<DoctorSearch> {
+        let words: Vec<String> = text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(|w| format!("{}:*", w.to_lowercase()))
+            .collect();
+        if words.is_empty() {
+            return Vec::new();
+        }
+        let tsquery = words.join(" & ");
+        let text = escape(text);
+        let query = format!("
+                    with docs as (
+                        select d.id as docid, d.name as docname,
+                        coalesce(string_agg(distinct c.city, ', '), '') as city,
+                        coalesce(string_agg(distinct c.address, '; '), '') as address,
+                        coalesce(string_agg(distinct s.name, ', '), '') as specname,
+                        coalesce(string_agg(distinct t.name, ', '), '') as apptypes,
+                        concat_ws(' ', d.name, string_agg(distinct s.name, ' '), string_agg(distinct t.name, ' '),
+                            string_agg(distinct c.name, ' '), string_agg(distinct c.city, ' '), string_agg(distinct c.address, ' ')) as body
+                        from doctors d
+                        left join doctor_clinics dc on dc.doctor_id = d.id
+                        left join clinics c on c.id = dc.clinic_id
+                        left join doctor_specialities ds on ds.doctor_id = d.id
+                        left join specialities s on s.id = ds.speciality_id
+                        left join appointment_prices p on p.doctor_id = d.id
+                        left join appointment_types t on t.id = p.appointment_type and t.speciality_id = ds.speciality_id
+                        where {verified}
+                        group by d.id
+                    ), q as (select to_tsquery('simple', '{tsquery}') as tsq)
+                    select docid, docname, city, address, specname, apptypes,
+                    (ts_rank(to_tsvector('simple', body), q.tsq) + word_similarity('{text}', body))::real as rank,
+                    ts_headline('simple', translate(body, chr(2) || chr(3), ''), q.tsq,
+                        'StartSel=' || chr(2) || ', StopSel=' || chr(3) || ', HighlightAll=true') as highlight
+                    from docs, q
+                    where to_tsvector('simple', body) @@ q.tsq or word_similarity('{text}', body) > {SEARCH_SIMILARITY}
+                    order by rank desc, docid
+                    limit 20;
+                    ", verified = verified_sql());
+        let mut results = self.get_query_result::<DoctorSearch, Postgres>(&query).await;
+        for doctor in results.iter_mut() {
+            doctor.highlight = highlight_html(&doctor.highlight);
+        }
+        results
+    }
+
+    //doctors with a clinic within radius km of the patient, closest first, with the same filters as view_doctor_prices
+    fn nearby_doctors_sql(filter: &NearbyFilter) -> String {
+        let radius = filter.radius.unwrap_or(DEFAULT_RADIUS_KM);
+        let mut filters = String::new();
+        if let Some(apptype) = filter.apptype.as_deref().filter(|a| !a.is_empty()) {
+            filters.push_str(&format!(" and t.name = '{}'", escape(apptype)));
+        }
+        if let Some(speciality) = filter.speciality {
+            filters.push_str(&format!(" and t.speciality_id = {}", speciality));
+        }
+        if let Some(min) = filter.min_price {
+            filters.push_str(&format!(" and p.price >= {}", min));
+        }
+        if let Some(max) = filter.max_price {
+            filters.push_str(&format!(" and p.price <= {}", max));
+        }
+        format!(
+            "
+                    select * from (
+                        select d.id as docid, d.name as docname, c.city as city, c.address as address, c.id as clinic_id, c.name as clinic,
+                        t.name as apptype, t.id as appid, p.price, spec.name as specname,
+                        {} as distance
+                        from doctors d
+                        join doctor_clinics dc on dc.doctor_id = d.id
+                        join clinics c on c.id = dc.clinic_id
+                        join doctor_specialities ds on ds.doctor_id = d.id
+                        join appointment_types t on t.speciality_id = ds.speciality_id
+                        join specialities spec on spec.id = t.speciality_id
+                        join appointment_prices p on d.id = p.doctor_id and t.id = p.appointment_type
+                        where {} and {} {}
+                    ) x
+                    where distance <= {}
+                    order by distance, price;
+                    ",
+            distance_sql(filter.lat, filter.lng),
+            bounding_box_sql(filter.lat, filter.lng, radius),
+            verified_sql(),
+            filters,
+            radius
+        )
+    }
+
+    pub async fn view_nearby_doctors(&self, filter: &NearbyFilter) -> Vec<NearbyDoctors> {
+        let query = Self::nearby_doctors_sql(filter);
+        self.get_query_result::<NearbyDoctors, Postgres>(&query)
+            .await
+    }
+
+    pub async fn view_doctor_clinics(&self, doctor_id: i64) -> Vec<Clinics> {
+        let query = format!("
+                    select c.id, c.name, c.address, c.city, c.latitude, c.longitude, c.hours, c.phone, c.owner_id::bigint as owner_id
+                    from doctor_clinics dc
+                    join clinics c on c.id = dc.clinic_id
+                    where dc.doctor_id = {}
+                    order by c.id;
+                            ", doctor_id);
+        self.get_query_result::<Clinics, Postgres>(&query)
+            .await
+    }
+
+    //the doctor creating the clinic owns it and practises there
+    pub async fn add_clinic(&self, input: &NewClinic) -> bool {
+        let optional = |value: &Option<String>| match value {
+            Some(value) => format!("'{}'", escape(value)),
+            None => String::from("null"),
+        };
+        let coordinate = |value: Option<f64>| match value {
+            Some(value) => value.to_string(),
+            None => String::from("null"),
+        };
+        let query = format!("
+                    with c as (
+                        insert into clinics(name, address, city, latitude, longitude, hours, phone, owner_id)
+                        values ('{}', '{}', '{}', {}, {}, {}, {}, {}) returning id, owner_id
+                    )
+                    insert into doctor_clinics(doctor_id, clinic_id) select owner_id, id from c;
+                            ",
+            escape(&input.name),
+            escape(&input.address),
+            escape(&input.city),
+            coordinate(input.latitude),
+            coordinate(input.longitude),
+            optional(&input.hours),
+            optional(&input.phone),
+            input.doctor_id
+        );
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(e) => {
+                tracing::error!("Error while adding clinic: {}", e);
+                false
+            }
+        }
+    }
+
+    //only the fields that were sent get changed, owner_id limits it to a clinic that doctor owns
+    fn clinic_update_sql(changes: &ClinicChanges, owner_id: Option<i64>) -> Option<String> {
+        let mut updates: Vec<String> = Vec::new();
+        let text_fields = [
+            ("name", &changes.name),
+            ("address", &changes.address),
+            ("city", &changes.city),
+            ("hours", &changes.hours),
+            ("phone", &changes.phone),
+        ];
+        for (column, value) in text_fields {
+            if let Some(value) = value {
+                updates.push(format!("{} = '{}'", column, escape(value)));
+            }
+        }
+        if let Some(latitude) = changes.latitude {
+            updates.push(format!("latitude = {}", latitude));
+        }
+        if let Some(longitude) = changes.longitude {
+            updates.push(format!("longitude = {}", longitude));
+        }
+        if updates.is_empty() {
+            tracing::error!("Nothing to update for clinic {}", changes.clinic_id);
+            return None;
+        }
+        let owner = match owner_id {
+            Some(owner_id) => format!(" and owner_id = {}", owner_id),
+            None => String::new(),
+        };
+        Some(format!("
+                    update clinics set {} where id = {}{};
+                            ", updates.join(", "), changes.clinic_id, owner))
+    }
+
+    //only the clinic's owner can edit it
+    pub async fn update_clinic(&self, input: &ClinicUpdate) -> bool {
+        let Some(query) = Self::clinic_update_sql(&input.changes, Some(input.doctor_id)) else {
+            return false;
+        };
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(e) => {
+                tracing::error!("Error while updating clinic: {}", e);
+                false
+            }
+        }
+    }
+
+    pub async fn admin_update_clinic(&self, input: &AdminClinicUpdate) -> bool {
+        let Some(query) = Self::clinic_update_sql(&input.changes, None) else {
+            return false;
+        };
+        let target = format!("clinic {}", input.changes.clinic_id);
+        self.execute_audited(&query, input.admin_id, "clinic.update", &target, "")
+            .await
+    }
+
+    //doctors only start practising at a clinic once its owner or an admin approves
+    pub async fn request_clinic(&self, doctor_id: i64, clinic_id: i64) -> bool {
+        let query = format!("
+                    insert into clinic_requests(doctor_id, clinic_id)
+                    select {0}, id from clinics where id = {1}
+                    and not exists (select 1 from doctor_clinics where doctor_id = {0} and clinic_id = {1})
+                    on conflict do nothing;
+                            ", doctor_id, clinic_id);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(e) => {
+                tracing::error!("Error while requesting clinic: {}", e);
+                false
+            }
+        }
+    }
+
+    //pending requests for the clinics owner_id owns, or for every clinic when there is no owner_id (admins)
+    pub async fn view_clinic_requests(&self, owner_id: Option<i64>) -> Vec<ClinicRequests> {
+        let owner = match owner_id {
+            Some(owner_id) => format!("where c.owner_id = {}", owner_id),
+            None => String::new(),
+        };
+        let query = format!("
+                    select d.id as doctor_id, d.name as docname, c.id as clinic_id, c.name as clinic,
+                    TO_CHAR(r.requested_at, 'YYYY-MM-DD HH24:MI:SS') as requested_at
+                    from clinic_requests r
+                    join doctors d on d.id = r.doctor_id
+                    join clinics c on c.id = r.clinic_id
+                    {}
+                    order by r.requested_at;
+                            ", owner);
+        self.get_query_result::<ClinicRequests, Postgres>(&query)
+            .await
+    }
+
+    //the request is gone either way, approving it adds the doctor to the clinic
+    fn clinic_decision_sql(clinic_id: i64, requester_id: i64, approved: bool, owner_id: Option<i64>) -> String {
+        let owner = match owner_id {
+            Some(owner_id) => format!(" and clinic_id in (select id from clinics where owner_id = {})", owner_id),
+            None => String::new(),
+        };
+        let request = format!(
+            "delete from clinic_requests where doctor_id = {} and clinic_id = {}{}",
+            requester_id, clinic_id, owner
+        );
+        if approved {
+            format!("
+                    with r as ({} returning doctor_id, clinic_id)
+                    insert into doctor_clinics(doctor_id, clinic_id) select doctor_id, clinic_id from r;
+                            ", request)
+        } else {
+            format!("{};", request)
+        }
+    }
+
+    pub async fn decide_clinic_request(&self, input: &ClinicRequestDecision) -> bool {
+        let query = Self::clinic_decision_sql(input.clinic_id, input.requester_id, input.approved, Some(input.doctor_id));
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(e) => {
+                tracing::error!("Error while deciding clinic request: {}", e);
+                false
+            }
+        }
+    }
+
+    pub async fn admin_decide_clinic_request(&self, input: &AdminClinicRequestDecision) -> bool {
+        let query = Self::clinic_decision_sql(input.clinic_id, input.requester_id, input.approved, None);
+        let action = if input.approved { "clinic.approve_doctor" } else { "clinic.decline_doctor" };
+        let target = format!("clinic {}", input.clinic_id);
+        self.execute_audited(&query, input.admin_id, action, &target, &format!("doctor {}", input.requester_id))
+            .await
+    }
+
+    //a doctor has to keep at least one clinic, their slots there stop showing up
+    pub async fn remove_doctor_clinic(&self, doctor_id: i64, clinic_id: i64) -> bool {
+        let query = format!("
+                    delete from doctor_clinics
+                    where doctor_id = {0} and clinic_id = {1}
+                    and (select count(*) from doctor_clinics where doctor_id = {0}) > 1;
+                            ", doctor_id, clinic_id);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(e) => {
+                tracing::error!("Error while removing clinic: {}", e);
+                false
+            }
+        }
+    }
+
+    //patients can review each fulfilled appointment or token once
+    fn add_review_sql(input: &NewReview) -> Option<String> {
+        let tablename = match input.source.as_str() {
+            "appointment" => "appointments",
+            "token" => "tokens",
+            _ => {
+                tracing::error!("Unknown review source {}", input.source);
+                return None;
+            }
+        };
+        Some(format!("
+                    insert into reviews(doctor_id, patient_id, {}_id, rating, review)
+                    select doctor_id, patient_id, id, {}, '{}' from {}
+                    where id = {} and patient_id = {} and status = 'fulfilled'
+                    returning id;
+                            ", input.source, input.rating, escape(&input.review), tablename, input.source_id, input.patient_id))
+    }
+
+    pub async fn add_review(&self, input: &NewReview) -> bool {
+        let Some(query) = Self::add_review_sql(input) else {
+            return false;
+        };
+        match sqlx::query(&query).fetch_optional(&self.connection).await {
+            Ok(Some(_)) => true,
+            Ok(None) => {
+                tracing::error!("No fulfilled {} {} for patient {}", input.source, input.source_id, input.patient_id);
+                false
+            }
+            Err(e) => {
+                tracing::error!("Error while adding review: {}", e);
+                false
+            }
+        }
+    }
+
+    pub async fn reply_review(&self, doctor_id: i64, review_id: i64, reply: &str) -> bool {
+        let query = format!("
+                    update reviews set reply = '{}', replied_at = now() where id = {} and doctor_id = {};
+                            ", escape(reply), review_id, doctor_id);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(_) => false,
+        }
+    }
+
+    //marks a review for moderation, it stays visible until it is hidden
+    pub async fn flag_review(&self, doctor_id: i64, review_id: i64, reason: &str) -> bool {
+        let query = format!("
+                    update reviews set flagged = true, flag_reason = '{}' where id = {} and doctor_id = {};
+                            ", escape(reason), review_id, doctor_id);
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(_) => false,
+        }
+    }
+
+    pub async fn view_doctor_reviews(&self, doctor_id: i64) -> Vec<Reviews> {
+        let query = format!("
+                    select r.id, r.rating, r.review, r.reply, split_part(p.name, ' ', 1) as patname,
+                    TO_CHAR(r.created_at, 'YYYY-MM-DD') as date
+                    from reviews r
+                    join patients p on p.id = r.patient_id
+                    where r.doctor_id = {} and not r.hidden
+                    order by r.created_at desc;
+                            ", doctor_id);
+        self.get_query_result::<Reviews, Postgres>(&query)
+            .await
+    }
+
+    pub async fn view_specialities(&self) -> Vec<Specialities> {
+        let query = String::from(
+            "select id, name, description as desc
+                    from specialities;",
+        );
+        self.get_query_result::<Specialities, Postgres>(&query)
+            .await
+    }
+
+    pub async fn view_appointment_types(&self) -> Vec<Apptypes> {
+        let query = String::from(
+            "select id, name from appointment_types;"
+        );
+        self.get_query_result::<Apptypes, Postgres>(&query)
+            .await
+    }
+
+    pub async fn view_cities(&self) -> Vec<Cities> {
+        let query = String::from(
+            "select distinct(city) as city from clinics"
+        );
+        self.get_query_result::<Cities, Postgres>(&query)
+            .await
+    }
+
+    pub async fn view_new_token(&self, doctor_id: i64, clinic_id: i64, date: &str) -> TokenNumberPrimary {
+        let query = format!("select count(*) as num from tokens where doctor_id = {} and clinic_id = {} and TO_CHAR(appointment_date, 'YYYY-MM-DD') = '{}'", doctor_id, clinic_id, escape(date));
+        match sqlx::query_as::<_, TokenNumberPrimary>(&query)
+            .fetch_one(&self.connection)
+            .await {
+                Ok(mut tn) => {
+                    tn.num += 1;
+                    tn
+                }
+                Err(_) => {
+                    TokenNumberPrimary {
+                        num: 1
+                    }
+                }
+            }
+    }
+
+    pub async fn view_current_token(&self,doctor_id: i64, clinic_id: i64, date: &str) -> TokenNumberPrimary {
+        let query = format!("select token_number::bigint as num from tokens where doctor_id = {} and clinic_id = {} and TO_CHAR(appointment_date, 'YYYY-MM-DD') = '{}' and status = 'ongoing'", doctor_id, clinic_id, escape(date));
+        match sqlx::query_as::<_, TokenNumberPrimary>(&query)
+            .fetch_one(&self.connection)
+            .await {
+                Ok(tn) => tn,
+                Err(_) => {
+                    TokenNumberPrimary {
+                        num: 0
+                    }
+                }
+            }
+    }
+
+    pub async fn get_patient_token(&self, doctor_id: i64, patient_id: i64, date: &String) -> TokenNumber {
+        let query = format!("select token_number as num from tokens where doctor_id = {} and TO_CHAR(appointment_date, 'YYYY-MM-DD') = '{}' and patient_id = {}", doctor_id, date, patient_id);
+        match sqlx::query_as::<_, TokenNumber>(&query)
+            .fetch_one(&self.connection)
+            .await {
+                Ok(tn) => tn,
+                Err(_) => {
+                    TokenNumber {
+                        num: 0
+                    }
+                }
+            }
+    }
+
+
+    pub async fn view_doctor_appointments(&self, doctor_id: i64) -> Vec<DoctorAppointments> {
+        let query = format!(
+            "
+            select id, patient_id, appointment_type as apptype,
+            TO_CHAR(appointment_date, 'YYYY-MM-DD') as date,
+            type as phyorvirt, status, slot_id, {} from appointments where doctor_id = {} order by date
+            ", sealed_column("appointments", "appointments", "symptom"), doctor_id
+        );
+        self.get_query_result::<DoctorAppointments, Postgres>(&query)
+            .await
+    }
+
+    pub async fn view_doctor_emergencies(&self, doctor_id: i64, date: &String) -> Vec<EmergencyAppointments> {
+        let query = format!(
+            "
+            select emergency_no as id, patient_id, appointment_type as apptype,
+            {} from emergency_appointments where doctor_id = {}
+            and TO_CHAR(appointment_date, 'YYYY-MM-DD') = '{}'
+            order by appointment_date
+            ", sealed_column("emergency_appointments", "emergency_appointments", "symptom"), doctor_id, date
+        );
+        self.get_query_result::<EmergencyAppointments, Postgres>(&query)
+            .await
+    }
+
+    //inserts a profile (the query returns its id) and the login pointing at it, neither is kept without the other
+    async fn signup(&self, profile: &str, email: &str, password: &str, role: &str) -> Result<(), SignupError> {
+        let Some(column) = profile_column(role) else {
+            tracing::error!("No such role {}", role);
+            return Err(SignupError::Failed);
+        };
+        let Ok((hash, salt)) = argon_hash_password::create_hash_and_salt(password) else {
+            tracing::error!("Hash and salt were not able to be created, registration error");
+            return Err(SignupError::Failed);
+        };
+        let Ok(mut tx) = self.connection.begin().await else {
+            tracing::error!("Couldn't start transaction for signup");
+            return Err(SignupError::Failed);
+        };
+        let id: i64 = match sqlx::query(profile).fetch_optional(&mut tx).await {
+            Ok(Some(row)) => match row.try_get("id") {
+                Ok(id) => id,
+                Err(e) => {
+                    tracing::error!("Error while retrieving {} id: {}", role, e);
+                    return Err(SignupError::Failed);
+                }
+            },
+            Ok(None) => {
+                tracing::error!("No {} was inserted", role);
+                return Err(SignupError::Failed);
+            }
+            Err(e) if unique_violation(&e) => {
+                tracing::error!("A {} with this email already exists", role);
+                return Err(SignupError::Duplicate);
+            }
+            Err(e) => {
+                tracing::error!("Error while inserting {}: {}", role, e);
+                return Err(SignupError::Failed);
+            }
+        };
+        let query = format!("
+                    insert into login(email, password, salt, isdoctor, role, {}) values ('{}', '{}', '{}', {}, '{}', {});
+                            ", column, escape(email), hash, salt, role == "doctor", role, id);
+        match sqlx::query(&query).execute(&mut tx).await {
+            Ok(_) => (),
+            Err(e) if unique_violation(&e) => {
+                tracing::error!("A login with this email already exists");
+                return Err(SignupError::Duplicate);
+            }
+            Err(e) => {
+                tracing::error!("Error while inserting login: {}", e);
+                return Err(SignupError::Failed);
+            }
+        }
+        tx.commit().await.map_err(|e| {
+            tracing::error!("Error while committing signup: {}", e);
+            SignupError::Failed
+        })
+    }
+
+    //only a doctor practising at the clinic can add its receptionists
+    pub async fn add_receptionist(&self, input: &NewReceptionist) -> Result<(), SignupError> {
+        let query = format!("
+                    insert into receptionists(name, email, phone, clinic_id)
+                    select '{}', '{}', '{}', clinic_id from doctor_clinics where doctor_id = {} and clinic_id = {}
+                    returning id;
+                            ",
+            escape(&input.name),
+            escape(&input.email),
+            escape(&input.phone),
+            input.doctor_id,
+            input.clinic_id
+        );
+        self.signup(&query, &input.email, &input.password, "receptionist")
+            .await
+    }
+
+    pub async fn receptionist_clinic(&self, receptionist_id: i64) -> Option<i64> {
+        let query = format!("select clinic_id from receptionists where id = {};", receptionist_id);
+        match sqlx::query(&query).fetch_one(&self.connection).await {
+            Ok(row) => row.try_get::<i32, _>("clinic_id").ok().map(i64::from),
+            Err(e) => {
+                tracing::error!("Error while fetching clinic of receptionist {}: {}", receptionist_id, e);
+                None
+            }
+        }
+    }
+
+    pub async fn doctor_at_clinic(&self, doctor_id: i64, clinic_id: i64) -> bool {
+        let query = format!("
+                    select 1 from doctor_clinics where doctor_id = {} and clinic_id = {};
+                            ", doctor_id, clinic_id);
+        matches!(sqlx::query(&query).fetch_optional(&self.connection).await, Ok(Some(_)))
+    }
+
+    //unverified doctors can't be booked or write prescriptions
+    pub async fn doctor_verified(&self, doctor_id: i64) -> bool {
+        let query = format!("
+                    select 1 from doctors d where d.id = {} and {};
+                            ", doctor_id, verified_sql());
+        matches!(sqlx::query(&query).fetch_optional(&self.connection).await, Ok(Some(_)))
+    }
+
+    pub async fn add_walkin_patient(&self, input: &WalkInPatient) -> Option<PatientContact> {
+        let id = self.next_id("patients").await?;
+        let gender = match &input.gender {
+            Some(gender) => sealed(gender, "patients", "gender", id)?,
+            None => String::from("null"),
+        };
+        let age = match input.age {
+            Some(age) => sealed(&age.to_string(), "patients", "age", id)?,
+            None => String::from("null"),
+        };
+        let query = format!("
+                    insert into patients(id, name, phone, gender, age) values ({}, '{}', '{}', {}, {})
+                    returning id, name, phone;
+                            ", id, escape(&input.name), escape(&input.phone), gender, age);
+        match sqlx::query_as::<_, PatientContact>(&query)
+            .fetch_one(&self.connection)
+            .await
+        {
+            Ok(patient) => Some(patient),
+            Err(e) => {
+                tracing::error!("Error while adding walk-in patient: {}", e);
+                None
+            }
+        }
+    }
+
+    //so returning walk-ins aren't registered twice
+    pub async fn find_patients_by_phone(&self, phone: &str) -> Vec<PatientContact> {
+        let query = format!("
+                    select id, name, phone from patients where phone = '{}' and deleted_at is null order by id;
+                            ", escape(phone));
+        self.get_query_result::<PatientContact, Postgres>(&query)
+            .await
+    }
+
+    //front desk marks a patient with a booking at their clinic as arrived
+    pub async fn check_in(&self, receptionist_id: i64, source: &str, source_id: i64) -> bool {
+        let query = match source {
+            "token" => format!("
+                    update tokens set checked_in_at = localtimestamp
+                    where id = {} and status = 'scheduled' and checked_in_at is null
+                    and clinic_id = (select clinic_id from receptionists where id = {});
+                            ", source_id, receptionist_id),
+            "appointment" => format!("
+                    update appointments a set checked_in_at = localtimestamp
+                    from doctor_slots s
+                    where s.id = a.slot_id and a.id = {} and a.status = 'scheduled' and a.checked_in_at is null
+                    and s.clinic_id = (select clinic_id from receptionists where id = {});
+                            ", source_id, receptionist_id),
+            other => {
+                tracing::error!("Can't check in to {}", other);
+                return false;
+            }
+        };
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(res) => res.rows_affected() == 1,
+            Err(e) => {
+                tracing::error!("Error while checking in: {}", e);
+                false
+            }
+        }
+    }
+
+    pub async fn view_token_queue(&self, receptionist_id: i64, doctor_id: i64, date: &str) -> Vec<TokenQueue> {
+        let query = format!("
+                    select t.id, t.token_number, t.patient_id, p.name as patname, t.status,
+                    TO_CHAR(t.checked_in_at, 'HH24:MI:SS') as checked_in_at
+                    from tokens t
+                    join patients p on p.id = t.patient_id
+                    where t.doctor_id = {} and TO_CHAR(t.appointment_date, 'YYYY-MM-DD') = '{}'
+                    and t.clinic_id = (select clinic_id from receptionists where id = {})
+                    order by t.token_number;
+                            ", doctor_id, escape(date), receptionist_id);
+        self.get_query_result::<TokenQueue, Postgres>(&query)
+            .await
+    }
+
+    //finishes the ongoing token and calls the next checked in one, 0 when nobody is waiting
+    pub async fn advance_token_queue(&self, receptionist_id: i64, doctor_id: i64, date: &str) -> Option<TokenNumber> {
+        let Ok(mut tx) = self.connection.begin().await else {
+            tracing::error!("Couldn't start transaction");
+            return None;
+        };
+        let scope = format!(
+            "doctor_id = {} and TO_CHAR(appointment_date, 'YYYY-MM-DD') = '{}'
+            and clinic_id = (select clinic_id from receptionists where id = {})",
+            doctor_id,
+            escape(date),
+            receptionist_id
+        );
+        let query = format!("
+                    update tokens set status = 'fulfilled' where {} and status = 'ongoing';
+                            ", scope);
+        if let Err(e) = sqlx::query(&query).execute(&mut tx).await {
+            tracing::error!("Error while finishing current token: {}", e);
+            return None;
+        }
+        let query = format!("
+                    update tokens set status = 'ongoing'
+                    where id = (
+                        select id from tokens where {} and status = 'scheduled' and checked_in_at is not null
+                        order by token_number limit 1 for update
+                    )
+                    returning token_number as num;
+                            ", scope);
+        let next = match sqlx::query_as::<_, TokenNumber>(&query)
+            .fetch_optional(&mut tx)
+            .await
+        {
+            Ok(next) => next.unwrap_or(TokenNumber { num: 0 }),
+            Err(e) => {
+                tracing::error!("Error while calling next token: {}", e);
+                return None;
+            }
+        };
+        match tx.commit().await {
+            Ok(_) => Some(next),
+            Err(e) => {
+                tracing::error!("Error while advancing queue: {}", e);
+                None
+            }
+        }
+    }
+
+    //runs a change made by an admin along with its audit log entry, neither happens without the other
+    async fn execute_audited(&self, query: &str, admin_id: i64, action: &str, target: &str, details: &str) -> bool {
+        let Ok(mut tx) = self.connection.begin().await else {
+            tracing::error!("Couldn't start transaction");
+            return false;
+        };
+        match sqlx::query(query).execute(&mut tx).await {
+            Ok(res) if res.rows_affected() > 0 => (),
+            Ok(_) => return false,
+            Err(e) => {
+                tracing::error!("Error while running {}: {}", action, e);
+                return false;
+            }
+        }
+        let query = format!("
+                    insert into audit_log(actor_id, actor_role, action, target, details) values ({}, 'admin', '{}', '{}', '{}');
+                            ", admin_id, escape(action), escape(target), escape(details));
+        if let Err(e) = sqlx::query(&query).execute(&mut tx).await {
+            tracing::error!("Error while writing audit log: {}", e);
+            return false;
+        }
+        tx.commit().await.is_ok()
+    }
+
+    pub async fn add_speciality(&self, input: &NewSpeciality) -> bool {
+        let description = input.description.as_deref().unwrap_or("");
+        let query = format!("
+                    insert into specialities(name, description) values ('{}', '{}');
+                            ", escape(&input.name), escape(description));
+        self.execute_audited(&query, input.admin_id, "speciality.create", &input.name, description)
+            .await
+    }
+
+    pub async fn update_speciality(&self, input: &SpecialityUpdate) -> bool {
+        let mut updates: Vec<String> = Vec::new();
+        if let Some(name) = &input.name {
+            updates.push(format!("name = '{}'", escape(name)));
+        }
+        if let Some(description) = &input.description {
+            updates.push(format!("description = '{}'", escape(description)));
+        }
+        if updates.is_empty() {
+            return false;
+        }
+        let details = updates.join(", ");
+        let query = format!("update specialities set {} where id = {};", details, input.id);
+        self.execute_audited(&query, input.admin_id, "speciality.update", &format!("speciality {}", input.id), &details)
+            .await
+    }
+
+    //fails while doctors or appointment types still use it
+    pub async fn delete_speciality(&self, admin_id: i64, id: i64) -> bool {
+        let query = format!("delete from specialities where id = {};", id);
+        self.execute_audited(&query, admin_id, "speciality.delete", &format!("speciality {}", id), "")
+            .await
+    }
+
+    pub async fn add_apptype(&self, input: &NewApptype) -> bool {
+        let description = input.description.as_deref().unwrap_or("");
+        let query = format!("
+                    insert into appointment_types(name, speciality_id, description) values ('{}', {}, '{}');
+                            ", escape(&input.name), input.speciality_id, escape(description));
+        let details = format!("speciality_id = {}, description = {}", input.speciality_id, description);
+        self.execute_audited(&query, input.admin_id, "apptype.create", &input.name, &details)
+            .await
+    }
+
+    pub async fn update_apptype(&self, input: &ApptypeUpdate) -> bool {
+        let mut updates: Vec<String> = Vec::new();
+        if let Some(name) = &input.name {
+            updates.push(format!("name = '{}'", escape(name)));
+        }
+        if let Some(speciality_id) = input.speciality_id {
+            updates.push(format!("speciality_id = {}", speciality_id));
+        }
+        if let Some(description) = &input.description {
+            updates.push(format!("description = '{}'", escape(description)));
+        }
+        if updates.is_empty() {
+            return false;
+        }
+        let details = updates.join(", ");
+        let query = format!("update appointment_types set {} where id = {};", details, input.id);
+        self.execute_audited(&query, input.admin_id, "apptype.update", &format!("apptype {}", input.id), &details)
+            .await
+    }
+
+    //fails while doctors have prices for it or it has been booked
+    pub async fn delete_apptype(&self, admin_id: i64, id: i64) -> bool {
+        let query = format!("delete from appointment_types where id = {};", id);
+        self.execute_audited(&query, admin_id, "apptype.delete", &format!("apptype {}", id), "")
+            .await
+    }
+
+    pub async fn view_users(&self, role: Option<&str>) -> Vec<Users> {
+        let filter = match role {
+            Some(role) => format!("where role = '{}'", escape(role)),
+            None => String::new(),
+        };
+        let query = format!("select id, email, phone, role, disabled, totp_enabled, totp_required from login {} order by id;", filter);
+        self.get_query_result::<Users, Postgres>(&query)
+            .await
+    }
+
+    pub async fn set_user_disabled(&self, admin_id: i64, email: &str, disabled: bool) -> bool {
+        let query = format!("update login set disabled = {} where email = '{}';", disabled, escape(email));
+        let action = if disabled { "user.disable" } else { "user.enable" };
+        self.execute_audited(&query, admin_id, action, email, "")
+            .await
+    }
+
+    pub async fn reset_password(&self, admin_id: i64, email: &str, password: &str) -> bool {
+        let Ok((hash, salt)) = argon_hash_password::create_hash_and_salt(password) else {
+            tracing::error!("Hash and salt were not able to be created, password reset error");
+            return false;
+        };
+        //logs the user out everywhere too
+        let query = format!("
+                    with s as (
+                        update sessions set revoked_at = now() where revoked_at is null and login_id = (select id from login where email = '{2}')
+                    )
+                    update login set password = '{0}', salt = '{1}' where email = '{2}';
+                            ", hash, salt, escape(email));
+        self.execute_audited(&query, admin_id, "user.reset_password", email, "")
+            .await
+    }
+
+    //only doctors can be required to use 2FA, until they set it up they can't use anything else
+    pub async fn set_totp_required(&self, admin_id: i64, email: &str, required: bool) -> bool {
+        let query = format!("
+                    update login set totp_required = {} where email = '{}' and role = 'doctor';
+                            ", required, escape(email));
+        let action = if required { "user.require_2fa" } else { "user.unrequire_2fa" };
+        self.execute_audited(&query, admin_id, action, email, "")
+            .await
+    }
+
+    //for a user who lost both the authenticator and the recovery codes, they have to set it up again
+    pub async fn reset_totp(&self, admin_id: i64, email: &str) -> bool {
+        let query = format!("
+                    with r as (delete from recovery_codes where login_id = (select id from login where email = '{0}'))
+                    update login set totp_enabled = false, totp_secret = null, totp_last_step = null where email = '{0}';
+                            ", escape(email));
+        self.execute_audited(&query, admin_id, "user.reset_2fa", email, "")
+            .await
+    }
+
+    //anyone can be made an admin, other roles need the matching doctor/patient/receptionist record
+    pub async fn change_role(&self, admin_id: i64, email: &str, role: &str) -> bool {
+        let profile = match role {
+            "admin" => String::new(),
+            "doctor" | "patient" | "receptionist" => format!(
+                ", {0}_id = coalesce({0}_id, (select id from {0}s where email = '{1}'))",
+                role, escape(email)
+            ),
+            other => {
+                tracing::error!("No such role {}", other);
+                return false;
+            }
+        };
+        //chk_profile turns this down when there's no profile to link
+        let query = format!("
+                    update login set role = '{0}', isdoctor = {1} {2} where email = '{3}';
+                            ", role, role == "doctor", profile, escape(email));
+        self.execute_audited(&query, admin_id, "user.change_role", email, role)
+            .await
+    }
+
+    pub async fn view_all_appointments(&self, filter: &AppointmentFilter) -> Vec<AdminAppointments> {
+        let mut filters = String::new();
+        if let Some(doctor_id) = filter.doctor_id {
+            filters.push_str(&format!(" and a.doctor_id = {}", doctor_id));
+        }
+        if let Some(patient_id) = filter.patient_id {
+            filters.push_str(&format!(" and a.patient_id = {}", patient_id));
+        }
+        if let Some(date) = filter.date.as_deref().filter(|d| !d.is_empty()) {
+            filters.push_str(&format!(" and TO_CHAR(a.appointment_date, 'YYYY-MM-DD') = '{}'", escape(date)));
+        }
+        if let Some(status) = filter.status.as_deref().filter(|s| !s.is_empty()) {
+            filters.push_str(&format!(" and a.status = '{}'", escape(status)));
+        }
+        let query = format!("
+                    select a.id, a.doctor_id, d.name as docname, a.patient_id, p.name as patname, t.name as apptype,
+                    TO_CHAR(a.appointment_date, 'YYYY-MM-DD') as date, TO_CHAR(s.time_start::timestamp, 'HH24:MI:SS') as time_start,
+                    c.name as clinic, a.type as phyorvirt, a.status, {},
+                    TO_CHAR(a.checked_in_at, 'YYYY-MM-DD HH24:MI:SS') as checked_in_at
+                    from appointments a
+                    join doctors d on d.id = a.doctor_id
+                    join patients p on p.id = a.patient_id
+                    join appointment_types t on t.id = a.appointment_type
+                    join doctor_slots s on s.id = a.slot_id
+                    join clinics c on c.id = s.clinic_id
+                    where 1=1 {}
+                    order by a.appointment_date desc, s.time_start desc
+                    limit {};
+                            ", sealed_column("appointments", "a", "symptom"), filters, MAX_PAGE_SIZE);
+        self.get_query_result::<AdminAppointments, Postgres>(&query)
+            .await
+    }
+
+    pub async fn view_flagged_reviews(&self) -> Vec<FlaggedReviews> {
+        let query = String::from("
+                    select r.id, r.doctor_id, d.name as docname, r.rating, r.review, r.flag_reason, r.hidden,
+                    TO_CHAR(r.created_at, 'YYYY-MM-DD') as date
+                    from reviews r
+                    join doctors d on d.id = r.doctor_id
+                    where r.flagged
+                    order by r.created_at;
+                            ");
+        self.get_query_result::<FlaggedReviews, Postgres>(&query)
+            .await
+    }
+
+    //hidden reviews don't show up or count towards the rating, the flag is cleared either way
+    pub async fn set_review_hidden(&self, admin_id: i64, review_id: i64, hidden: bool) -> bool {
+        let query = format!("
+                    update reviews set hidden = {}, flagged = false where id = {};
+                            ", hidden, review_id);
+        let action = if hidden { "review.hide" } else { "review.unhide" };
+        self.execute_audited(&query, admin_id, action, &format!("review {}", review_id), "")
+            .await
+    }
+
+    pub async fn view_audit_log(&self, filter: &AuditFilter) -> Vec<AuditEntries> {
+        let mut filters: Vec<String> = Vec::new();
+        if let Some(patient_id) = filter.patient_id {
+            filters.push(format!("patient_id = {}", patient_id));
+        }
+        if let Some(actor_id) = filter.actor_id {
+            filters.push(format!("actor_id = {}", actor_id));
+        }
+        if let Some(actor_role) = &filter.actor_role {
+            filters.push(format!("actor_role = '{}'", escape(actor_role)));
+        }
+        if let Some(action) = &filter.action {
+            filters.push(format!("action = '{}'", escape(action)));
+        }
+        if let Some(request_id) = &filter.request_id {
+            filters.push(format!("request_id = '{}'", escape(request_id)));
+        }
+        if let Some(from) = &filter.from {
+            let Ok(from) = NaiveDate::parse_from_str(from, "%Y-%m-%d") else {
+                return Vec::new();
+            };
+            filters.push(format!("created_at >= '{}'", from));
+        }
+        if let Some(to) = &filter.to {
+            let Ok(to) = NaiveDate::parse_from_str(to, "%Y-%m-%d") else {
+                return Vec::new();
+            };
+            filters.push(format!("created_at < '{}'::date + 1", to));
+        }
+        let filters = if filters.is_empty() { String::new() } else { format!("where {}", filters.join(" and ")) };
+        let query = format!("
+                    select id, actor_id, actor_role, action, target, details, patient_id, resource_id, request_id,
+                    TO_CHAR(created_at, 'YYYY-MM-DD HH24:MI:SS') as created_at
+                    from audit_log
+                    {}
+                    order by id desc
+                    limit {};
+                            ", filters, filter.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE));
+        self.get_query_result::<AuditEntries, Postgres>(&query)
             .await
     }
 
-    pub async fn view_specialities(&self) -> Vec<Specialities> {
-        let query = String::from(
-            "select id, name, description as desc
-                    from specialities;",
-        );
-        self.get_query_result::<Specialities, Postgres>(&query)
-            .await
+    //one entry per patient, callers don't hand out what was read when this fails
+    pub async fn record_access(&self, access: &MedicalAccess<'_>, patient_ids: &[i64]) -> bool {
+        if patient_ids.is_empty() {
+            return true;
+        }
+        let resource_id = access.resource_id.map_or(String::from("null"), |id| id.to_string());
+        let rows: Vec<String> = patient_ids
+            .iter()
+            .map(|patient_id| format!(
+                "({}, '{}', '{}', '{}', {}, {}, nullif('{}', ''))",
+                access.actor_id, escape(access.actor_role), escape(access.action), patient_id, patient_id, resource_id, escape(access.request_id)
+            ))
+            .collect();
+        let query = format!("
+                    insert into audit_log(actor_id, actor_role, action, target, patient_id, resource_id, request_id) values {};
+                            ", rows.join(", "));
+        match sqlx::query(&query).execute(&self.connection).await {
+            Ok(_) => true,
+            Err(e) => {
+                tracing::error!("Error while recording {} of patient data: {}", access.action, e);
+                false
+            }
+        }
     }
 
-    pub async fn view_appointment_types(&self) -> Vec<Apptypes> {
-        let query = String::from(
-            "select id, name from appointment_types;"
-        );
-        self.get_query_result::<Apptypes, Postgres>(&query)
-            .await
+    pub async fn refill_patient(&self, refill_id: i64) -> Option<i64> {
+        let query = format!("select patient_id::bigint as patient_id from refill_requests where id = {};", refill_id);
+        match sqlx::query(&query).fetch_optional(&self.connection).await {
+            Ok(Some(row)) => row.try_get("patient_id").ok(),
+            Ok(None) => None,
+            Err(e) => {
+                tracing::error!("Error while retrieving patient of refill: {}", e);
+                None
+            }
+        }
     }
 
-    pub async fn view_cities(&self) -> Vec<Cities> {
-        let query = String::from(
-            "select distinct(city) as city from doctors"
-        );
-        self.get_query_result::<Cities, Postgres>(&query)
+    //who else looked at or changed the patient's records, their own accesses are left out
+    pub async fn view_record_accesses(&self, patient_id: i64, limit: Option<i64>) -> Vec<RecordAccesses> {
+        let query = format!("
+                    select a.actor_role, a.action, a.resource_id, TO_CHAR(a.created_at, 'YYYY-MM-DD HH24:MI:SS') as created_at,
+                    (case a.actor_role when 'doctor' then (select name from doctors where id = a.actor_id)
+                    when 'receptionist' then (select name from receptionists where id = a.actor_id) end) as actor_name
+                    from audit_log a
+                    where a.patient_id = {0} and not (a.actor_role = 'patient' and a.actor_id = {0})
+                    order by a.id desc
+                    limit {1};
+                            ", patient_id, limit.unwrap_or(MAX_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE));
+        self.get_query_result::<RecordAccesses, Postgres>(&query)
             .await
     }
 
-    pub async fn view_new_token(&self, doctor_id: i64, date: &String) -> TokenNumberPrimary {
-        let query = format!("select count(*) as num from tokens where doctor_id = {} and TO_CHAR(appointment_date, 'YYYY-MM-DD') = '{}'", doctor_id, date);
-        match sqlx::query_as::<_, TokenNumberPrimary>(&query)
-            .fetch_one(&self.connection)
-            .await {
-                Ok(mut tn) => {
-                    tn.num += 1;
-                    tn
-                }
-                Err(_) => {
-                    TokenNumberPrimary {
-                        num: 1
-                    }
-                }
+    //like get_query_result, but an error fails the whole thing instead of looking like there are no rows
+    async fn query_all<ResultStruct>(&self, query: &str) -> Option<Vec<ResultStruct>>
+    where
+        ResultStruct: for<'r> sqlx::FromRow<'r, PgRow> + Unpin + Send,
+    {
+        match sqlx::query_as::<_, ResultStruct>(query).fetch_all(&self.connection).await {
+            Ok(result) => Some(result),
+            Err(e) => {
+                tracing::error!("Error while running query: {}", e);
+                None
             }
+        }
     }
 
-    pub async fn view_current_token(&self,doctor_id: i64, date: &String) -> TokenNumberPrimary {
-        let query = format!("select token_number as num from tokens where doctor_id = {} and TO_CHAR(appointment_date, 'YYYY-MM-DD') = '{}' and status = 'ongoing'", doctor_id, date);
-        match sqlx::query_as::<_, TokenNumberPrimary>(&query)
-            .fetch_one(&self.connection)
-            .await {
-                Ok(tn) => tn,
-                Err(_) => {
-                    TokenNumberPrimary {
-                        num: 0
-                    }
-                }
-            }
+    //all of it or nothing, a partial export would look like a complete one
+    pub async fn export_patient(&self, patient_id: i64) -> Option<PatientExport> {
+        let query = format!("
+                    select p.name, p.email, p.phone, l.phone as login_phone, {}, {}, {}, {}
+                    from patients p
+                    left join login l on l.patient_id = p.id
+                    where p.id = {} and p.deleted_at is null;
+                            ",
+                    sealed_column("patients", "p", "gender"),
+                    sealed_column("patients", "p", "weight"),
+                    sealed_column("patients", "p", "age"),
+                    sealed_column("patients", "p", "blood_group"),
+                    patient_id);
+        let profile = self.query_all::<ExportProfile>(&query).await?.into_iter().next()?;
+        let query = format!("
+                    select a.id, d.name as docname, t.name as apptype, TO_CHAR(a.appointment_date, 'YYYY-MM-DD') as date,
+                    TO_CHAR(s.time_start::timestamp, 'HH24:MI:SS') as time_start, c.name as clinic, a.type as phyorvirt,
+                    a.status, {}, a.prescription_id, TO_CHAR(a.checked_in_at, 'YYYY-MM-DD HH24:MI:SS') as checked_in_at
+                    from appointments a
+                    join doctors d on d.id = a.doctor_id
+                    join appointment_types t on t.id = a.appointment_type
+                    join doctor_slots s on s.id = a.slot_id
+                    join clinics c on c.id = s.clinic_id
+                    where a.patient_id = {}
+                    order by a.appointment_date, s.time_start;
+                            ", sealed_column("appointments", "a", "symptom"), patient_id);
+        let appointments = self.query_all::<ExportAppointments>(&query).await?;
+        let query = format!("
+                    select k.id, d.name as docname, t.name as apptype, TO_CHAR(k.appointment_date, 'YYYY-MM-DD') as date,
+                    c.name as clinic, k.token_number, k.status, {}, k.prescription_id,
+                    TO_CHAR(k.checked_in_at, 'YYYY-MM-DD HH24:MI:SS') as checked_in_at
+                    from tokens k
+                    join doctors d on d.id = k.doctor_id
+                    join appointment_types t on t.id = k.appointment_type
+                    join clinics c on c.id = k.clinic_id
+                    where k.patient_id = {}
+                    order by k.appointment_date, k.token_number;
+                            ", sealed_column("tokens", "k", "symptom"), patient_id);
+        let tokens = self.query_all::<ExportTokens>(&query).await?;
+        let query = format!("
+                    select e.id, d.name as docname, t.name as apptype, TO_CHAR(e.appointment_date, 'YYYY-MM-DD') as date,
+                    e.emergency_no, {}, e.prescription_id
+                    from emergency_appointments e
+                    join doctors d on d.id = e.doctor_id
+                    join appointment_types t on t.id = e.appointment_type
+                    where e.patient_id = {}
+                    order by e.appointment_date, e.emergency_no;
+                            ", sealed_column("emergency_appointments", "e", "symptom"), patient_id);
+        let emergencies = self.query_all::<ExportEmergencies>(&query).await?;
+        let query = format!("
+                    select p.id, p.doctor_id, d.name as docname, TO_CHAR(p.appointment_date, 'YYYY-MM-DD') as date, {},
+                    array(select g.name::text from prescription_drugs pd join drugs g on g.id = pd.drug_id
+                        where pd.prescription_id = p.id order by g.name) as drugs,
+                    p.refills_allowed, p.parent_id
+                    from prescriptions p
+                    join doctors d on d.id = p.doctor_id
+                    where p.patient_id = {}
+                    order by p.appointment_date, p.id;
+                            ", sealed_column("prescriptions", "p", "prescription"), patient_id);
+        let prescriptions = self.query_all::<ExportPrescriptions>(&query).await?;
+        let query = format!("
+                    select r.id, r.prescription_id, d.name as docname, r.status, r.reason, r.new_prescription_id,
+                    TO_CHAR(r.requested_at, 'YYYY-MM-DD HH24:MI:SS') as requested_at
+                    from refill_requests r
+                    join doctors d on d.id = r.doctor_id
+                    where r.patient_id = {}
+                    order by r.requested_at;
+                            ", patient_id);
+        let refills = self.query_all::<PatientRefills>(&query).await?;
+        let query = format!("
+                    select id, allergen from patient_allergies where patient_id = {} order by allergen;
+                            ", patient_id);
+        let allergies = self.query_all::<Allergies>(&query).await?;
+        let query = format!("
+                    select r.id, d.name as docname, r.rating, r.review, r.reply, TO_CHAR(r.created_at, 'YYYY-MM-DD HH24:MI:SS') as created_at
+                    from reviews r
+                    join doctors d on d.id = r.doctor_id
+                    where r.patient_id = {}
+                    order by r.created_at;
+                            ", patient_id);
+        let reviews = self.query_all::<ExportReviews>(&query).await?;
+        let query = format!("
+                    select id, message, TO_CHAR(date_time, 'YYYY-MM-DD HH24:MI:SS') as date_time
+                    from notifications where patient_id = {} order by date_time;
+                            ", patient_id);
+        let notifications = self.query_all::<Notifications>(&query).await?;
+        Some(PatientExport {
+            exported_at: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            profile,
+            appointments,
+            tokens,
+            emergencies,
+            prescriptions,
+            refills,
+            allergies,
+            reviews,
+            notifications,
+        })
     }
 
-    pub async fn get_patient_token(&self, doctor_id: i64, patient_id: i64, date: &String) -> TokenNumber {
-        let query = format!("select token_number as num from tokens where doctor_id = {} and TO_CHAR(appointment_date, 'YYYY-MM-DD') = '{}' and patient_id = {}", doctor_id, date, patient_id);
-        match sqlx::query_as::<_, TokenNumber>(&query)
-            .fetch_one(&self.connection)
-            .await {
-                Ok(tn) => tn,
-                Err(_) => {
-                    TokenNumber {
-                        num: 0
-                    }
-                }
+    //closes the login and removes what identifies the patient; the records doctors have to keep (visits,
+    //prescriptions, refills and the audit log) stay, pointing at the anonymized patient
+    pub async fn delete_patient_account(&self, sid: i64, patient_id: i64, password: Option<&str>, code: Option<&str>) -> bool {
+        let query = format!("
+                    select l.id, l.password is not null as has_password, l.phone
+                    from login l join sessions s on s.login_id = l.id
+                    where s.id = {} and l.patient_id = {};
+                            ", sid, patient_id);
+        let row = match sqlx::query(&query).fetch_optional(&self.connection).await {
+            Ok(Some(row)) => row,
+            Ok(None) => {
+                tracing::error!("Session {} isn't for patient {}", sid, patient_id);
+                return false;
+            }
+            Err(e) => {
+                tracing::error!("Error while looking up login for deletion: {}", e);
+                return false;
+            }
+        };
+        let (Ok(login_id), Ok(has_password), Ok(phone)): (Result<i64, _>, Result<bool, _>, Result<Option<String>, _>) =
+            (row.try_get("id"), row.try_get("has_password"), row.try_get("phone"))
+        else {
+            tracing::error!("Error while retrieving login for deletion");
+            return false;
+        };
+        //phone only logins confirm with a texted code, there is no password to ask for
+        let confirmed = match (has_password, password, phone.as_deref(), code) {
+            (true, Some(password), _, _) => self.password_matches(sid, password).await,
+            (false, _, Some(phone), Some(code)) => self.use_phone_otp(phone, code).await,
+            _ => false,
+        };
+        if !confirmed {
+            tracing::error!("Password or code doesn't match, not deleting patient {}", patient_id);
+            return false;
+        }
+        let mut queries = vec![
+            //visits that haven't happened yet are given up, the doctor's slot frees up
+            format!("
+                    update appointments set status = 'cancelled' where patient_id = {} and status = 'scheduled' and appointment_date >= current_date;
+                            ", patient_id),
+            format!("
+                    update tokens set status = 'cancelled' where patient_id = {} and status = 'scheduled' and appointment_date >= current_date;
+                            ", patient_id),
+            //emergencies have no status to cancel them with, the ones still to come are taken off the doctor's list
+            format!("
+                    delete from emergency_appointments where patient_id = {} and appointment_date >= current_date and prescription_id is null;
+                            ", patient_id),
+            format!("
+                    update refill_requests set status = 'declined', reason = 'Account deleted', decided_at = now()
+                    where patient_id = {} and status = 'pending';
+                            ", patient_id),
+            format!("delete from notifications where patient_id = {};", patient_id),
+            format!("delete from patient_allergies where patient_id = {};", patient_id),
+            //ratings still count towards the doctor, what the patient wrote goes
+            format!("update reviews set review = null where patient_id = {};", patient_id),
+            format!("delete from sessions where login_id = {};", login_id),
+            format!("delete from auth_tokens where login_id = {};", login_id),
+            format!("delete from recovery_codes where login_id = {};", login_id),
+            format!("delete from login where id = {};", login_id),
+            format!("
+                    update patients set name = '{}', email = null, phone = '', gender = null, weight = null, age = null,
+                    blood_group = null, deleted_at = now() where id = {};
+                            ", DELETED_PATIENT_NAME, patient_id),
+        ];
+        if let Some(phone) = &phone {
+            queries.push(format!("delete from phone_otps where phone = '{}';", escape(phone)));
+        }
+        let Ok(mut tx) = self.connection.begin().await else {
+            tracing::error!("Couldn't start transaction for account deletion");
+            return false;
+        };
+        for query in queries {
+            if let Err(e) = sqlx::query(&query).execute(&mut tx).await {
+                tracing::error!("Error while deleting account of patient {}: {}", patient_id, e);
+                return false;
+            }
+        }
+        match tx.commit().await {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::error!("Error while committing account deletion: {}", e);
+                false
             }
+        }
     }
 
+    //a rejected doctor uploading a new document goes back in the queue for verification
+    pub async fn add_doctor_document(&self, input: &DoctorDocumentUpload) -> bool {
+        if !DOCUMENT_TYPES.contains(&input.content_type.as_str()) {
+            tracing::error!("Document type {} isn't allowed", input.content_type);
+            return false;
+        }
+        let content: String = input.content.chars().filter(|c| !c.is_whitespace()).collect();
+        if content.is_empty()
+            || content.len() / 4 * 3 > MAX_DOCUMENT_BYTES
+            || !content.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/' || c == '=')
+        {
+            tracing::error!("Document content is empty, too large or not base64");
+            return false;
+        }
+        let Ok(mut tx) = self.connection.begin().await else {
+            tracing::error!("Couldn't start transaction for document upload");
+            return false;
+        };
+        let query = format!("
+                    insert into doctor_documents(doctor_id, kind, filename, content_type, content)
+                    values ({}, '{}', '{}', '{}', decode('{}', 'base64'));
+                            ", input.doctor_id, escape(&input.kind), escape(&input.filename), input.content_type, content);
+        if let Err(e) = sqlx::query(&query).execute(&mut tx).await {
+            tracing::error!("Error while inserting document: {}", e);
+            return false;
+        }
+        let query = format!("
+                    update doctors set verification_status = 'pending_verification' where id = {} and verification_status = 'rejected';
+                            ", input.doctor_id);
+        if let Err(e) = sqlx::query(&query).execute(&mut tx).await {
+            tracing::error!("Error while resubmitting doctor for verification: {}", e);
+            return false;
+        }
+        tx.commit().await.is_ok()
+    }
 
-    pub async fn view_doctor_appointments(&self, doctor_id: i64) -> Vec<DoctorAppointments> {
-        let query = format!(
-            "
-            select id, patient_id, appointment_type as apptype,
-            TO_CHAR(appointment_date, 'YYYY-MM-DD') as date,
-            type as phyorvirt, status, slot_id, symptom from appointments where doctor_id = {} order by date
-            ", doctor_id
-        );
-        self.get_query_result::<DoctorAppointments, Postgres>(&query)
+    pub async fn view_doctor_documents(&self, doctor_id: i64) -> Vec<DoctorDocuments> {
+        let query = format!("
+                    select id, kind, filename, content_type, octet_length(content) as size,
+                    TO_CHAR(uploaded_at, 'YYYY-MM-DD HH24:MI:SS') as uploaded_at
+                    from doctor_documents where doctor_id = {} order by id;
+                            ", doctor_id);
+        self.get_query_result::<DoctorDocuments, Postgres>(&query)
             .await
     }
 
-    pub async fn view_doctor_emergencies(&self, doctor_id: i64, date: &String) -> Vec<EmergencyAppointments> {
-        let query = format!(
-            "
-            select emergency_no as id, patient_id, appointment_type as apptype,
-            symptom from emergency_appointments where doctor_id = {}
-            and TO_CHAR(appointment_date, 'YYYY-MM-DD') = '{}'
-            order by appointment_date
-            ", doctor_id, date
-        );
-        self.get_query_result::<EmergencyAppointments, Postgres>(&query)
+    pub async fn view_document_file(&self, document_id: i64) -> Option<DocumentFile> {
+        let query = format!("
+                    select filename, content_type, content from doctor_documents where id = {};
+                            ", document_id);
+        match sqlx::query_as::<_, DocumentFile>(&query).fetch_optional(&self.connection).await {
+            Ok(file) => file,
+            Err(e) => {
+                tracing::error!("Error while fetching document: {}", e);
+                None
+            }
+        }
+    }
+
+    pub async fn view_verification_status(&self, doctor_id: i64) -> Option<VerificationStatus> {
+        let query = format!("
+                    select verification_status as status, verification_notes as notes,
+                    TO_CHAR(verified_at, 'YYYY-MM-DD HH24:MI:SS') as verified_at
+                    from doctors where id = {};
+                            ", doctor_id);
+        match sqlx::query_as::<_, VerificationStatus>(&query).fetch_optional(&self.connection).await {
+            Ok(status) => status,
+            Err(e) => {
+                tracing::error!("Error while fetching verification status: {}", e);
+                None
+            }
+        }
+    }
+
+    //oldest signups first
+    pub async fn view_pending_doctors(&self) -> Vec<PendingDoctors> {
+        let query = String::from("
+                    select d.id, d.name, d.email, d.phone, d.registration_number, d.registration_council,
+                    (select count(*) from doctor_documents x where x.doctor_id = d.id) as documents
+                    from doctors d
+                    where d.verification_status = 'pending_verification'
+                    order by d.id;
+                            ");
+        self.get_query_result::<PendingDoctors, Postgres>(&query)
             .await
     }
 
-    pub async fn register(&self, email: &String, password: &String, isdoctor: bool) -> bool {
-        let Ok((hash, salt)) = argon_hash_password::create_hash_and_salt(&password) else {
-            tracing::error!("Hash and salt were not able to be created, registration error");
-            return false;
+    //only doctors that have uploaded a document can be approved
+    pub async fn set_doctor_verification(&self, input: &VerificationDecision) -> bool {
+        let notes = input.notes.as_deref().unwrap_or("");
+        let (status, verified_at, check) = match input.approved {
+            true => (
+                "verified",
+                "now()",
+                format!("and exists (select 1 from doctor_documents x where x.doctor_id = {})", input.doctor_id),
+            ),
+            false => ("rejected", "null", String::new()),
         };
-        let query = format!(
-            "
-                    insert into login(email, password, salt, isdoctor) values ('{}', '{}', '{}', {})
-                            ",
-            email, hash, salt, isdoctor
-        );
-        match sqlx::query(&query).execute(&self.connection).await {
-            Ok(_) => return true,
-            Err(_) => return false,
-        }
+        let query = format!("
+                    update doctors set verification_status = '{}', verification_notes = nullif('{}', ''), verified_at = {}
+                    where id = {} {};
+                            ", status, escape(notes), verified_at, input.doctor_id, check);
+        let action = format!("doctor.{}", if input.approved { "verify" } else { "reject" });
+        self.execute_audited(&query, input.admin_id, &action, &format!("doctor {}", input.doctor_id), notes)
+            .await
     }
 
-    pub async fn add_new_patient(&self, name: &String, email: &String, phone: &String) -> bool {
+    pub async fn add_new_patient(&self, name: &str, email: &str, phone: &str, password: &str) -> Result<(), SignupError> {
         let query = format!(
             "
-                    insert into patients(name, email, phone) values ('{}','{}','{}');
+                    insert into patients(name, email, phone) values ('{}','{}','{}') returning id;
                             ",
-            name, email, phone
+            escape(name), escape(email), escape(phone)
         );
-        match sqlx::query(&query).execute(&self.connection).await {
-            Ok(_) => return true,
-            Err(_) => return false,
-        }
+        self.signup(&query, email, password, "patient")
+            .await
     }
 
-    pub async fn update_patient(&self, patient_id: i64, gender: &String, weight: i32, age: i32, blood_group: &String) -> bool {
+    pub async fn update_patient(&self, patient_id: i64, gender: &str, weight: i32, age: i32, blood_group: &str) -> bool {
+        let (Some(weight), Some(age), Some(blood_group), Some(gender)) = (
+            sealed(&weight.to_string(), "patients", "weight", patient_id),
+            sealed(&age.to_string(), "patients", "age", patient_id),
+            sealed(blood_group, "patients", "blood_group", patient_id),
+            sealed(gender, "patients", "gender", patient_id),
+        ) else {
+            return false;
+        };
         let query = format!(
             "
-                    update patients set weight = {}, age = {}, blood_group = '{}', gender = '{}' where id = {};
+                    update patients set weight = {}, age = {}, blood_group = {}, gender = {} where id = {};
                             ",
             weight, age, blood_group, gender, patient_id
         );
@@ -435,38 +3308,32 @@ impl Database {
         }
     }
 
-    pub async fn add_new_doctor(
-        &self,
-        name: &String,
-        speciality: i64,
-        city: &String,
-        address: &String,
-        email: &String,
-        phone: &String,
-    ) -> bool {
+    pub async fn add_new_doctor(&self, doctor: &Doctor) -> Result<(), SignupError> {
         let query = format!("
-                    insert into doctors(name, speciality_id, city, address, email, phone) values ('{}',{},'{}', '{}', '{}', '{}');
-                            ", name, speciality, city, address, email, phone);
-        match sqlx::query(&query).execute(&self.connection).await {
-            Ok(_) => return true,
-            Err(_) => return false,
-        }
+                    with d as (
+                        insert into doctors(name, city, address, email, phone) values ('{0}','{1}', '{2}', '{3}', '{4}') returning id
+                    ), s as (
+                        insert into doctor_specialities(doctor_id, speciality_id) select id, {5} from d
+                    ), c as (
+                        insert into clinics(name, address, city, phone, owner_id) select '{0}', '{2}', '{1}', '{4}', id from d returning id
+                    )
+                    insert into doctor_clinics(doctor_id, clinic_id) select d.id, c.id from d, c
+                    returning doctor_id::bigint as id;
+                            ", escape(&doctor.name), escape(&doctor.city), escape(&doctor.address), escape(&doctor.email), escape(&doctor.phone), doctor.speciality);
+        self.signup(&query, &doctor.email, &doctor.password, "doctor")
+            .await
     }
 
-    pub async fn add_new_appointment(
-        &self,
-        docid: i64,
-        patid: i64,
-        apptype: i64,
-        date: &String,
-        slot_id: i64,
-        phyorvirt: &String,
-        symptom: &String,
-    ) -> bool {
+    pub async fn add_new_appointment(&self, appointment: &Appointment) -> bool {
+        let Appointment { doctor_id: docid, patient_id: patid, apptype, slot_id, ref date, ref phyorvirt, ref symptom } = *appointment;
         let Ok(naivedate) = NaiveDate::parse_from_str(date, "%Y-%m-%d") else {
             tracing::error!("Couldn't parse date into NaiveDateTime");
             return false;
         };
+        if !self.doctor_verified(docid).await {
+            tracing::error!("Doctor {} isn't verified, can't be booked", docid);
+            return false;
+        }
         //check if no appointment has been booked at same time
         let doctorapps = self.view_doctor_appointments(docid).await;
         for app in doctorapps.iter() {
@@ -479,9 +3346,15 @@ impl Database {
                 return false;
             }
         }
+        let Some(id) = self.next_id("appointments").await else {
+            return false;
+        };
+        let Some(symptom) = sealed(symptom, "appointments", "symptom", id) else {
+            return false;
+        };
         let query = format!("
-                    INSERT INTO Appointments (doctor_id, patient_id, appointment_type, appointment_date, slot_id, type, status, symptom) VALUES ({}, {}, {}, '{}', {}, '{}', 'scheduled', '{}')
-                            ", docid, patid, apptype, naivedate, slot_id, phyorvirt, symptom);
+                    INSERT INTO Appointments (id, doctor_id, patient_id, appointment_type, appointment_date, slot_id, type, status, symptom) VALUES ({}, {}, {}, {}, '{}', {}, '{}', 'scheduled', {})
+                            ", id, docid, patid, apptype, naivedate, slot_id, escape(phyorvirt), symptom);
         match sqlx::query(&query).execute(&self.connection).await {
             Ok(_) => return true,
             Err(_) => return false,
@@ -491,15 +3364,20 @@ impl Database {
     pub async fn add_new_token(
         &self,
         docid: i64,
+        clinic_id: i64,
         patid: i64,
         apptype: i64,
         date: &String,
-        symptom: &String,
+        symptom: &str,
     ) -> bool {
         let Ok(naivedate) = NaiveDate::parse_from_str(date, "%Y-%m-%d") else {
             tracing::error!("Couldn't parse date into NaiveDateTime");
             return false;
         };
+        if !self.doctor_verified(docid).await {
+            tracing::error!("Doctor {} isn't verified, can't be booked", docid);
+            return false;
+        }
         let checkquery = format!("select doctor_id, patient_id, appointment_date, appointment_type from tokens where doctor_id = {} and patient_id = {} and TO_CHAR(appointment_date, 'YYYY-MM-DD') = '{}' and appointment_type = {}", docid, patid, date, apptype);
         match sqlx::query(&checkquery).fetch_one(&self.connection).await {
             Ok(_) => {
@@ -510,18 +3388,27 @@ impl Database {
                 tracing::debug!("An error occurred, attempting to proceed");
             }
         }
-        let token_number = self.view_new_token(docid, date).await.num;
+        let Some(id) = self.next_id("tokens").await else {
+            return false;
+        };
+        let Some(symptom) = sealed(symptom, "tokens", "symptom", id) else {
+            return false;
+        };
+        let token_number = self.view_new_token(docid, clinic_id, date).await.num;
+        //only at a clinic the doctor practises at
         let query = format!("
-                    INSERT INTO Tokens (doctor_id, patient_id, appointment_type, appointment_date, token_number, status, symptom) VALUES ({}, {}, {}, '{}', {}, 'scheduled', '{}')
-                            ", docid, patid, apptype, naivedate, token_number, symptom);
+                    INSERT INTO Tokens (id, doctor_id, clinic_id, patient_id, appointment_type, appointment_date, token_number, status, symptom)
+                    SELECT {}, {}, {}, {}, {}, '{}', {}, 'scheduled', {} FROM Doctor_Clinics WHERE doctor_id = {} AND clinic_id = {}
+                            ", id, docid, clinic_id, patid, apptype, naivedate, token_number, symptom, docid, clinic_id);
         match sqlx::query(&query).execute(&self.connection).await {
-            Ok(_) => return true,
+            Ok(res) => return res.rows_affected() == 1,
             Err(_) => return false,
         }
     }
 
-    pub async fn view_new_emergency_no(&self, doctor_id: i64, date: &String) -> TokenNumberPrimary {
-        let query = format!("select count(*) as num from emergency_appointments where doctor_id = {} and TO_CHAR(appointment_date, 'YYYY-MM-DD') = '{}'", doctor_id, date);
+    pub async fn view_new_emergency_no(&self, doctor_id: i64, date: &str) -> TokenNumberPrimary {
+        //the highest number rather than a count, emergencies of deleted accounts leave gaps
+        let query = format!("select coalesce(max(emergency_no), 0)::bigint as num from emergency_appointments where doctor_id = {} and TO_CHAR(appointment_date, 'YYYY-MM-DD') = '{}'", doctor_id, escape(date));
         match sqlx::query_as::<_, TokenNumberPrimary>(&query)
             .fetch_one(&self.connection)
             .await {
@@ -544,12 +3431,16 @@ impl Database {
         patid: i64,
         apptype: i64,
         date: &String,
-        symptom: &String,
+        symptom: &str,
     ) -> bool {
         let Ok(naivedate) = NaiveDate::parse_from_str(date, "%Y-%m-%d") else {
             tracing::error!("Couldn't parse date into NaiveDateTime");
             return false;
         };
+        if !self.doctor_verified(docid).await {
+            tracing::error!("Doctor {} isn't verified, can't be booked", docid);
+            return false;
+        }
         let checkquery = format!("select doctor_id, patient_id, appointment_date, appointment_type from emergency_appointments where doctor_id = {} and patient_id = {} and TO_CHAR(appointment_date, 'YYYY-MM-DD') = '{}' and appointment_type = {}", docid, patid, date, apptype);
         match sqlx::query(&checkquery).fetch_one(&self.connection).await {
             Ok(_) => {
@@ -560,10 +3451,16 @@ impl Database {
                 tracing::debug!("An error occurred, attempting to proceed");
             }
         }
+        let Some(id) = self.next_id("emergency_appointments").await else {
+            return false;
+        };
+        let Some(symptom) = sealed(symptom, "emergency_appointments", "symptom", id) else {
+            return false;
+        };
         let emergency_no = self.view_new_emergency_no(docid, date).await.num;
         let query = format!("
-                    INSERT INTO emergency_appointments (doctor_id, patient_id, appointment_type, appointment_date, emergency_no, symptom) VALUES ({}, {}, {}, '{}', {}, '{}')
-                            ", docid, patid, apptype, naivedate, emergency_no, symptom);
+                    INSERT INTO emergency_appointments (id, doctor_id, patient_id, appointment_type, appointment_date, emergency_no, symptom) VALUES ({}, {}, {}, {}, '{}', {}, {})
+                            ", id, docid, patid, apptype, naivedate, emergency_no, symptom);
         match sqlx::query(&query).execute(&self.connection).await {
             Ok(_) => return true,
             Err(_) => return false,
@@ -580,4 +3477,226 @@ impl Database {
         }
     }
 
+    //seals the values of one of crypto::SEALED_COLUMNS that are still in plaintext, not bound to their row or
+    //sealed with an older master key, returns how many were changed; a value changed in the meantime is left for the next run
+    pub async fn reseal_column(&self, table: &str, column: &str) -> Option<u64> {
+        let mut last_id: i64 = 0;
+        let mut changed = 0;
+        loop {
+            let query = format!("
+                    select id, {column} as value from {table} where id > {last_id} and {column} is not null order by id limit {RESEAL_BATCH};
+                            ");
+            let rows = match sqlx::query(&query).fetch_all(&self.connection).await {
+                Ok(rows) => rows,
+                Err(e) => {
+                    tracing::error!("Error while reading {}.{}: {}", table, column, e);
+                    return None;
+                }
+            };
+            if rows.is_empty() {
+                return Some(changed);
+            }
+            for row in rows {
+                let (Ok(id), Ok(value)) = (row.try_get::<i64, _>("id"), row.try_get::<String, _>("value")) else {
+                    tracing::error!("Couldn't read {}.{}, has the schema been updated?", table, column);
+                    return None;
+                };
+                last_id = id;
+                let resealed = match crypto::reseal(&value, &crypto::location(table, column, id)) {
+                    Ok(Some(resealed)) => resealed,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        tracing::error!("Couldn't reseal {}.{} of {}: {}", table, column, id, e);
+                        return None;
+                    }
+                };
+                //bound instead of formatted in so the plaintext doesn't end up in the query log
+                let query = format!("
+                    update {table} set {column} = $1 where id = $2 and {column} = $3;
+                            ");
+                match sqlx::query(&query)
+                    .bind(&resealed)
+                    .bind(id)
+                    .bind(&value)
+                    .execute(&self.connection)
+                    .await
+                {
+                    Ok(res) => changed += res.rows_affected(),
+                    Err(e) => {
+                        tracing::error!("Error while resealing {}.{} of {}: {}", table, column, id, e);
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUOTED: &str = "x' or '1'='1";
+
+    #[test]
+    fn escape_doubles_quotes() {
+        assert_eq!(escape(QUOTED), "x'' or ''1''=''1");
+        assert_eq!(escape("no quotes"), "no quotes");
+    }
+
+    #[test]
+    fn doctor_profile_reverifies_changed_registration() {
+        let input = DoctorProfileInput {
+            doctor_id: 2,
+            bio: Some(QUOTED.to_string()),
+            ..Default::default()
+        };
+        let query = Database::doctor_profile_sql(&input).unwrap();
+        assert!(query.contains("bio = 'x'' or ''1''=''1' where id = 2;"));
+        assert!(!query.contains("verification_status"));
+        let input = DoctorProfileInput {
+            doctor_id: 2,
+            registration_number: Some(String::from("MH-1'2")),
+            ..Default::default()
+        };
+        let query = Database::doctor_profile_sql(&input).unwrap();
+        assert!(query.contains("is distinct from ('MH-1''2', registration_council) then 'pending_verification'"));
+        assert!(query.contains("verified_at = case when"));
+        assert!(Database::doctor_profile_sql(&DoctorProfileInput::default()).is_none());
+    }
+
+    #[test]
+    fn highlight_escapes_doctor_text() {
+        assert_eq!(
+            highlight_html("\u{2}Dr\u{3} <script>alert('x')</script> & co"),
+            "<b>Dr</b> &lt;script&gt;alert(&#39;x&#39;)&lt;/script&gt; &amp; co"
+        );
+    }
+
+    #[test]
+    fn clinic_update_escapes_and_checks_owner() {
+        let changes = ClinicChanges {
+            clinic_id: 3,
+            name: Some(QUOTED.to_string()),
+            address: None,
+            city: None,
+            latitude: Some(19.07),
+            longitude: None,
+            hours: None,
+            phone: None,
+        };
+        let query = Database::clinic_update_sql(&changes, Some(4)).unwrap();
+        assert!(query.contains("set name = 'x'' or ''1''=''1', latitude = 19.07 where id = 3 and owner_id = 4;"));
+        let query = Database::clinic_update_sql(&changes, None).unwrap();
+        assert!(query.contains("where id = 3;"));
+        let nothing = ClinicChanges { name: None, latitude: None, ..changes };
+        assert!(Database::clinic_update_sql(&nothing, Some(4)).is_none());
+    }
+
+    #[test]
+    fn clinic_decision_checks_owner() {
+        let query = Database::clinic_decision_sql(3, 7, true, Some(4));
+        assert!(query.contains("delete from clinic_requests where doctor_id = 7 and clinic_id = 3 and clinic_id in (select id from clinics where owner_id = 4) returning"));
+        assert!(query.contains("insert into doctor_clinics"));
+        let query = Database::clinic_decision_sql(3, 7, false, None);
+        assert_eq!(query, "delete from clinic_requests where doctor_id = 7 and clinic_id = 3;");
+    }
+
+    #[test]
+    fn nearby_doctors_escapes_apptype() {
+        let filter = NearbyFilter {
+            lat: 19.07,
+            lng: 72.87,
+            radius: None,
+            apptype: Some(QUOTED.to_string()),
+            speciality: None,
+            min_price: None,
+            max_price: None,
+        };
+        let query = Database::nearby_doctors_sql(&filter);
+        assert!(query.contains("and t.name = 'x'' or ''1''=''1'"));
+        assert!(!query.contains(QUOTED));
+    }
+
+    #[test]
+    fn add_review_escapes_text() {
+        let review = NewReview {
+            patient_id: 5,
+            source: String::from("appointment"),
+            source_id: 3,
+            rating: 4,
+            review: QUOTED.to_string(),
+        };
+        let query = Database::add_review_sql(&review).unwrap();
+        assert!(query.contains("select doctor_id, patient_id, id, 4, 'x'' or ''1''=''1' from appointments"));
+        assert!(!query.contains(QUOTED));
+        let review = NewReview { source: String::from("reviews"), ..review };
+        assert!(Database::add_review_sql(&review).is_none());
+    }
+
+    #[test]
+    fn request_refill_escapes_note() {
+        let query = Database::request_refill_sql(5, 7, QUOTED);
+        assert!(query.contains("'pending', 'x'' or ''1''=''1' from prescriptions"));
+        assert!(!query.contains(QUOTED));
+    }
+
+    #[test]
+    fn search_drugs_escapes_prefix() {
+        let query = Database::search_drugs_sql(QUOTED);
+        assert!(query.contains("name ilike 'x'' or ''1''=''1%'"));
+        assert!(!query.contains(QUOTED));
+    }
+
+    //needs a Postgres server like tests/schema_migration.rs, skipped when TEST_DATABASE_URL isn't set
+    #[tokio::test]
+    async fn prescribed_appointment_can_be_reviewed_once() {
+        let Ok(url) = env::var("TEST_DATABASE_URL") else {
+            eprintln!("TEST_DATABASE_URL isn't set, skipping");
+            return;
+        };
+        let admin = PgPoolOptions::new().max_connections(1).connect(&url).await.unwrap();
+        sqlx::query("drop database if exists review_test with (force)").execute(&admin).await.unwrap();
+        sqlx::query("create database review_test").execute(&admin).await.unwrap();
+        let (base, params) = url.split_once('?').map_or((url.as_str(), String::new()), |(base, params)| (base, format!("?{}", params)));
+        let server = base.rsplit_once('/').map_or(base, |(server, _)| server);
+        let connection = PgPoolOptions::new().connect(&format!("{}/review_test{}", server, params)).await.unwrap();
+        sqlx::Executor::execute(&connection, include_str!("schema.sql")).await.unwrap();
+        sqlx::Executor::execute(&connection, include_str!("dummydata.sql")).await.unwrap();
+        let db = Database { jwt_secret: b"test".to_vec(), connection, require_email_verification: false };
+
+        let appointment = Appointment {
+            doctor_id: 1,
+            patient_id: 1,
+            apptype: 1,
+            slot_id: 1,
+            date: String::from("2099-01-05"),
+            phyorvirt: String::from("physical"),
+            symptom: String::from("fever"),
+        };
+        assert!(db.add_new_appointment(&appointment).await);
+        let id: i64 = sqlx::query("select max(id) as id from appointments where doctor_id = 1 and patient_id = 1")
+            .fetch_one(&db.connection)
+            .await
+            .unwrap()
+            .get("id");
+        let review = NewReview { patient_id: 1, source: String::from("appointment"), source_id: id, rating: 5, review: String::from("Good") };
+        assert!(!db.add_review(&review).await, "reviewed before the visit was closed");
+        let prescription = PrescriptionInfoInput {
+            patient_id: 1,
+            doctor_id: 1,
+            source: String::from("appointment"),
+            source_id: id,
+            prescription: String::from("rest"),
+            drugs: Vec::new(),
+            acknowledged: false,
+            refills: 0,
+        };
+        assert!(db.add_new_prescription(&prescription).await);
+        assert!(db.add_review(&review).await);
+        assert!(!db.add_review(&review).await, "reviewed twice");
+
+        db.connection.close().await;
+        sqlx::query("drop database review_test with (force)").execute(&admin).await.unwrap();
+    }
 }
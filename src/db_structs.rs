@@ -4,6 +4,8 @@ use sqlx::{FromRow, Postgres};
 use std::fmt::Display;
 use std::str::FromStr;
 
+use crate::crypto::Sealed;
+
 //inputs; input JSON -> serde -> these structs
 #[derive(Deserialize)]
 pub struct Login {
@@ -17,6 +19,12 @@ pub struct PatientID {
     pub patient_id: i64,
 }
 
+#[derive(Deserialize)]
+pub struct DoctorID {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+}
+
 #[derive(Deserialize)]
 pub struct Patient {
     pub name: String,
@@ -40,13 +48,149 @@ pub struct PatientInfoInput {
 #[derive(Deserialize)]
 pub struct PrescriptionInfoInput {
      #[serde(deserialize_with = "from_str")]
-    pub patient_id: i32,
+    pub patient_id: i64,
     #[serde(deserialize_with = "from_str")]
-    pub doctor_id: i32,
+    pub doctor_id: i64,
+    //one of appointment, token or emergency
+    pub source: String,
+    #[serde(deserialize_with = "from_str")]
+    pub source_id: i64,
     pub prescription: String,
-    pub date: String
+    //IDs from the drug catalog, checked for interactions and allergies
+    #[serde(default)]
+    pub drugs: Vec<i64>,
+    //has to be set to go ahead when the drug check gave warnings
+    #[serde(default)]
+    pub acknowledged: bool,
+    //how many times the patient may get this refilled without an appointment
+    #[serde(default, deserialize_with = "from_str")]
+    pub refills: i32,
 }
 
+#[derive(Deserialize)]
+pub struct RefillRequest {
+    #[serde(deserialize_with = "from_str")]
+    pub patient_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub prescription_id: i64,
+    #[serde(default)]
+    pub note: String,
+}
+
+#[derive(Deserialize)]
+pub struct RefillDecision {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub refill_id: i64,
+    //only used when declining
+    #[serde(default)]
+    pub reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct DrugCheck {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub patient_id: i64,
+    pub drugs: Vec<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct DrugSearch {
+    pub q: String,
+}
+
+#[derive(Deserialize)]
+pub struct PatientAllergen {
+    #[serde(deserialize_with = "from_str")]
+    pub patient_id: i64,
+    pub allergen: String,
+}
+
+#[derive(Deserialize)]
+pub struct AccessLogFilter {
+    #[serde(deserialize_with = "from_str")]
+    pub patient_id: i64,
+    #[serde(default, deserialize_with = "from_str_opt")]
+    pub limit: Option<i64>,
+}
+
+//the password, or for logins that only have a phone number a code texted through /phone/otp
+#[derive(Deserialize)]
+pub struct AccountDeletion {
+    #[serde(deserialize_with = "from_str")]
+    pub patient_id: i64,
+    pub password: Option<String>,
+    pub code: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct PatientAllergyID {
+    #[serde(deserialize_with = "from_str")]
+    pub patient_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub allergy_id: i64,
+}
+
+
+#[derive(Deserialize)]
+pub struct PrescriptionPatient {
+    #[serde(deserialize_with = "from_str")]
+    pub prescription_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub patient_id: i64,
+}
+
+#[derive(Deserialize)]
+pub struct PrescriptionCode {
+    #[serde(deserialize_with = "from_str")]
+    pub id: i64,
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct PatientPrescriptions {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub patient_id: i64,
+    //only prescriptions issued by this doctor
+    #[serde(default, deserialize_with = "from_str_opt")]
+    pub by_doctor: Option<i64>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct NewReview {
+    #[serde(deserialize_with = "from_str")]
+    pub patient_id: i64,
+    //appointment or token
+    pub source: String,
+    #[serde(deserialize_with = "from_str")]
+    pub source_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub rating: i32,
+    #[serde(default)]
+    pub review: String,
+}
+
+#[derive(Deserialize)]
+pub struct ReviewResponse {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub review_id: i64,
+    //the reply, or the reason when flagging
+    pub text: String,
+}
+
+#[derive(Deserialize)]
+pub struct DoctorIDQuery {
+    pub doctor_id: i64,
+}
 
 #[derive(Deserialize)]
 pub struct DoctorDate {
@@ -55,6 +199,15 @@ pub struct DoctorDate {
     pub date: String
 }
 
+#[derive(Deserialize)]
+pub struct DoctorClinicDate {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub clinic_id: i64,
+    pub date: String
+}
+
 #[derive(Deserialize)]
 pub struct DoctorPatientDate {
     #[serde(deserialize_with = "from_str")]
@@ -76,6 +229,47 @@ pub struct Doctor {
     pub password: String,
 }
 
+//fields left out are not changed
+#[derive(Deserialize, Default)]
+pub struct DoctorProfileInput {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    pub name: Option<String>,
+    pub city: Option<String>,
+    pub address: Option<String>,
+    pub phone: Option<String>,
+    pub gender: Option<String>,
+    pub qualifications: Option<String>,
+    pub registration_number: Option<String>,
+    pub registration_council: Option<String>,
+    #[serde(default, deserialize_with = "from_str_opt")]
+    pub years_of_experience: Option<i32>,
+    pub languages: Option<Vec<String>>,
+    pub bio: Option<String>,
+    //any of physical and virtual
+    pub consultation_modes: Option<Vec<String>>,
+    pub photo_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DoctorSpeciality {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub speciality_id: i64,
+}
+
+//price is only needed when setting one
+#[derive(Deserialize)]
+pub struct AppointmentPrice {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub apptype: i64,
+    #[serde(default, deserialize_with = "from_str_opt")]
+    pub price: Option<i32>,
+}
+
 #[derive(Deserialize)]
 pub struct City {
     pub city: String,
@@ -87,6 +281,139 @@ pub struct CityApptype {
     pub apptype: String,
 }
 
+#[derive(Deserialize)]
+pub struct FindFilter {
+    #[serde(default)]
+    pub city: String,
+    #[serde(default)]
+    pub apptype: String,
+    pub speciality: Option<i64>,
+    pub min_price: Option<i32>,
+    pub max_price: Option<i32>,
+    //physical or virtual
+    pub mode: Option<String>,
+    pub language: Option<String>,
+    pub gender: Option<String>,
+    //only doctors taking emergencies
+    #[serde(default)]
+    pub emergency: bool,
+    //price (default), distance (needs lat and lng), next_available or rating
+    pub sort: Option<String>,
+    pub lat: Option<f64>,
+    pub lng: Option<f64>,
+    //next_cursor of the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct NextAvailableFilter {
+    #[serde(default)]
+    pub city: String,
+    pub speciality: Option<i64>,
+    //appointment type ID, only doctors pricing it are returned
+    pub apptype: Option<i64>,
+    //YYYY-MM-DD, defaults to today and two weeks from it
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct NearbyFilter {
+    pub lat: f64,
+    pub lng: f64,
+    //in km
+    pub radius: Option<f64>,
+    pub apptype: Option<String>,
+    pub speciality: Option<i64>,
+    pub min_price: Option<i32>,
+    pub max_price: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct NewClinic {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    pub name: String,
+    pub address: String,
+    pub city: String,
+    #[serde(default, deserialize_with = "from_str_opt")]
+    pub latitude: Option<f64>,
+    #[serde(default, deserialize_with = "from_str_opt")]
+    pub longitude: Option<f64>,
+    pub hours: Option<String>,
+    pub phone: Option<String>,
+}
+
+//fields left out are not changed
+#[derive(Deserialize)]
+pub struct ClinicChanges {
+    #[serde(deserialize_with = "from_str")]
+    pub clinic_id: i64,
+    pub name: Option<String>,
+    pub address: Option<String>,
+    pub city: Option<String>,
+    #[serde(default, deserialize_with = "from_str_opt")]
+    pub latitude: Option<f64>,
+    #[serde(default, deserialize_with = "from_str_opt")]
+    pub longitude: Option<f64>,
+    pub hours: Option<String>,
+    pub phone: Option<String>,
+}
+
+//doctor_id has to own the clinic
+#[derive(Deserialize)]
+pub struct ClinicUpdate {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    #[serde(flatten)]
+    pub changes: ClinicChanges,
+}
+
+#[derive(Deserialize)]
+pub struct AdminClinicUpdate {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    #[serde(flatten)]
+    pub changes: ClinicChanges,
+}
+
+#[derive(Deserialize)]
+pub struct DoctorClinic {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub clinic_id: i64,
+}
+
+//the clinic's owner letting requester_id practise there or not
+#[derive(Deserialize)]
+pub struct ClinicRequestDecision {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub clinic_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub requester_id: i64,
+    pub approved: bool,
+}
+
+#[derive(Deserialize)]
+pub struct AdminClinicRequestDecision {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub clinic_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub requester_id: i64,
+    pub approved: bool,
+}
+
+#[derive(Deserialize)]
+pub struct SearchText {
+    pub q: String,
+}
+
 #[derive(Deserialize)]
 pub struct Appointment {
     #[serde(deserialize_with = "from_str")]
@@ -103,25 +430,348 @@ pub struct Appointment {
 }
 
 #[derive(Deserialize)]
-pub struct Token {
-    #[serde(deserialize_with = "from_str")]
-    pub doctor_id: i64,
-    #[serde(deserialize_with = "from_str")]
-    pub patient_id: i64,
-    #[serde(deserialize_with = "from_str")]
-    pub apptype: i64,
-    #[serde(deserialize_with = "from_str")]
-    pub date: String,
-    pub symptom: String,
+pub struct Token {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub clinic_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub patient_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub apptype: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub date: String,
+    pub symptom: String,
+}
+
+#[derive(Deserialize)]
+pub struct Emergency {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub patient_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub apptype: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub date: String,
+    pub symptom: String,
+}
+
+#[derive(Deserialize)]
+pub struct CancelAppointment {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub patient_id: i64,
+    pub date: String,
+}
+
+#[derive(Deserialize)]
+pub struct NewReceptionist {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub clinic_id: i64,
+    pub name: String,
+    pub email: String,
+    pub phone: String,
+    pub password: String,
+}
+
+//just enough to issue a token, the rest can be filled in later
+#[derive(Deserialize)]
+pub struct WalkInPatient {
+    #[serde(deserialize_with = "from_str")]
+    pub receptionist_id: i64,
+    pub name: String,
+    pub phone: String,
+    pub gender: Option<String>,
+    #[serde(default, deserialize_with = "from_str_opt")]
+    pub age: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct ReceptionistPhone {
+    #[serde(deserialize_with = "from_str")]
+    pub receptionist_id: i64,
+    pub phone: String,
+}
+
+#[derive(Deserialize)]
+pub struct ReceptionBooking {
+    #[serde(deserialize_with = "from_str")]
+    pub receptionist_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub patient_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub apptype: i64,
+    pub date: String,
+    pub symptom: String,
+}
+
+#[derive(Deserialize)]
+pub struct CheckIn {
+    #[serde(deserialize_with = "from_str")]
+    pub receptionist_id: i64,
+    //appointment or token
+    pub source: String,
+    #[serde(deserialize_with = "from_str")]
+    pub source_id: i64,
+}
+
+#[derive(Deserialize)]
+pub struct ReceptionistDoctorDate {
+    #[serde(deserialize_with = "from_str")]
+    pub receptionist_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    pub date: String,
+}
+
+#[derive(Deserialize)]
+pub struct AdminID {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+}
+
+//a speciality or appointment type to delete
+#[derive(Deserialize)]
+pub struct AdminItem {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub id: i64,
+}
+
+#[derive(Deserialize)]
+pub struct NewSpeciality {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+//fields left out are not changed
+#[derive(Deserialize)]
+pub struct SpecialityUpdate {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub id: i64,
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct NewApptype {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    pub name: String,
+    #[serde(deserialize_with = "from_str")]
+    pub speciality_id: i64,
+    pub description: Option<String>,
+}
+
+//fields left out are not changed
+#[derive(Deserialize)]
+pub struct ApptypeUpdate {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub id: i64,
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "from_str_opt")]
+    pub speciality_id: Option<i64>,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UserFilter {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    pub role: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UserStatus {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    pub email: String,
+    pub disabled: bool,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordReset {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorRequirement {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    pub email: String,
+    pub required: bool,
+}
+
+#[derive(Deserialize)]
+pub struct AdminEmail {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct RoleChange {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    pub email: String,
+    pub role: String,
+}
+
+#[derive(Deserialize)]
+pub struct AppointmentFilter {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    #[serde(default, deserialize_with = "from_str_opt")]
+    pub doctor_id: Option<i64>,
+    #[serde(default, deserialize_with = "from_str_opt")]
+    pub patient_id: Option<i64>,
+    //YYYY-MM-DD
+    pub date: Option<String>,
+    pub status: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ReviewVisibility {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub review_id: i64,
+    pub hidden: bool,
+}
+
+#[derive(Deserialize)]
+pub struct AuditFilter {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    #[serde(default, deserialize_with = "from_str_opt")]
+    pub limit: Option<i64>,
+    #[serde(default, deserialize_with = "from_str_opt")]
+    pub patient_id: Option<i64>,
+    #[serde(default, deserialize_with = "from_str_opt")]
+    pub actor_id: Option<i64>,
+    pub actor_role: Option<String>,
+    pub action: Option<String>,
+    pub request_id: Option<String>,
+    //YYYY-MM-DD, both ends included
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+//content is the file encoded as base64
+#[derive(Deserialize)]
+pub struct DoctorDocumentUpload {
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    //license, registration, degree...
+    pub kind: String,
+    pub filename: String,
+    pub content_type: String,
+    pub content: String,
+}
+
+#[derive(Deserialize)]
+pub struct AdminDoctor {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+}
+
+#[derive(Deserialize)]
+pub struct VerificationDecision {
+    #[serde(deserialize_with = "from_str")]
+    pub admin_id: i64,
+    #[serde(deserialize_with = "from_str")]
+    pub doctor_id: i64,
+    pub approved: bool,
+    pub notes: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct Email {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct TokenQuery {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct NewPassword {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordChange {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct EmailChange {
+    pub password: String,
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct SessionID {
+    #[serde(deserialize_with = "from_str")]
+    pub session_id: i64,
+}
+
+//code is from the authenticator app, or a recovery code where those are accepted
+#[derive(Deserialize)]
+pub struct TotpCode {
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct TotpDisable {
+    pub password: String,
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct SecondFactor {
+    pub challenge: String,
+    pub code: String,
 }
 
 #[derive(Deserialize)]
-pub struct CancelAppointment {
-    #[serde(deserialize_with = "from_str")]
-    pub doctor_id: i64,
-    #[serde(deserialize_with = "from_str")]
-    pub patient_id: i64,
-    pub date: String,
+pub struct PhoneNumber {
+    pub phone: String,
+}
+
+//code is the one texted by /phone/otp
+#[derive(Deserialize)]
+pub struct PhoneLogin {
+    pub phone: String,
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct PhoneSignup {
+    pub name: String,
+    pub phone: String,
+    pub code: String,
 }
 
 #[derive(Deserialize)]
@@ -148,13 +798,94 @@ pub struct Timeslots {
     time_start: String,
     available: bool,
     slot_id: i64,
+    clinic_id: i64,
+    clinic: String,
 }
 
 #[derive(FromRow, Serialize)]
 pub struct Prescriptions {
+    id: i64,
+    doctor_id: i32,
     docname: String,
     date: String,
-    prescription: String,
+    prescription: Sealed<String>,
+}
+
+#[derive(FromRow)]
+pub struct PrescriptionDocument {
+    pub id: i64,
+    pub patient_id: i32,
+    pub doctor_id: i32,
+    pub docname: String,
+    pub registration_number: Option<String>,
+    pub registration_council: Option<String>,
+    pub address: String,
+    pub city: String,
+    pub docphone: String,
+    pub patname: String,
+    pub age: Option<Sealed<i32>>,
+    pub gender: Option<Sealed<String>>,
+    pub weight: Option<Sealed<i32>>,
+    pub date: String,
+    pub prescription: Option<Sealed<String>>,
+}
+
+#[derive(Serialize)]
+pub struct PrescriptionVerification {
+    pub valid: bool,
+    pub docname: String,
+    pub registration_number: Option<String>,
+    pub date: String,
+    pub patient_initials: String,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct Drugs {
+    id: i64,
+    name: String,
+    generic_name: String,
+    drug_class: Option<String>,
+    form: Option<String>,
+    strength: Option<String>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct DrugWarning {
+    kind: String,
+    drug: String,
+    conflict: String,
+    severity: String,
+    description: String,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct Allergies {
+    id: i64,
+    allergen: String,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct RefillQueue {
+    id: i64,
+    pub patient_id: i32,
+    patname: String,
+    prescription_id: i32,
+    prescription: Option<Sealed<String>>,
+    note: Option<String>,
+    requested_at: String,
+    refills_used: i64,
+    refills_allowed: i32,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct PatientRefills {
+    id: i64,
+    prescription_id: i32,
+    docname: String,
+    status: String,
+    reason: Option<String>,
+    new_prescription_id: Option<i32>,
+    requested_at: String,
 }
 
 #[derive(FromRow, Serialize)]
@@ -163,7 +894,8 @@ pub struct PrevAppointments {
     date: String,
     phyorvirt: String,
     appstatus: String,
-    prescription_id: i32,
+    prescription_id: Option<i32>,
+    prescription: Option<Sealed<String>>,
     appname: String,
 }
 
@@ -173,17 +905,43 @@ pub struct DoctorInfo {
     docname: String,
     specname: String,
     address: String,
+    clinic_id: i64,
+    clinic: String,
+    rating: Option<f64>,
+    rating_count: i64,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct DoctorProfile {
+    docid: i64,
+    docname: String,
+    specname: String,
+    city: String,
+    address: String,
+    phone: String,
+    gender: Option<String>,
+    qualifications: Option<String>,
+    registration_number: Option<String>,
+    registration_council: Option<String>,
+    years_of_experience: Option<i32>,
+    languages: Vec<String>,
+    bio: Option<String>,
+    consultation_modes: Vec<String>,
+    photo_url: Option<String>,
+    rating: Option<f64>,
+    rating_count: i64,
 }
 
 #[derive(FromRow, Serialize)]
 pub struct PatientInfo {
     name: String,
-    email: String,
+    //walk-ins registered at the front desk don't have one
+    email: Option<String>,
     phone: String,
-    gender: String,
-    weight: i32,
-    age: i32,
-    blood_group: String
+    gender: Sealed<String>,
+    weight: Sealed<i32>,
+    age: Sealed<i32>,
+    blood_group: Sealed<String>
 }
 
 #[derive(FromRow, Serialize)]
@@ -192,10 +950,97 @@ pub struct DoctorPrices {
     docname: String,
     city: String,
     address: String,
+    clinic_id: i64,
+    clinic: String,
+    apptype: String,
+    appid: i64,
+    price: i32,
+    specname: String,
+    rating: Option<f64>,
+    rating_count: i64,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct FindResults {
+    pub docid: i64,
+    docname: String,
+    city: String,
+    address: String,
+    pub clinic_id: i64,
+    clinic: String,
+    apptype: String,
+    pub appid: i64,
+    price: i32,
+    specname: String,
+    //in km, only when lat and lng are given
+    distance: Option<f64>,
+    next_available: Option<String>,
+    rating: Option<f64>,
+    rating_count: i64,
+    #[serde(skip)]
+    pub sortkey: f64,
+}
+
+//not sent as is, results go in the body and the rest in headers
+pub struct FindPage {
+    pub results: Vec<FindResults>,
+    pub total: i64,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct NextAvailable {
+    docid: i64,
+    docname: String,
+    city: String,
+    address: String,
+    clinic_id: i64,
+    clinic: String,
+    specname: String,
+    price: Option<i32>,
+    slot_id: i64,
+    date: String,
+    time_start: String,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct NearbyDoctors {
+    docid: i64,
+    docname: String,
+    city: String,
+    address: String,
+    clinic_id: i64,
+    clinic: String,
     apptype: String,
     appid: i64,
     price: i32,
     specname: String,
+    //in km
+    distance: f64,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct DoctorSearch {
+    docid: i64,
+    docname: String,
+    city: String,
+    address: String,
+    specname: String,
+    apptypes: String,
+    rank: f32,
+    //the matched text, HTML escaped, with matching words wrapped in <b></b>
+    pub highlight: String,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct Reviews {
+    id: i64,
+    rating: i32,
+    review: Option<String>,
+    reply: Option<String>,
+    //first name only
+    patname: String,
+    date: String,
 }
 
 #[derive(FromRow, Serialize)]
@@ -204,6 +1049,278 @@ pub struct Apptypes {
     name: String
 }
 
+#[derive(FromRow, Serialize)]
+pub struct PatientContact {
+    id: i64,
+    name: String,
+    phone: String,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct TokenQueue {
+    id: i64,
+    token_number: i32,
+    patient_id: i32,
+    patname: String,
+    status: String,
+    //HH24:MI:SS, null until the patient checks in
+    checked_in_at: Option<String>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct Users {
+    id: i64,
+    //phone signups have only a phone number
+    email: Option<String>,
+    phone: Option<String>,
+    role: String,
+    disabled: bool,
+    totp_enabled: bool,
+    totp_required: bool,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct AdminAppointments {
+    id: i64,
+    doctor_id: i32,
+    docname: String,
+    pub patient_id: i32,
+    patname: String,
+    apptype: String,
+    date: String,
+    time_start: String,
+    clinic: String,
+    phyorvirt: String,
+    status: String,
+    symptom: Sealed<String>,
+    checked_in_at: Option<String>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct FlaggedReviews {
+    id: i64,
+    doctor_id: i32,
+    docname: String,
+    rating: i32,
+    review: Option<String>,
+    flag_reason: Option<String>,
+    hidden: bool,
+    date: String,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct AuditEntries {
+    id: i64,
+    actor_id: i32,
+    actor_role: String,
+    action: String,
+    target: String,
+    details: Option<String>,
+    patient_id: Option<i32>,
+    resource_id: Option<i64>,
+    request_id: Option<String>,
+    created_at: String,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct RecordAccesses {
+    actor_role: String,
+    //name of the doctor or receptionist, none for admins
+    actor_name: Option<String>,
+    action: String,
+    resource_id: Option<i64>,
+    created_at: String,
+}
+
+//everything kept about a patient, for /patient/export
+#[derive(Serialize)]
+pub struct PatientExport {
+    pub exported_at: String,
+    pub profile: ExportProfile,
+    pub appointments: Vec<ExportAppointments>,
+    pub tokens: Vec<ExportTokens>,
+    pub emergencies: Vec<ExportEmergencies>,
+    pub prescriptions: Vec<ExportPrescriptions>,
+    pub refills: Vec<PatientRefills>,
+    pub allergies: Vec<Allergies>,
+    pub reviews: Vec<ExportReviews>,
+    pub notifications: Vec<Notifications>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct ExportProfile {
+    name: String,
+    email: Option<String>,
+    phone: String,
+    //the phone number logged in with, when the patient signed up with one
+    login_phone: Option<String>,
+    gender: Option<Sealed<String>>,
+    weight: Option<Sealed<i32>>,
+    age: Option<Sealed<i32>>,
+    blood_group: Option<Sealed<String>>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct ExportAppointments {
+    id: i64,
+    docname: String,
+    apptype: String,
+    date: String,
+    time_start: String,
+    clinic: String,
+    phyorvirt: String,
+    status: String,
+    symptom: Sealed<String>,
+    prescription_id: Option<i32>,
+    checked_in_at: Option<String>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct ExportTokens {
+    id: i64,
+    docname: String,
+    apptype: String,
+    date: String,
+    clinic: String,
+    token_number: i32,
+    status: String,
+    symptom: Sealed<String>,
+    prescription_id: Option<i32>,
+    checked_in_at: Option<String>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct ExportEmergencies {
+    id: i64,
+    docname: String,
+    apptype: String,
+    date: String,
+    emergency_no: i32,
+    symptom: Sealed<String>,
+    prescription_id: Option<i32>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct ExportPrescriptions {
+    id: i64,
+    doctor_id: i32,
+    docname: String,
+    date: String,
+    prescription: Option<Sealed<String>>,
+    //names from the drug catalog
+    drugs: Vec<String>,
+    refills_allowed: i32,
+    //the prescription this one is a refill of
+    parent_id: Option<i32>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct ExportReviews {
+    id: i64,
+    docname: String,
+    rating: i32,
+    review: Option<String>,
+    reply: Option<String>,
+    created_at: String,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct Notifications {
+    id: i64,
+    message: String,
+    date_time: String,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct VerificationStatus {
+    status: String,
+    notes: Option<String>,
+    verified_at: Option<String>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct PendingDoctors {
+    id: i64,
+    name: String,
+    email: String,
+    phone: String,
+    registration_number: Option<String>,
+    registration_council: Option<String>,
+    documents: i64,
+}
+
+//without the file itself, which is only sent when it is downloaded
+#[derive(FromRow, Serialize)]
+pub struct DoctorDocuments {
+    id: i64,
+    kind: String,
+    filename: String,
+    content_type: String,
+    size: i32,
+    uploaded_at: String,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct Sessions {
+    id: i64,
+    user_agent: Option<String>,
+    created_at: String,
+    last_seen_at: String,
+    //the session the request was made with
+    current: bool,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct TwoFactorStatus {
+    enabled: bool,
+    //set by an admin, 2FA can't be turned off
+    required: bool,
+    recovery_codes_left: i64,
+}
+
+//uri is what the QR code (an SVG) holds, secret is for typing into the authenticator by hand
+#[derive(Serialize)]
+pub struct TotpSetup {
+    pub secret: String,
+    pub uri: String,
+    pub qr: String,
+}
+
+//what /login gives instead of the JWT when the second factor is still needed
+#[derive(Serialize)]
+pub struct TwoFactorChallenge {
+    pub challenge: String,
+}
+
+#[derive(FromRow)]
+pub struct DocumentFile {
+    pub filename: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct Clinics {
+    id: i64,
+    name: String,
+    address: String,
+    city: String,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    hours: Option<String>,
+    phone: Option<String>,
+    owner_id: Option<i64>,
+}
+
+#[derive(FromRow, Serialize)]
+pub struct ClinicRequests {
+    doctor_id: i64,
+    docname: String,
+    clinic_id: i64,
+    clinic: String,
+    requested_at: String,
+}
+
 #[derive(FromRow, Serialize)]
 pub struct Cities {
     city: String
@@ -215,7 +1332,7 @@ pub struct DoctorAppointments {
     #[serde(deserialize_with = "from_str")]
     id: i64,
     #[serde(deserialize_with = "from_str")]
-    patient_id: i32,
+    pub patient_id: i32,
     #[serde(deserialize_with = "from_str")]
     apptype: i32,
     pub date: String,
@@ -223,7 +1340,7 @@ pub struct DoctorAppointments {
     pub status: String,
     #[serde(deserialize_with = "from_str")]
     pub slot_id: i32,
-    pub symptom: String,
+    pub symptom: Sealed<String>,
 }
 
 #[derive(FromRow, Serialize)]
@@ -231,10 +1348,10 @@ pub struct EmergencyAppointments {
     #[serde(deserialize_with = "from_str")]
     id: i32,
     #[serde(deserialize_with = "from_str")]
-    patient_id: i32,
+    pub patient_id: i32,
     #[serde(deserialize_with = "from_str")]
     apptype: i32,
-    pub symptom: String,
+    pub symptom: Sealed<String>,
 }
 
 #[derive(FromRow, Serialize)]
@@ -249,6 +1366,13 @@ pub struct LoginTable {
     pub salt: String,
     pub hashedpass: String,
     pub isdoctor: bool,
+    pub role: String,
+    pub disabled: bool,
+    pub email_verified: bool,
+    pub totp_enabled: bool,
+    pub id: i64,
+    pub login_id: i64,
+    pub locked_for: i64,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -256,6 +1380,10 @@ pub struct JWT {
     pub isdoctor: bool,
     #[serde(deserialize_with = "from_str")]
     pub id: i64,
+    //patient, doctor, receptionist or admin
+    pub role: String,
+    //the session this JWT belongs to
+    pub sid: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -263,6 +1391,12 @@ pub struct InternalJWT {
     pub isdoctor: bool,
     pub id: String,
     pub exp: usize,
+    //missing from tokens issued before roles, isdoctor decides then
+    #[serde(default)]
+    pub role: Option<String>,
+    //missing from tokens issued before sessions, which aren't accepted anymore
+    #[serde(default)]
+    pub sid: Option<i64>,
 }
 
 //function to convert the input string into a number with some Serde magic
@@ -275,3 +1409,17 @@ where
     let s = String::deserialize(deserializer)?;
     T::from_str(&s).map_err(de::Error::custom)
 }
+
+//same as from_str, but for optional fields that may be left out or sent empty
+fn from_str_opt<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: FromStr,
+    T::Err: Display,
+    D: Deserializer<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(s) if !s.is_empty() => T::from_str(&s).map(Some).map_err(de::Error::custom),
+        _ => Ok(None),
+    }
+}
+
@@ -4,11 +4,44 @@ INSERT INTO Specialities (name, description) VALUES
 ('Gastroenterology', 'Digestive system diseases'),
 ('Pediatrics','Child health and diseases');
 
-INSERT INTO Doctors (name, speciality_id, city, address, email, phone) VALUES
-('Dr. Rohan Shah', 1, 'Mumbai', '1234 Main St', 'rohan.shah@example.com', '1234567890'),
-('Dr. Priya Patel', 2, 'Delhi', '5678 Park Ave', 'priya.patel@example.com', '0987654321'),
-('Dr. Vikram Singh', 3, 'Bangalore', '9101112 Oak St', 'vikram.singh@example.com', '1212121212'),
-('Dr. Anjali Gupta', 4, 'Chennai', '131415 Elm St', 'anjali.gupta@example.com', '3434343434');
+INSERT INTO Doctors (name, city, address, email, phone, registration_number, registration_council) VALUES
+('Dr. Rohan Shah', 'Mumbai', '1234 Main St', 'rohan.shah@example.com', '1234567890', 'MMC-2011-04521', 'Maharashtra Medical Council'),
+('Dr. Priya Patel', 'Delhi', '5678 Park Ave', 'priya.patel@example.com', '0987654321', 'DMC-2014-11873', 'Delhi Medical Council'),
+('Dr. Vikram Singh', 'Bangalore', '9101112 Oak St', 'vikram.singh@example.com', '1212121212', 'KMC-2009-07342', 'Karnataka Medical Council'),
+('Dr. Anjali Gupta', 'Chennai', '131415 Elm St', 'anjali.gupta@example.com', '3434343434', 'TNMC-2016-02987', 'Tamil Nadu Medical Council');
+
+INSERT INTO Doctor_Specialities (doctor_id, speciality_id) VALUES
+(1, 1),
+(2, 2),
+(3, 3),
+(4, 4),
+(4, 2);
+
+UPDATE Doctors SET gender = 'M', languages = '{English,Hindi,Marathi}', consultation_modes = '{physical,virtual}' WHERE id = 1;
+UPDATE Doctors SET gender = 'F', languages = '{English,Hindi}', consultation_modes = '{virtual}' WHERE id = 2;
+UPDATE Doctors SET gender = 'M', languages = '{English,Kannada}' WHERE id = 3;
+UPDATE Doctors SET gender = 'F', languages = '{English,Tamil}', consultation_modes = '{physical,virtual}' WHERE id = 4;
+
+UPDATE Doctors SET qualifications = 'MBBS, MD (Medicine), DM (Cardiology)', years_of_experience = 14, bio = 'Interventional cardiologist with a focus on preventive heart care.' WHERE id = 1;
+UPDATE Doctors SET qualifications = 'MBBS, MD (Dermatology)', years_of_experience = 9, bio = 'Treats acne, eczema and other common skin conditions.' WHERE id = 2;
+UPDATE Doctors SET qualifications = 'MBBS, MD (Medicine), DM (Gastroenterology)', years_of_experience = 16 WHERE id = 3;
+UPDATE Doctors SET qualifications = 'MBBS, MD (Pediatrics)', years_of_experience = 7 WHERE id = 4;
+
+UPDATE Doctors SET verification_status = 'verified', verified_at = now() WHERE id IN (1, 2, 3, 4);
+
+INSERT INTO Clinics (name, address, city, latitude, longitude, hours, phone, owner_id) VALUES
+('Shah Heart Clinic', '1234 Main St', 'Mumbai', 19.0760, 72.8777, 'Mon-Sat 09:00-18:00', '1234567890', 1),
+('Patel Skin Care', '5678 Park Ave', 'Delhi', 28.6139, 77.2090, 'Mon-Fri 10:00-17:00', '0987654321', 2),
+('Singh Gastro Centre', '9101112 Oak St', 'Bangalore', 12.9716, 77.5946, 'Mon-Sat 09:00-16:00', '1212121212', 3),
+('Gupta Child Care', '131415 Elm St', 'Chennai', 13.0827, 80.2707, 'Mon-Sat 09:00-13:00', '3434343434', 4),
+('City Care Hospital', '22 FC Road', 'Pune', 18.5204, 73.8567, 'All days 08:00-20:00', '2020202020', 1);
+
+INSERT INTO Doctor_Clinics (doctor_id, clinic_id) VALUES
+(1, 1),
+(2, 2),
+(3, 3),
+(4, 4),
+(1, 5);
 
 INSERT INTO Doctors_Emergency (doctor_id, available) VALUES
 (1, true),
@@ -39,16 +72,18 @@ INSERT INTO Prescriptions (patient_id, doctor_id, prescription, appointment_date
 (3,4,'Do Physical Therap', '2021-02-01 00:00:00'),
 (4,1,'Take ample rest and avoid stressful situations', '2022-01-21 00:00:00');
 
-INSERT INTO Doctor_Slots (doctor_id, time_start) VALUES
-(1, '1111-11-11 09:00:00'),
-(1, '1111-11-11 10:00:00'),
-(1, '1111-11-11 11:00:00'),
-(2, '1111-11-11 09:00:00'),
-(2, '1111-11-11 10:00:00'),
-(3, '1111-11-11 09:00:00'),
-(3, '1111-11-11 10:00:00'),
-(4, '1111-11-11 09:00:00'),
-(4, '1111-11-11 10:00:00');
+INSERT INTO Doctor_Slots (doctor_id, clinic_id, time_start) VALUES
+(1, 1, '1111-11-11 09:00:00'),
+(1, 1, '1111-11-11 10:00:00'),
+(1, 1, '1111-11-11 11:00:00'),
+(2, 2, '1111-11-11 09:00:00'),
+(2, 2, '1111-11-11 10:00:00'),
+(3, 3, '1111-11-11 09:00:00'),
+(3, 3, '1111-11-11 10:00:00'),
+(4, 4, '1111-11-11 09:00:00'),
+(4, 4, '1111-11-11 10:00:00'),
+(1, 5, '1111-11-11 16:00:00'),
+(1, 5, '1111-11-11 17:00:00');
 
 INSERT INTO Appointments (doctor_id, patient_id, appointment_type, appointment_date, slot_id, type, status, symptom, prescription_id) VALUES
 (1, 1, 1, '2022-02-01 00:00:00', 1, 'physical', 'scheduled', 'Headache', 1),
@@ -56,8 +91,8 @@ INSERT INTO Appointments (doctor_id, patient_id, appointment_type, appointment_d
 (3, 3, 3, '2022-02-03 00:00:00', 7, 'physical', 'scheduled', 'Stomach pain', 3),
 (4, 4, 4, '2022-02-04 00:00:00', 9, 'virtual', 'scheduled', 'Chest pain', 4);
 
-INSERT INTO Tokens (doctor_id, patient_id, appointment_type, appointment_date, token_number, symptom, status) VALUES
-(1, 1, 1, '2022-02-01 10:00:00', 1, 'Headache way too severe', 'fulfilled'),
-(2, 2, 2, '2022-02-02 10:00:00', 2, 'Acne too much', 'ongoing'),
-(3, 3, 3, '2022-02-03 10:00:00', 3, 'Stomach pains for few days', 'scheduled'),
-(4, 4, 4, '2022-02-04 10:00:00', 4, 'Chest pains for last few hours', 'scheduled');
+INSERT INTO Tokens (doctor_id, clinic_id, patient_id, appointment_type, appointment_date, token_number, symptom, status) VALUES
+(1, 1, 1, 1, '2022-02-01 10:00:00', 1, 'Headache way too severe', 'fulfilled'),
+(2, 2, 2, 2, '2022-02-02 10:00:00', 2, 'Acne too much', 'ongoing'),
+(3, 3, 3, 3, '2022-02-03 10:00:00', 3, 'Stomach pains for few days', 'scheduled'),
+(4, 4, 4, 4, '2022-02-04 10:00:00', 4, 'Chest pains for last few hours', 'scheduled');
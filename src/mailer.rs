@@ -0,0 +1,55 @@
+//sending emails to users; MAILER picks how: log (default) only writes them to the log,
+//file appends them to MAIL_FILE, both meant for local development until a real mail service is plugged in
+use async_trait::async_trait;
+use std::env;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> bool;
+}
+
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> bool {
+        tracing::info!("Mail to {}\nSubject: {}\n\n{}", to, subject, body);
+        true
+    }
+}
+
+pub struct FileMailer {
+    path: String,
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, to: &str, subject: &str, body: &str) -> bool {
+        let mail = format!("To: {}\nSubject: {}\n\n{}\n\n", to, subject, body);
+        let file = OpenOptions::new().create(true).append(true).open(&self.path).await;
+        match file {
+            Ok(mut file) => match file.write_all(mail.as_bytes()).await {
+                Ok(_) => true,
+                Err(e) => {
+                    tracing::error!("Couldn't write mail to {}: {}", self.path, e);
+                    false
+                }
+            },
+            Err(e) => {
+                tracing::error!("Couldn't open mail file {}: {}", self.path, e);
+                false
+            }
+        }
+    }
+}
+
+pub fn from_env() -> Box<dyn Mailer> {
+    match env::var("MAILER").as_deref() {
+        Ok("file") => Box::new(FileMailer {
+            path: env::var("MAIL_FILE").unwrap_or_else(|_| String::from("mail.txt")),
+        }),
+        _ => Box::new(LogMailer),
+    }
+}
@@ -1,14 +1,17 @@
 use axum::{
-    extract::Query,
+    extract::{Path, Query},
     http::{
-        header::{HeaderMap, AUTHORIZATION},
+        header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE, USER_AGENT},
         Method, StatusCode,
     },
+    middleware,
     response::{IntoResponse, Response},
     routing::{get, post},
-    Json, Router,
+    Extension, Json, Router,
 };
+use std::sync::Arc;
 use db_structs::*;
+use std::env;
 use std::net::SocketAddr;
 use tokio;
 use tower_http::cors::{Any, CorsLayer};
@@ -16,8 +19,19 @@ use tracing;
 use tracing_subscriber;
 use sqlx::postgres;
 
+mod crypto;
 mod database;
 mod db_structs;
+mod mailer;
+mod pdf;
+mod ratelimit;
+mod request_id;
+mod sms;
+mod totp;
+
+//pagination details for /find, sent as headers so the body stays a plain array
+const TOTAL_COUNT: HeaderName = HeaderName::from_static("x-total-count");
+const NEXT_CURSOR: HeaderName = HeaderName::from_static("x-next-cursor");
 
 async fn authenticate(
     conn: &database::Database,
@@ -25,28 +39,122 @@ async fn authenticate(
     given_id: &i64,
     isdoctor: bool,
 ) -> bool {
+    let role = if isdoctor { "doctor" } else { "patient" };
+    authenticate_role(conn, headers, given_id, role).await
+}
+
+//same as authenticate for any role, like receptionist
+async fn authenticate_role(
+    conn: &database::Database,
+    headers: HeaderMap,
+    given_id: &i64,
+    role: &str,
+) -> bool {
+    match authenticated(conn, &headers).await {
+        Some(jwt) if *given_id == jwt.id && role == jwt.role => {
+            tracing::debug!("Correct JWT is given!");
+            true
+        }
+        Some(_) => {
+            tracing::error!("Incorrect JWT!");
+            false
+        }
+        None => false,
+    }
+}
+
+//whoever the JWT belongs to, as long as their login and session are still active
+async fn authenticated(conn: &database::Database, headers: &HeaderMap) -> Option<JWT> {
+    session(conn, headers, false).await
+}
+
+//for the /account/2fa endpoints, which logins an admin requires 2FA for still need before they've set it up
+async fn authenticated_for_2fa(conn: &database::Database, headers: &HeaderMap) -> Option<JWT> {
+    session(conn, headers, true).await
+}
+
+async fn session(conn: &database::Database, headers: &HeaderMap, setting_up_2fa: bool) -> Option<JWT> {
     let Some(entry) = headers.get(AUTHORIZATION) else {
         tracing::error!("No JWT given in request, denying access..");
-        return false;
+        return None;
     };
     let Ok(rawjwt) = entry.to_str() else {
         tracing::error!("JWT can't be parsed, denying access..");
-        return false;
+        return None;
     };
-    match conn.verify_jwt(rawjwt) {
-        Some(jwt) => {
-            tracing::debug!("Verified and parsed JWT");
-            if *given_id == jwt.id && isdoctor == jwt.isdoctor {
-                tracing::debug!("Correct JWT is given!");
-                return true;
-            } else {
-                tracing::error!("Incorrect JWT!");
-                return false;
-            }
+    let Some(jwt) = conn.verify_jwt(rawjwt) else {
+        tracing::debug!("Could not verify JWT!");
+        return None;
+    };
+    tracing::debug!("Verified and parsed JWT");
+    if !conn.account_active(&jwt.role, jwt.id, jwt.sid, setting_up_2fa).await {
+        tracing::error!("Login of JWT is disabled, no longer has this role, its session was revoked or it has to set up 2FA!");
+        return None;
+    }
+    Some(jwt)
+}
+
+//who is reading or writing which patient's health data in this request, for the audit log
+fn medical_access<'a>(
+    request_id: &'a str,
+    actor_id: i64,
+    actor_role: &'a str,
+    action: &'a str,
+    resource_id: Option<i64>,
+) -> database::MedicalAccess<'a> {
+    database::MedicalAccess { request_id, actor_id, actor_role, action, resource_id }
+}
+
+//writes are recorded as <action>_attempt before they run, so none can go unrecorded, and once they are done
+//as <action>, or <action>_failed when they didn't go through
+async fn record_attempt(conn: &database::Database, access: &database::MedicalAccess<'_>, patient_ids: &[i64]) -> bool {
+    let action = format!("{}_attempt", access.action);
+    conn.record_access(&database::MedicalAccess { action: &action, ..*access }, patient_ids).await
+}
+
+//the attempt is already on record, so a missing outcome is only logged by record_access
+async fn record_outcome(conn: &database::Database, access: &database::MedicalAccess<'_>, patient_ids: &[i64], done: bool) {
+    let action = match done {
+        true => access.action.to_string(),
+        false => format!("{}_failed", access.action),
+    };
+    conn.record_access(&database::MedicalAccess { action: &action, ..*access }, patient_ids).await;
+}
+
+//address the backend is reachable at, for links handed out in prescriptions and mails
+fn public_url() -> String {
+    let base = env::var("PUBLIC_URL").unwrap_or_else(|_| String::from("http://localhost:3000"));
+    base.trim_end_matches('/').to_string()
+}
+
+//mails a link for confirming the email, does nothing when it is already verified
+async fn send_verification(conn: &database::Database, email: &str) {
+    let Some(token) = conn.create_auth_token(email, "verify_email").await else {
+        return;
+    };
+    let body = format!(
+        "Open this link to confirm your email address, it is valid for 24 hours:\n{}/verify?token={}",
+        public_url(),
+        token
+    );
+    if !mailer::from_env().send(email, "Confirm your email address", &body).await {
+        tracing::error!("Couldn't send verification mail to {}", email);
+    }
+}
+
+//409 when the email is already registered, so clients can tell it apart from bad input
+async fn signup_response(conn: &database::Database, res: Result<(), database::SignupError>, email: &str) -> Response {
+    match res {
+        Ok(()) => {
+            tracing::debug!("Record inserted successfully");
+            send_verification(conn, email).await;
+            (StatusCode::OK, Json("Inserted")).into_response()
         }
-        None => {
-            tracing::debug!("Could not verify JWT!");
-            false
+        Err(database::SignupError::Duplicate) => {
+            (StatusCode::CONFLICT, Json("Email is already registered")).into_response()
+        }
+        Err(database::SignupError::Failed) => {
+            (StatusCode::BAD_REQUEST, Json("Error while inserting")).into_response()
         }
     }
 }
@@ -54,17 +162,46 @@ async fn authenticate(
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt::init();
+    if let Some(command) = env::args().nth(1) {
+        std::process::exit(run_command(&command).await);
+    }
+    if let Err(e) = crypto::check() {
+        tracing::error!("{}", e);
+        std::process::exit(1);
+    }
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_headers(Any)
         .expose_headers(Any)
         .allow_methods([Method::GET, Method::POST]);
+    //signups and everything that takes a password or mailed token, limited per client IP
+    let auth = Router::new()
+        .route("/login", post(login))
+        .route("/login/2fa", post(login_2fa))
+        .route("/phone/otp", post(phone_otp))
+        .route("/phone/login", post(phone_login))
+        .route("/phone/signup", post(phone_signup))
+        .route("/newpatient", post(newpatient))
+        .route("/newdoctor", post(newdoctor))
+        .route("/verify", get(verify))
+        .route("/verify/resend", post(verify_resend))
+        .route("/password/forgot", post(password_forgot))
+        .route("/password/reset", post(password_reset))
+        .route_layer(middleware::from_fn(ratelimit::limit_by_ip));
+
     let app = Router::new()
         .route("/", get(root))
         .route("/prevapp", post(prevapp))
         .route("/doctorappointments", post(doctorappointments))
         .route("/emergency/appointments", post(emergency_appointments))
         .route("/doctors", post(doctors))
+        .route("/doctors/:id", get(doctor_profile))
+        .route("/doctor/update", post(doctor_update))
+        .route("/doctor/specialities", get(doctor_specialities))
+        .route("/doctor/specialities/add", post(doctor_specialities_add))
+        .route("/doctor/specialities/remove", post(doctor_specialities_remove))
+        .route("/doctor/prices/set", post(doctor_prices_set))
+        .route("/doctor/prices/remove", post(doctor_prices_remove))
         .route("/doctor/timeslots", post(doctor_timeslots))
         .route("/doctor/newtoken", post(doctor_newtoken))
         .route("/doctor/curtoken", post(doctor_curtoken))
@@ -72,9 +209,64 @@ async fn main() {
         .route("/patient/update", post(patient_update))
         .route("/emergency/find", get(emergency_find))
         .route("/find", get(find))
-        .route("/login", post(login))
-        .route("/newpatient", post(newpatient))
-        .route("/newdoctor", post(newdoctor))
+        .route("/search", get(search))
+        .route("/nearby", get(nearby))
+        .route("/review/new", post(review_new))
+        .route("/review/reply", post(review_reply))
+        .route("/review/flag", post(review_flag))
+        .route("/doctor/reviews", get(doctor_reviews))
+        .route("/nextavailable", get(nextavailable))
+        .route("/doctor/documents", post(doctor_documents))
+        .route("/doctor/documents/upload", post(doctor_documents_upload))
+        .route("/doctor/verification", post(doctor_verification))
+        .route("/doctor/clinics", get(doctor_clinics))
+        .route("/doctor/clinics/add", post(doctor_clinics_add))
+        .route("/doctor/clinics/remove", post(doctor_clinics_remove))
+        .route("/clinic/new", post(clinic_new))
+        .route("/clinic/update", post(clinic_update))
+        .route("/clinic/requests", post(clinic_requests))
+        .route("/clinic/requests/decide", post(clinic_requests_decide))
+        .route("/account/password", post(account_password))
+        .route("/account/email", post(account_email))
+        .route("/account/sessions", get(account_sessions))
+        .route("/account/sessions/revoke", post(account_sessions_revoke))
+        .route("/account/sessions/revokeothers", post(account_sessions_revokeothers))
+        .route("/account/2fa", get(account_2fa))
+        .route("/account/2fa/setup", post(account_2fa_setup))
+        .route("/account/2fa/enable", post(account_2fa_enable))
+        .route("/account/2fa/disable", post(account_2fa_disable))
+        .route("/account/2fa/recoverycodes", post(account_2fa_recoverycodes))
+        .route("/receptionist/new", post(receptionist_new))
+        .route("/reception/patient/new", post(reception_patient_new))
+        .route("/reception/patient/search", post(reception_patient_search))
+        .route("/reception/token", post(reception_token))
+        .route("/reception/emergency", post(reception_emergency))
+        .route("/reception/checkin", post(reception_checkin))
+        .route("/reception/queue", post(reception_queue))
+        .route("/reception/next", post(reception_next))
+        .route("/admin/specialities/new", post(admin_speciality_new))
+        .route("/admin/specialities/update", post(admin_speciality_update))
+        .route("/admin/specialities/delete", post(admin_speciality_delete))
+        .route("/admin/apptypes/new", post(admin_apptype_new))
+        .route("/admin/apptypes/update", post(admin_apptype_update))
+        .route("/admin/apptypes/delete", post(admin_apptype_delete))
+        .route("/admin/doctors/pending", post(admin_doctors_pending))
+        .route("/admin/doctors/documents", post(admin_doctors_documents))
+        .route("/admin/doctors/document", post(admin_doctors_document))
+        .route("/admin/doctors/verify", post(admin_doctors_verify))
+        .route("/admin/users", post(admin_users))
+        .route("/admin/users/disable", post(admin_users_disable))
+        .route("/admin/users/resetpassword", post(admin_users_resetpassword))
+        .route("/admin/users/role", post(admin_users_role))
+        .route("/admin/users/require2fa", post(admin_users_require2fa))
+        .route("/admin/users/reset2fa", post(admin_users_reset2fa))
+        .route("/admin/appointments", post(admin_appointments))
+        .route("/admin/reviews/flagged", post(admin_reviews_flagged))
+        .route("/admin/reviews/hide", post(admin_reviews_hide))
+        .route("/admin/clinics/update", post(admin_clinics_update))
+        .route("/admin/clinics/requests", post(admin_clinics_requests))
+        .route("/admin/clinics/requests/decide", post(admin_clinics_requests_decide))
+        .route("/admin/audit", post(admin_audit))
         .route("/newappointment", post(newappointment))
         .route("/newtoken", post(newtoken))
         .route("/newemergency", post(newemergency))
@@ -85,16 +277,82 @@ async fn main() {
         .route("/apptypes", get(apptypes))
         .route("/newprescription", post(newprescription))
         .route("/prescriptions", post(prescriptions))
+        .route("/prescription/pdf", post(prescription_pdf))
+        .route("/prescriptions/verify", get(prescription_verify))
+        .route("/prescription/check", post(prescription_check))
+        .route("/doctor/patientprescriptions", post(doctor_patient_prescriptions))
+        .route("/drugs/search", get(drugs_search))
+        .route("/refill/request", post(refill_request))
+        .route("/refill/queue", post(refill_queue))
+        .route("/refill/approve", post(refill_approve))
+        .route("/refill/decline", post(refill_decline))
+        .route("/patient/refills", post(patient_refills))
+        .route("/patient/allergies", post(patient_allergies))
+        .route("/patient/allergies/add", post(patient_allergies_add))
+        .route("/patient/allergies/remove", post(patient_allergies_remove))
+        .route("/patient/accesslog", post(patient_accesslog))
+        .route("/patient/export", post(patient_export))
+        .route("/patient/delete", post(patient_delete))
+        .merge(auth)
+        .layer(Extension(ratelimit::from_env().await))
+        .layer(middleware::from_fn(request_id::assign))
         .layer(cors);
 
     let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
     tracing::debug!("listening on {}", addr);
     axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
 }
 
+//keygen adds a new current master key to ENCRYPTION_KEY_FILE, encrypt seals health data written before
+//encryption was turned on and rewraps what is still sealed with an older master key
+async fn run_command(command: &str) -> i32 {
+    match command {
+        "keygen" => {
+            dotenvy::dotenv().ok();
+            let path = match env::var("ENCRYPTION_KEY_FILE") {
+                Ok(path) if !path.is_empty() => path,
+                _ => {
+                    eprintln!("Set ENCRYPTION_KEY_FILE to the key file to add the key to");
+                    return 1;
+                }
+            };
+            match crypto::generate_key(&path) {
+                Ok(id) => {
+                    println!("Added key {} to {}, restart the backend and run encrypt to rewrap existing data with it", id, path);
+                    0
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    1
+                }
+            }
+        }
+        "encrypt" => {
+            let Some(conn) = database::init().await else {
+                eprintln!("Couldn't connect to the database");
+                return 1;
+            };
+            for (table, column) in crypto::SEALED_COLUMNS {
+                match conn.reseal_column(table, column).await {
+                    Some(changed) => println!("{}.{}: {} values sealed", table, column, changed),
+                    None => {
+                        eprintln!("Stopped at {}.{}, see the log for why", table, column);
+                        return 1;
+                    }
+                }
+            }
+            0
+        }
+        _ => {
+            eprintln!("Unknown command {}, expected keygen or encrypt", command);
+            1
+        }
+    }
+}
+
 async fn root() -> &'static str {
     "Hello world"
 }
@@ -104,11 +362,33 @@ async fn newprescription(headers: HeaderMap, Json(payload): Json<PrescriptionInf
         "Got request to add new prescription for patient ID {} and doctor ID {}",
         payload.patient_id, payload.doctor_id
     );
+    let request_id = request_id::from_headers(&headers);
     let mut code = StatusCode::OK;
     let res = match database::init().await {
         Some(conn) => {
-            if authenticate(&conn, headers, &(payload.patient_id as i64), true).await {
-                match conn.add_new_prescription(payload.doctor_id, payload.patient_id, &payload.prescription, &payload.date).await {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await
+                && conn.doctor_treats_patient(payload.doctor_id, payload.patient_id).await
+            {
+                if !payload.acknowledged {
+                    let Ok(warnings) = conn.check_drugs(payload.patient_id, &payload.drugs).await else {
+                        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while checking drugs")).into_response();
+                    };
+                    if !warnings.is_empty() {
+                        let access = medical_access(&request_id, payload.doctor_id, "doctor", "drug_check.read", None);
+                        if !conn.record_access(&access, &[payload.patient_id]).await {
+                            return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while inserting")).into_response();
+                        }
+                        tracing::debug!("Drug check gave {} warnings, needs acknowledgement", warnings.len());
+                        return (StatusCode::CONFLICT, Json(warnings)).into_response();
+                    }
+                }
+                let access = medical_access(&request_id, payload.doctor_id, "doctor", "prescription.create", None);
+                if !record_attempt(&conn, &access, &[payload.patient_id]).await {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while inserting")).into_response();
+                }
+                let done = conn.add_new_prescription(&payload).await;
+                record_outcome(&conn, &access, &[payload.patient_id], done).await;
+                match done {
                     true => "Inserted",
                     false => {
                         code = StatusCode::BAD_REQUEST;
@@ -133,12 +413,18 @@ async fn prescriptions(headers: HeaderMap, Json(payload): Json<PatientID>) -> Re
         "Got request to view previous appointments for patient ID {}",
         payload.patient_id
     );
+    let request_id = request_id::from_headers(&headers);
     let mut code = StatusCode::OK;
     let res = match database::init().await {
         Some(conn) => {
             if authenticate(&conn, headers, &payload.patient_id, false).await {
-                let res = conn.view_prescriptions(payload.patient_id).await;
-                res
+                let access = medical_access(&request_id, payload.patient_id, "patient", "prescription.read", None);
+                if conn.record_access(&access, &[payload.patient_id]).await {
+                    conn.view_prescriptions(payload.patient_id, None, None, None).await
+                } else {
+                    code = StatusCode::INTERNAL_SERVER_ERROR;
+                    Vec::new()
+                }
             } else {
                 code = StatusCode::UNAUTHORIZED;
                 let res: Vec<Prescriptions> = Vec::new();
@@ -157,26 +443,40 @@ async fn prescriptions(headers: HeaderMap, Json(payload): Json<PatientID>) -> Re
     (code, Json(res)).into_response()
 }
 
-async fn emergency_appointments(headers: HeaderMap, Json(payload): Json<DoctorDate>) -> Response {
+async fn doctor_patient_prescriptions(headers: HeaderMap, Json(payload): Json<PatientPrescriptions>) -> Response {
     tracing::debug!(
-        "Got request to view emergency appointments for doctor ID {}",
-        payload.doctor_id
+        "Got request from doctor ID {} to view prescriptions of patient ID {}",
+        payload.doctor_id, payload.patient_id
     );
+    let request_id = request_id::from_headers(&headers);
     let mut code = StatusCode::OK;
     let res = match database::init().await {
         Some(conn) => {
-            if authenticate(&conn, headers, &payload.doctor_id, true).await {
-                let res = conn.view_doctor_emergencies(payload.doctor_id, &payload.date).await;
-                res
+            if authenticate(&conn, headers, &payload.doctor_id, true).await
+                && conn.doctor_treats_patient(payload.doctor_id, payload.patient_id).await
+            {
+                let access = medical_access(&request_id, payload.doctor_id, "doctor", "prescription.read", None);
+                if conn.record_access(&access, &[payload.patient_id]).await {
+                    conn.view_prescriptions(
+                        payload.patient_id,
+                        payload.by_doctor,
+                        payload.from.as_deref(),
+                        payload.to.as_deref(),
+                    )
+                    .await
+                } else {
+                    code = StatusCode::INTERNAL_SERVER_ERROR;
+                    Vec::new()
+                }
             } else {
                 code = StatusCode::UNAUTHORIZED;
-                let res: Vec<EmergencyAppointments> = Vec::new();
+                let res: Vec<Prescriptions> = Vec::new();
                 res
             }
         }
         None => {
             code = StatusCode::INTERNAL_SERVER_ERROR;
-            let res: Vec<EmergencyAppointments> = Vec::new();
+            let res: Vec<Prescriptions> = Vec::new();
             res
         }
     };
@@ -186,28 +486,154 @@ async fn emergency_appointments(headers: HeaderMap, Json(payload): Json<DoctorDa
     (code, Json(res)).into_response()
 }
 
+async fn prescription_pdf(headers: HeaderMap, Json(payload): Json<PrescriptionPatient>) -> Response {
+    tracing::debug!(
+        "Got request to print prescription ID {} for patient ID {}",
+        payload.prescription_id, payload.patient_id
+    );
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while generating PDF")).into_response();
+    };
+    let request_id = request_id::from_headers(&headers);
+    if !authenticate(&conn, headers, &payload.patient_id, false).await {
+        return (StatusCode::UNAUTHORIZED, Json("Error while generating PDF")).into_response();
+    }
+    let access = medical_access(&request_id, payload.patient_id, "patient", "prescription.print", Some(payload.prescription_id));
+    if !conn.record_access(&access, &[payload.patient_id]).await {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while generating PDF")).into_response();
+    }
+    let Some(doc) = conn.view_prescription_document(payload.prescription_id, payload.patient_id).await else {
+        return (StatusCode::BAD_REQUEST, Json("No such prescription")).into_response();
+    };
+    let Some(code) = conn.prescription_code(&doc) else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while generating PDF")).into_response();
+    };
+    let url = format!("{}/prescriptions/verify?id={}&code={}", public_url(), doc.id, code);
+    match pdf::prescription(&doc, &code, &url) {
+        Some(bytes) => (
+            StatusCode::OK,
+            [
+                (CONTENT_TYPE, String::from("application/pdf")),
+                (CONTENT_DISPOSITION, format!("attachment; filename=\"prescription-{}.pdf\"", doc.id)),
+            ],
+            bytes,
+        )
+            .into_response(),
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while generating PDF")).into_response(),
+    }
+}
 
+async fn prescription_verify(payload: Query<PrescriptionCode>) -> Response {
+    tracing::debug!("Got request to verify prescription ID {}", payload.id);
+    match database::init().await {
+        Some(conn) => match conn.verify_prescription(payload.id, &payload.code).await {
+            Some(res) => (StatusCode::OK, Json(res)).into_response(),
+            None => (StatusCode::BAD_REQUEST, Json("Invalid verification code")).into_response(),
+        },
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while verifying")).into_response(),
+    }
+}
 
-async fn doctorappointments(headers: HeaderMap, Json(payload): Json<PatientID>) -> Response {
+async fn prescription_check(headers: HeaderMap, Json(payload): Json<DrugCheck>) -> Response {
     tracing::debug!(
-        "Got request to view appointments for doctor ID {}",
-        payload.patient_id
+        "Got request to check {} drugs for patient ID {}",
+        payload.drugs.len(), payload.patient_id
     );
+    let request_id = request_id::from_headers(&headers);
     let mut code = StatusCode::OK;
     let res = match database::init().await {
         Some(conn) => {
-            if authenticate(&conn, headers, &payload.patient_id, true).await {
-                let res = conn.view_doctor_appointments(payload.patient_id).await;
+            if authenticate(&conn, headers, &payload.doctor_id, true).await
+                && conn.doctor_treats_patient(payload.doctor_id, payload.patient_id).await
+            {
+                let access = medical_access(&request_id, payload.doctor_id, "doctor", "drug_check.read", None);
+                if conn.record_access(&access, &[payload.patient_id]).await {
+                    conn.check_drugs(payload.patient_id, &payload.drugs).await.unwrap_or_else(|_| {
+                        code = StatusCode::INTERNAL_SERVER_ERROR;
+                        Vec::new()
+                    })
+                } else {
+                    code = StatusCode::INTERNAL_SERVER_ERROR;
+                    Vec::new()
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                let res: Vec<DrugWarning> = Vec::new();
                 res
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<DrugWarning> = Vec::new();
+            res
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn refill_request(headers: HeaderMap, Json(payload): Json<RefillRequest>) -> Response {
+    tracing::debug!(
+        "Got request to refill prescription ID {} for patient ID {}",
+        payload.prescription_id, payload.patient_id
+    );
+    let request_id = request_id::from_headers(&headers);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.patient_id, false).await {
+                let access = medical_access(&request_id, payload.patient_id, "patient", "refill.create", Some(payload.prescription_id));
+                if !record_attempt(&conn, &access, &[payload.patient_id]).await {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while requesting refill")).into_response();
+                }
+                let done = conn.request_refill(payload.patient_id, payload.prescription_id, &payload.note).await;
+                record_outcome(&conn, &access, &[payload.patient_id], done).await;
+                match done {
+                    true => "Requested",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while requesting refill"
+                    }
+                }
             } else {
                 code = StatusCode::UNAUTHORIZED;
-                let res: Vec<DoctorAppointments> = Vec::new();
+                "Error while requesting refill"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while requesting refill"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn refill_queue(headers: HeaderMap, Json(payload): Json<DoctorID>) -> Response {
+    tracing::debug!("Got request to view refill queue for doctor ID {}", payload.doctor_id);
+    let request_id = request_id::from_headers(&headers);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await {
+                let res = conn.view_refill_queue(payload.doctor_id).await;
+                let mut patients: Vec<i64> = res.iter().map(|refill| refill.patient_id as i64).collect();
+                patients.sort_unstable();
+                patients.dedup();
+                let access = medical_access(&request_id, payload.doctor_id, "doctor", "refill.read", None);
+                if conn.record_access(&access, &patients).await {
+                    res
+                } else {
+                    code = StatusCode::INTERNAL_SERVER_ERROR;
+                    Vec::new()
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                let res: Vec<RefillQueue> = Vec::new();
                 res
             }
         }
         None => {
             code = StatusCode::INTERNAL_SERVER_ERROR;
-            let res: Vec<DoctorAppointments> = Vec::new();
+            let res: Vec<RefillQueue> = Vec::new();
             res
         }
     };
@@ -217,26 +643,29 @@ async fn doctorappointments(headers: HeaderMap, Json(payload): Json<PatientID>)
     (code, Json(res)).into_response()
 }
 
-async fn prevapp(headers: HeaderMap, Json(payload): Json<PatientID>) -> Response {
-    tracing::debug!(
-        "Got request to view previous appointments for patient ID {}",
-        payload.patient_id
-    );
+async fn patient_refills(headers: HeaderMap, Json(payload): Json<PatientID>) -> Response {
+    tracing::debug!("Got request to view refill requests for patient ID {}", payload.patient_id);
+    let request_id = request_id::from_headers(&headers);
     let mut code = StatusCode::OK;
     let res = match database::init().await {
         Some(conn) => {
             if authenticate(&conn, headers, &payload.patient_id, false).await {
-                let res = conn.view_prev_appointments(payload.patient_id).await;
-                res
+                let access = medical_access(&request_id, payload.patient_id, "patient", "refill.read", None);
+                if conn.record_access(&access, &[payload.patient_id]).await {
+                    conn.view_patient_refills(payload.patient_id).await
+                } else {
+                    code = StatusCode::INTERNAL_SERVER_ERROR;
+                    Vec::new()
+                }
             } else {
                 code = StatusCode::UNAUTHORIZED;
-                let res: Vec<PrevAppointments> = Vec::new();
+                let res: Vec<PatientRefills> = Vec::new();
                 res
             }
         }
         None => {
             code = StatusCode::INTERNAL_SERVER_ERROR;
-            let res: Vec<PrevAppointments> = Vec::new();
+            let res: Vec<PatientRefills> = Vec::new();
             res
         }
     };
@@ -246,14 +675,86 @@ async fn prevapp(headers: HeaderMap, Json(payload): Json<PatientID>) -> Response
     (code, Json(res)).into_response()
 }
 
-async fn doctors(Json(payload): Json<City>) -> Response {
-    tracing::debug!("Got request to view doctors in city {}", payload.city);
+async fn refill_approve(headers: HeaderMap, Json(payload): Json<RefillDecision>) -> Response {
+    tracing::debug!("Got request to approve refill ID {}", payload.refill_id);
+    let request_id = request_id::from_headers(&headers);
     let mut code = StatusCode::OK;
     let res = match database::init().await {
-        Some(conn) => conn.view_same_city_doctors(payload.city).await,
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await {
+                let Some(patient_id) = conn.refill_patient(payload.refill_id).await else {
+                    return (StatusCode::BAD_REQUEST, Json("Error while approving refill")).into_response();
+                };
+                let access = medical_access(&request_id, payload.doctor_id, "doctor", "refill.approve", Some(payload.refill_id));
+                if !record_attempt(&conn, &access, &[patient_id]).await {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while approving refill")).into_response();
+                }
+                let done = conn.approve_refill(payload.doctor_id, payload.refill_id).await;
+                record_outcome(&conn, &access, &[patient_id], done).await;
+                match done {
+                    true => "Approved",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while approving refill"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while approving refill"
+            }
+        }
         None => {
             code = StatusCode::INTERNAL_SERVER_ERROR;
-            let res: Vec<DoctorInfo> = Vec::new();
+            "Error while approving refill"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn refill_decline(headers: HeaderMap, Json(payload): Json<RefillDecision>) -> Response {
+    tracing::debug!("Got request to decline refill ID {}", payload.refill_id);
+    let request_id = request_id::from_headers(&headers);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await {
+                let Some(patient_id) = conn.refill_patient(payload.refill_id).await else {
+                    return (StatusCode::BAD_REQUEST, Json("Error while declining refill")).into_response();
+                };
+                let access = medical_access(&request_id, payload.doctor_id, "doctor", "refill.decline", Some(payload.refill_id));
+                if !record_attempt(&conn, &access, &[patient_id]).await {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while declining refill")).into_response();
+                }
+                let done = conn.decline_refill(payload.doctor_id, payload.refill_id, &payload.reason).await;
+                record_outcome(&conn, &access, &[patient_id], done).await;
+                match done {
+                    true => "Declined",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while declining refill"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while declining refill"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while declining refill"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn drugs_search(payload: Query<DrugSearch>) -> Response {
+    tracing::debug!("Got request to search drugs starting with {}", payload.q);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => conn.search_drugs(&payload.q).await,
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<Drugs> = Vec::new();
             res
         }
     };
@@ -263,60 +764,1587 @@ async fn doctors(Json(payload): Json<City>) -> Response {
     (code, Json(res)).into_response()
 }
 
+async fn patient_allergies(headers: HeaderMap, Json(payload): Json<PatientID>) -> Response {
+    tracing::debug!("Got request to view allergies for patient ID {}", payload.patient_id);
+    let request_id = request_id::from_headers(&headers);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.patient_id, false).await {
+                let access = medical_access(&request_id, payload.patient_id, "patient", "allergy.read", None);
+                if conn.record_access(&access, &[payload.patient_id]).await {
+                    conn.view_allergies(payload.patient_id).await
+                } else {
+                    code = StatusCode::INTERNAL_SERVER_ERROR;
+                    Vec::new()
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                let res: Vec<Allergies> = Vec::new();
+                res
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<Allergies> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
 
-async fn doctor_curtoken(Json(payload): Json<DoctorDate>) -> Response {
-    tracing::debug!("Got request to get current ongoing token for doctor ID {}", payload.doctor_id);
+async fn patient_allergies_add(headers: HeaderMap, Json(payload): Json<PatientAllergen>) -> Response {
+    tracing::debug!("Got request to add allergy for patient ID {}", payload.patient_id);
+    let request_id = request_id::from_headers(&headers);
     let mut code = StatusCode::OK;
     let res = match database::init().await {
-        Some(conn) => conn.view_current_token(payload.doctor_id, &payload.date).await,
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.patient_id, false).await {
+                let access = medical_access(&request_id, payload.patient_id, "patient", "allergy.create", None);
+                if !record_attempt(&conn, &access, &[payload.patient_id]).await {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while inserting")).into_response();
+                }
+                let done = conn.add_allergy(payload.patient_id, &payload.allergen).await;
+                record_outcome(&conn, &access, &[payload.patient_id], done).await;
+                match done {
+                    true => "Inserted",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while inserting"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while inserting"
+            }
+        }
         None => {
-            code = StatusCode::BAD_REQUEST;
-            TokenNumberPrimary {
-                num: 0,
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while inserting"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn patient_allergies_remove(headers: HeaderMap, Json(payload): Json<PatientAllergyID>) -> Response {
+    tracing::debug!("Got request to remove allergy for patient ID {}", payload.patient_id);
+    let request_id = request_id::from_headers(&headers);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.patient_id, false).await {
+                let access = medical_access(&request_id, payload.patient_id, "patient", "allergy.delete", Some(payload.allergy_id));
+                if !record_attempt(&conn, &access, &[payload.patient_id]).await {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while removing")).into_response();
+                }
+                let done = conn.remove_allergy(payload.patient_id, payload.allergy_id).await;
+                record_outcome(&conn, &access, &[payload.patient_id], done).await;
+                match done {
+                    true => "Removed",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while removing"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while removing"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while removing"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+//doctors, receptionists and admins who read or changed the patient's health data
+async fn patient_accesslog(headers: HeaderMap, Json(payload): Json<AccessLogFilter>) -> Response {
+    tracing::debug!("Got request to view record accesses for patient ID {}", payload.patient_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.patient_id, false).await {
+                conn.view_record_accesses(payload.patient_id, payload.limit).await
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                Vec::new()
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<RecordAccesses> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+//everything kept about the patient as one JSON file
+async fn patient_export(headers: HeaderMap, Json(payload): Json<PatientID>) -> Response {
+    tracing::debug!("Got request to export data of patient ID {}", payload.patient_id);
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while exporting")).into_response();
+    };
+    let request_id = request_id::from_headers(&headers);
+    if !authenticate(&conn, headers, &payload.patient_id, false).await {
+        return (StatusCode::UNAUTHORIZED, Json("Error while exporting")).into_response();
+    }
+    let access = medical_access(&request_id, payload.patient_id, "patient", "patient.export", None);
+    if !conn.record_access(&access, &[payload.patient_id]).await {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while exporting")).into_response();
+    }
+    match conn.export_patient(payload.patient_id).await {
+        Some(export) => (
+            StatusCode::OK,
+            [(CONTENT_DISPOSITION, format!("attachment; filename=\"patient-{}.json\"", payload.patient_id))],
+            Json(export),
+        )
+            .into_response(),
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while exporting")).into_response(),
+    }
+}
+
+//closes the account for good, the records doctors have to keep stay but no longer say who the patient was
+async fn patient_delete(headers: HeaderMap, Json(payload): Json<AccountDeletion>) -> Response {
+    tracing::debug!("Got request to delete account of patient ID {}", payload.patient_id);
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while removing")).into_response();
+    };
+    let request_id = request_id::from_headers(&headers);
+    let sid = match authenticated(&conn, &headers).await {
+        Some(jwt) if jwt.role == "patient" && jwt.id == payload.patient_id => jwt.sid,
+        _ => None,
+    };
+    let Some(sid) = sid else {
+        return (StatusCode::UNAUTHORIZED, Json("Error while removing")).into_response();
+    };
+    let access = medical_access(&request_id, payload.patient_id, "patient", "patient.delete", None);
+    if !record_attempt(&conn, &access, &[payload.patient_id]).await {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while removing")).into_response();
+    }
+    let done = conn
+        .delete_patient_account(sid, payload.patient_id, payload.password.as_deref(), payload.code.as_deref())
+        .await;
+    record_outcome(&conn, &access, &[payload.patient_id], done).await;
+    if !done {
+        return (StatusCode::BAD_REQUEST, Json("Error while removing")).into_response();
+    }
+    (StatusCode::OK, Json("Removed")).into_response()
+}
+
+async fn emergency_appointments(headers: HeaderMap, Json(payload): Json<DoctorDate>) -> Response {
+    tracing::debug!(
+        "Got request to view emergency appointments for doctor ID {}",
+        payload.doctor_id
+    );
+    let request_id = request_id::from_headers(&headers);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await {
+                let res = conn.view_doctor_emergencies(payload.doctor_id, &payload.date).await;
+                let mut patients: Vec<i64> = res.iter().map(|app| app.patient_id as i64).collect();
+                patients.sort_unstable();
+                patients.dedup();
+                let access = medical_access(&request_id, payload.doctor_id, "doctor", "emergency.read", None);
+                if conn.record_access(&access, &patients).await {
+                    res
+                } else {
+                    code = StatusCode::INTERNAL_SERVER_ERROR;
+                    Vec::new()
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                let res: Vec<EmergencyAppointments> = Vec::new();
+                res
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<EmergencyAppointments> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+
+
+async fn doctorappointments(headers: HeaderMap, Json(payload): Json<PatientID>) -> Response {
+    tracing::debug!(
+        "Got request to view appointments for doctor ID {}",
+        payload.patient_id
+    );
+    let request_id = request_id::from_headers(&headers);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.patient_id, true).await {
+                let res = conn.view_doctor_appointments(payload.patient_id).await;
+                let mut patients: Vec<i64> = res.iter().map(|app| app.patient_id as i64).collect();
+                patients.sort_unstable();
+                patients.dedup();
+                let access = medical_access(&request_id, payload.patient_id, "doctor", "appointment.read", None);
+                if conn.record_access(&access, &patients).await {
+                    res
+                } else {
+                    code = StatusCode::INTERNAL_SERVER_ERROR;
+                    Vec::new()
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                let res: Vec<DoctorAppointments> = Vec::new();
+                res
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<DoctorAppointments> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn prevapp(headers: HeaderMap, Json(payload): Json<PatientID>) -> Response {
+    tracing::debug!(
+        "Got request to view previous appointments for patient ID {}",
+        payload.patient_id
+    );
+    let request_id = request_id::from_headers(&headers);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.patient_id, false).await {
+                let access = medical_access(&request_id, payload.patient_id, "patient", "appointment.read", None);
+                if conn.record_access(&access, &[payload.patient_id]).await {
+                    conn.view_prev_appointments(payload.patient_id).await
+                } else {
+                    code = StatusCode::INTERNAL_SERVER_ERROR;
+                    Vec::new()
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                let res: Vec<PrevAppointments> = Vec::new();
+                res
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<PrevAppointments> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn doctors(Json(payload): Json<City>) -> Response {
+    tracing::debug!("Got request to view doctors in city {}", payload.city);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => conn.view_same_city_doctors(payload.city).await,
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<DoctorInfo> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+
+async fn doctor_profile(Path(doctor_id): Path<i64>) -> Response {
+    tracing::debug!("Got request to view profile of doctor ID {}", doctor_id);
+    match database::init().await {
+        Some(conn) => match conn.view_doctor_profile(doctor_id).await {
+            Some(profile) => (StatusCode::OK, Json(profile)).into_response(),
+            None => (StatusCode::BAD_REQUEST, Json("No such doctor")).into_response(),
+        },
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while fetching profile")).into_response(),
+    }
+}
+
+async fn doctor_update(headers: HeaderMap, Json(payload): Json<DoctorProfileInput>) -> Response {
+    tracing::debug!("Got request to update profile of doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await {
+                match conn.update_doctor_profile(&payload).await {
+                    true => "Updated",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while updating"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while updating"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while updating"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn doctor_specialities(payload: Query<DoctorIDQuery>) -> Response {
+    tracing::debug!("Got request to view specialities of doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => conn.view_doctor_specialities(payload.doctor_id).await,
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<Specialities> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn doctor_specialities_add(headers: HeaderMap, Json(payload): Json<DoctorSpeciality>) -> Response {
+    tracing::debug!("Got request to add a speciality for doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await {
+                match conn.add_doctor_speciality(payload.doctor_id, payload.speciality_id).await {
+                    true => "Added",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while adding"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while adding"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while adding"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn doctor_specialities_remove(headers: HeaderMap, Json(payload): Json<DoctorSpeciality>) -> Response {
+    tracing::debug!("Got request to remove a speciality for doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await {
+                match conn.remove_doctor_speciality(payload.doctor_id, payload.speciality_id).await {
+                    true => "Removed",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while removing"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while removing"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while removing"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn doctor_prices_set(headers: HeaderMap, Json(payload): Json<AppointmentPrice>) -> Response {
+    tracing::debug!("Got request to set an appointment price for doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await {
+                let updated = match payload.price.filter(|price| *price >= 0) {
+                    Some(price) => conn.set_appointment_price(payload.doctor_id, payload.apptype, price).await,
+                    None => false,
+                };
+                match updated {
+                    true => "Updated",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while updating"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while updating"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while updating"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn doctor_prices_remove(headers: HeaderMap, Json(payload): Json<AppointmentPrice>) -> Response {
+    tracing::debug!("Got request to remove an appointment price for doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await {
+                match conn.remove_appointment_price(payload.doctor_id, payload.apptype).await {
+                    true => "Removed",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while removing"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while removing"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while removing"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn doctor_curtoken(Json(payload): Json<DoctorClinicDate>) -> Response {
+    tracing::debug!("Got request to get current ongoing token for doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => conn.view_current_token(payload.doctor_id, payload.clinic_id, &payload.date).await,
+        None => {
+            code = StatusCode::BAD_REQUEST;
+            TokenNumberPrimary {
+                num: 0,
+            }
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn patient_token(Json(payload): Json<DoctorPatientDate>) -> Response {
+    tracing::debug!("Got request to get token booked for patient ID {}", payload.patient_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => conn.get_patient_token(payload.doctor_id, payload.patient_id, &payload.date).await,
+        None => {
+            code = StatusCode::BAD_REQUEST;
+            TokenNumber {
+                num: 0,
+            }
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn doctor_newtoken(Json(payload): Json<DoctorClinicDate>) -> Response {
+    tracing::debug!("Got request to predict new token for doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => conn.view_new_token(payload.doctor_id, payload.clinic_id, &payload.date).await,
+        None => {
+            code = StatusCode::BAD_REQUEST;
+            TokenNumberPrimary {
+                num: 0,
+            }
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn doctor_timeslots(Json(payload): Json<DoctorDate>) -> Response {
+    tracing::debug!("Got request to view timeslots for doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => conn.view_doctor_timeslots(payload.doctor_id, &payload.date).await,
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<Timeslots> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn patient(headers: HeaderMap, Json(payload): Json<PatientID>) -> Response {
+    tracing::debug!(
+        "Got request to view patient info corresponding to patient ID {}",
+        payload.patient_id
+    );
+    let request_id = request_id::from_headers(&headers);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.patient_id, false).await {
+                let access = medical_access(&request_id, payload.patient_id, "patient", "patient_info.read", None);
+                if conn.record_access(&access, &[payload.patient_id]).await {
+                    conn.view_patient_info(payload.patient_id).await
+                } else {
+                    code = StatusCode::INTERNAL_SERVER_ERROR;
+                    Vec::new()
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                let res: Vec<PatientInfo> = Vec::new();
+                res
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<PatientInfo> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn patient_update(headers: HeaderMap, Json(payload): Json<PatientInfoInput>) -> Response {
+    tracing::debug!(
+        "Got request to update patient info corresponding to patient ID {}",
+        payload.patient_id
+    );
+    let request_id = request_id::from_headers(&headers);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.patient_id, false).await {
+                let access = medical_access(&request_id, payload.patient_id, "patient", "patient_info.update", None);
+                if !record_attempt(&conn, &access, &[payload.patient_id]).await {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response();
+                }
+                let done = conn.update_patient(payload.patient_id, &payload.gender, payload.weight, payload.age, &payload.blood_group).await;
+                record_outcome(&conn, &access, &[payload.patient_id], done).await;
+                match done {
+                    true => "Updated",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while updating"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while updating"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while updating"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+
+async fn emergency_find(payload: Query<CityApptype>) -> Response {
+    tracing::debug!(
+        "Got request to view all doctors with appointment type {} in city {} in emergency",
+        payload.apptype,
+        payload.city
+    );
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            conn.view_doctor_prices_emergency(&payload.city, &payload.apptype)
+                .await
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<DoctorPrices> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn find(payload: Query<FindFilter>) -> Response {
+    tracing::debug!(
+        "Got request to view all doctors with appointment type {} in city {}",
+        payload.apptype,
+        payload.city
+    );
+    let Some(conn) = database::init().await else {
+        let res: Vec<FindResults> = Vec::new();
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(res)).into_response();
+    };
+    let Some(page) = conn.view_doctor_prices(&payload).await else {
+        let res: Vec<FindResults> = Vec::new();
+        return (StatusCode::BAD_REQUEST, Json(res)).into_response();
+    };
+    let mut code = StatusCode::OK;
+    if page.results.is_empty() {
+        code = StatusCode::BAD_REQUEST;
+    }
+    let mut headers = HeaderMap::new();
+    headers.insert(TOTAL_COUNT, page.total.into());
+    if let Some(cursor) = page.next_cursor.and_then(|c| HeaderValue::from_str(&c).ok()) {
+        headers.insert(NEXT_CURSOR, cursor);
+    }
+    (code, headers, Json(page.results)).into_response()
+}
+
+async fn search(payload: Query<SearchText>) -> Response {
+    tracing::debug!("Got request to search doctors for {}", payload.q);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => conn.search_doctors(&payload.q).await,
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<DoctorSearch> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn review_new(headers: HeaderMap, Json(payload): Json<NewReview>) -> Response {
+    tracing::debug!("Got request to add review from patient ID {}", payload.patient_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.patient_id, false).await {
+                match conn.add_review(&payload).await {
+                    true => "Inserted",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while inserting"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while inserting"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while inserting"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn review_reply(headers: HeaderMap, Json(payload): Json<ReviewResponse>) -> Response {
+    tracing::debug!("Got request to reply to review ID {}", payload.review_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await {
+                match conn.reply_review(payload.doctor_id, payload.review_id, &payload.text).await {
+                    true => "Updated",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while updating"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while updating"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while updating"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn review_flag(headers: HeaderMap, Json(payload): Json<ReviewResponse>) -> Response {
+    tracing::debug!("Got request to flag review ID {}", payload.review_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await {
+                match conn.flag_review(payload.doctor_id, payload.review_id, &payload.text).await {
+                    true => "Flagged",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while flagging"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while flagging"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while flagging"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn doctor_reviews(payload: Query<DoctorIDQuery>) -> Response {
+    tracing::debug!("Got request to view reviews for doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => conn.view_doctor_reviews(payload.doctor_id).await,
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<Reviews> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn nextavailable(payload: Query<NextAvailableFilter>) -> Response {
+    tracing::debug!("Got request to find earliest free slots in city {}", payload.city);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => conn.view_next_available(&payload).await,
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<NextAvailable> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn nearby(payload: Query<NearbyFilter>) -> Response {
+    tracing::debug!(
+        "Got request to view doctors near {}, {}",
+        payload.lat, payload.lng
+    );
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => conn.view_nearby_doctors(&payload).await,
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<NearbyDoctors> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn doctor_clinics(payload: Query<DoctorIDQuery>) -> Response {
+    tracing::debug!("Got request to view clinics of doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => conn.view_doctor_clinics(payload.doctor_id).await,
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<Clinics> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn doctor_documents_upload(headers: HeaderMap, Json(payload): Json<DoctorDocumentUpload>) -> Response {
+    tracing::debug!("Got request to upload {} document for doctor ID {}", payload.kind, payload.doctor_id);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate(&conn, headers, &payload.doctor_id, true).await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while inserting")).into_response();
+            }
+            if conn.add_doctor_document(&payload).await {
+                tracing::debug!("Record inserted successfully");
+                (StatusCode::OK, Json("Inserted")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Error while inserting")).into_response()
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while inserting")).into_response(),
+    }
+}
+
+async fn doctor_documents(headers: HeaderMap, Json(payload): Json<DoctorID>) -> Response {
+    tracing::debug!("Got request to view documents of doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await {
+                conn.view_doctor_documents(payload.doctor_id).await
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                Vec::new()
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<DoctorDocuments> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn doctor_verification(headers: HeaderMap, Json(payload): Json<DoctorID>) -> Response {
+    tracing::debug!("Got request to view verification status of doctor ID {}", payload.doctor_id);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate(&conn, headers, &payload.doctor_id, true).await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while fetching")).into_response();
+            }
+            match conn.view_verification_status(payload.doctor_id).await {
+                Some(status) => (StatusCode::OK, Json(status)).into_response(),
+                None => (StatusCode::BAD_REQUEST, Json("Error while fetching")).into_response(),
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while fetching")).into_response(),
+    }
+}
+
+async fn clinic_new(headers: HeaderMap, Json(payload): Json<NewClinic>) -> Response {
+    tracing::debug!("Got request to add a clinic for doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await {
+                match conn.add_clinic(&payload).await {
+                    true => "Inserted",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while inserting"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while inserting"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while inserting"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn clinic_update(headers: HeaderMap, Json(payload): Json<ClinicUpdate>) -> Response {
+    tracing::debug!("Got request to update a clinic for doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await {
+                match conn.update_clinic(&payload).await {
+                    true => "Updated",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while updating"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while updating"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while updating"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn doctor_clinics_add(headers: HeaderMap, Json(payload): Json<DoctorClinic>) -> Response {
+    tracing::debug!("Got request to join a clinic for doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await {
+                match conn.request_clinic(payload.doctor_id, payload.clinic_id).await {
+                    true => "Requested",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while requesting"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while requesting"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while requesting"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn clinic_requests(headers: HeaderMap, Json(payload): Json<DoctorID>) -> Response {
+    tracing::debug!("Got request to view clinic requests for owner doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await {
+                conn.view_clinic_requests(Some(payload.doctor_id)).await
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                Vec::new()
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<ClinicRequests> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn clinic_requests_decide(headers: HeaderMap, Json(payload): Json<ClinicRequestDecision>) -> Response {
+    tracing::debug!(
+        "Got request to set approved = {} for doctor ID {} at clinic ID {}",
+        payload.approved, payload.requester_id, payload.clinic_id
+    );
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate(&conn, headers, &payload.doctor_id, true).await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+            }
+            if conn.decide_clinic_request(&payload).await {
+                tracing::debug!("Record updated successfully");
+                (StatusCode::OK, Json("Updated")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response()
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response(),
+    }
+}
+
+async fn doctor_clinics_remove(headers: HeaderMap, Json(payload): Json<DoctorClinic>) -> Response {
+    tracing::debug!("Got request to leave a clinic for doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate(&conn, headers, &payload.doctor_id, true).await {
+                match conn.remove_doctor_clinic(payload.doctor_id, payload.clinic_id).await {
+                    true => "Removed",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while removing"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while removing"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while removing"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn receptionist_new(headers: HeaderMap, Json(payload): Json<NewReceptionist>) -> Response {
+    tracing::debug!("Got request to add receptionist for clinic ID {}", payload.clinic_id);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate(&conn, headers, &payload.doctor_id, true).await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while inserting")).into_response();
+            }
+            let res = conn.add_receptionist(&payload).await;
+            signup_response(&conn, res, &payload.email).await
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while inserting")).into_response(),
+    }
+}
+
+async fn reception_patient_new(headers: HeaderMap, Json(payload): Json<WalkInPatient>) -> Response {
+    tracing::debug!("Got request to register walk-in patient from receptionist ID {}", payload.receptionist_id);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate_role(&conn, headers, &payload.receptionist_id, "receptionist").await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while inserting")).into_response();
+            }
+            match conn.add_walkin_patient(&payload).await {
+                Some(patient) => (StatusCode::OK, Json(patient)).into_response(),
+                None => (StatusCode::BAD_REQUEST, Json("Error while inserting")).into_response(),
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while inserting")).into_response(),
+    }
+}
+
+async fn reception_patient_search(headers: HeaderMap, Json(payload): Json<ReceptionistPhone>) -> Response {
+    tracing::debug!("Got request to look up patients by phone from receptionist ID {}", payload.receptionist_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate_role(&conn, headers, &payload.receptionist_id, "receptionist").await {
+                conn.find_patients_by_phone(&payload.phone).await
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                Vec::new()
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<PatientContact> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn reception_token(headers: HeaderMap, Json(payload): Json<ReceptionBooking>) -> Response {
+    tracing::debug!("Got request to issue a token from receptionist ID {}", payload.receptionist_id);
+    let request_id = request_id::from_headers(&headers);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate_role(&conn, headers, &payload.receptionist_id, "receptionist").await {
+                let access = medical_access(&request_id, payload.receptionist_id, "receptionist", "token.create", None);
+                if !record_attempt(&conn, &access, &[payload.patient_id]).await {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while inserting")).into_response();
+                }
+                let booked = match conn.receptionist_clinic(payload.receptionist_id).await {
+                    Some(clinic_id) => {
+                        conn.add_new_token(
+                            payload.doctor_id,
+                            clinic_id,
+                            payload.patient_id,
+                            payload.apptype,
+                            &payload.date,
+                            &payload.symptom,
+                        )
+                        .await
+                    }
+                    None => false,
+                };
+                record_outcome(&conn, &access, &[payload.patient_id], booked).await;
+                match booked {
+                    true => "Inserted",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while inserting"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while inserting"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while inserting"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn reception_emergency(headers: HeaderMap, Json(payload): Json<ReceptionBooking>) -> Response {
+    tracing::debug!("Got request to add an emergency from receptionist ID {}", payload.receptionist_id);
+    let request_id = request_id::from_headers(&headers);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate_role(&conn, headers, &payload.receptionist_id, "receptionist").await {
+                let access = medical_access(&request_id, payload.receptionist_id, "receptionist", "emergency.create", None);
+                if !record_attempt(&conn, &access, &[payload.patient_id]).await {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while inserting")).into_response();
+                }
+                //emergencies aren't tied to a clinic but the doctor has to work at this one
+                let booked = match conn.receptionist_clinic(payload.receptionist_id).await {
+                    Some(clinic_id) => {
+                        conn.doctor_at_clinic(payload.doctor_id, clinic_id).await
+                            && conn
+                                .add_new_emergency_app(
+                                    payload.doctor_id,
+                                    payload.patient_id,
+                                    payload.apptype,
+                                    &payload.date,
+                                    &payload.symptom,
+                                )
+                                .await
+                    }
+                    None => false,
+                };
+                record_outcome(&conn, &access, &[payload.patient_id], booked).await;
+                match booked {
+                    true => "Inserted",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while inserting"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while inserting"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while inserting"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn reception_checkin(headers: HeaderMap, Json(payload): Json<CheckIn>) -> Response {
+    tracing::debug!("Got request to check in a patient from receptionist ID {}", payload.receptionist_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate_role(&conn, headers, &payload.receptionist_id, "receptionist").await {
+                match conn.check_in(payload.receptionist_id, &payload.source, payload.source_id).await {
+                    true => "Checked in",
+                    false => {
+                        code = StatusCode::BAD_REQUEST;
+                        "Error while checking in"
+                    }
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                "Error while checking in"
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            "Error while checking in"
+        }
+    };
+    (code, Json(res)).into_response()
+}
+
+async fn reception_queue(headers: HeaderMap, Json(payload): Json<ReceptionistDoctorDate>) -> Response {
+    tracing::debug!("Got request to view token queue of doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate_role(&conn, headers, &payload.receptionist_id, "receptionist").await {
+                conn.view_token_queue(payload.receptionist_id, payload.doctor_id, &payload.date).await
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                Vec::new()
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<TokenQueue> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn reception_next(headers: HeaderMap, Json(payload): Json<ReceptionistDoctorDate>) -> Response {
+    tracing::debug!("Got request to call next token of doctor ID {}", payload.doctor_id);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate_role(&conn, headers, &payload.receptionist_id, "receptionist").await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+            }
+            match conn
+                .advance_token_queue(payload.receptionist_id, payload.doctor_id, &payload.date)
+                .await
+            {
+                Some(next) => (StatusCode::OK, Json(next)).into_response(),
+                None => (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response(),
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response(),
+    }
+}
+
+async fn admin_speciality_new(headers: HeaderMap, Json(payload): Json<NewSpeciality>) -> Response {
+    tracing::debug!("Got request to add speciality {}", payload.name);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while inserting")).into_response();
+            }
+            if conn.add_speciality(&payload).await {
+                tracing::debug!("Record inserted successfully");
+                (StatusCode::OK, Json("Inserted")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Error while inserting")).into_response()
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while inserting")).into_response(),
+    }
+}
+
+async fn admin_speciality_update(headers: HeaderMap, Json(payload): Json<SpecialityUpdate>) -> Response {
+    tracing::debug!("Got request to update speciality ID {}", payload.id);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+            }
+            if conn.update_speciality(&payload).await {
+                tracing::debug!("Record updated successfully");
+                (StatusCode::OK, Json("Updated")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response()
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response(),
+    }
+}
+
+async fn admin_speciality_delete(headers: HeaderMap, Json(payload): Json<AdminItem>) -> Response {
+    tracing::debug!("Got request to delete speciality ID {}", payload.id);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while removing")).into_response();
+            }
+            if conn.delete_speciality(payload.admin_id, payload.id).await {
+                tracing::debug!("Record removed successfully");
+                (StatusCode::OK, Json("Removed")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Error while removing")).into_response()
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while removing")).into_response(),
+    }
+}
+
+async fn admin_apptype_new(headers: HeaderMap, Json(payload): Json<NewApptype>) -> Response {
+    tracing::debug!("Got request to add appointment type {}", payload.name);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while inserting")).into_response();
+            }
+            if conn.add_apptype(&payload).await {
+                tracing::debug!("Record inserted successfully");
+                (StatusCode::OK, Json("Inserted")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Error while inserting")).into_response()
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while inserting")).into_response(),
+    }
+}
+
+async fn admin_apptype_update(headers: HeaderMap, Json(payload): Json<ApptypeUpdate>) -> Response {
+    tracing::debug!("Got request to update appointment type ID {}", payload.id);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+            }
+            if conn.update_apptype(&payload).await {
+                tracing::debug!("Record updated successfully");
+                (StatusCode::OK, Json("Updated")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response()
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response(),
+    }
+}
+
+async fn admin_apptype_delete(headers: HeaderMap, Json(payload): Json<AdminItem>) -> Response {
+    tracing::debug!("Got request to delete appointment type ID {}", payload.id);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while removing")).into_response();
+            }
+            if conn.delete_apptype(payload.admin_id, payload.id).await {
+                tracing::debug!("Record removed successfully");
+                (StatusCode::OK, Json("Removed")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Error while removing")).into_response()
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while removing")).into_response(),
+    }
+}
+
+async fn admin_doctors_pending(headers: HeaderMap, Json(payload): Json<AdminID>) -> Response {
+    tracing::debug!("Got request to view doctors pending verification from admin ID {}", payload.admin_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                conn.view_pending_doctors().await
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                Vec::new()
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<PendingDoctors> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn admin_doctors_documents(headers: HeaderMap, Json(payload): Json<AdminDoctor>) -> Response {
+    tracing::debug!("Got request to view documents of doctor ID {}", payload.doctor_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                conn.view_doctor_documents(payload.doctor_id).await
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                Vec::new()
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<DoctorDocuments> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn admin_doctors_document(headers: HeaderMap, Json(payload): Json<AdminItem>) -> Response {
+    tracing::debug!("Got request to download document ID {}", payload.id);
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while fetching")).into_response();
+    };
+    if !authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+        return (StatusCode::UNAUTHORIZED, Json("Error while fetching")).into_response();
+    }
+    match conn.view_document_file(payload.id).await {
+        Some(file) => (
+            StatusCode::OK,
+            [
+                (CONTENT_TYPE, file.content_type),
+                (CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file.filename.replace('"', ""))),
+            ],
+            file.content,
+        )
+            .into_response(),
+        None => (StatusCode::BAD_REQUEST, Json("No such document")).into_response(),
+    }
+}
+
+async fn admin_doctors_verify(headers: HeaderMap, Json(payload): Json<VerificationDecision>) -> Response {
+    tracing::debug!("Got request to set approved = {} for doctor ID {}", payload.approved, payload.doctor_id);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+            }
+            if conn.set_doctor_verification(&payload).await {
+                tracing::debug!("Record updated successfully");
+                (StatusCode::OK, Json("Updated")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response()
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response(),
+    }
+}
+
+async fn admin_users(headers: HeaderMap, Json(payload): Json<UserFilter>) -> Response {
+    tracing::debug!("Got request to list users from admin ID {}", payload.admin_id);
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => {
+            if authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                conn.view_users(payload.role.as_deref()).await
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                Vec::new()
+            }
+        }
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<Users> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn admin_users_disable(headers: HeaderMap, Json(payload): Json<UserStatus>) -> Response {
+    tracing::debug!("Got request to set disabled = {} for {}", payload.disabled, payload.email);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+            }
+            if conn.set_user_disabled(payload.admin_id, &payload.email, payload.disabled).await {
+                tracing::debug!("Record updated successfully");
+                (StatusCode::OK, Json("Updated")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response()
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response(),
+    }
+}
+
+async fn admin_users_require2fa(headers: HeaderMap, Json(payload): Json<TwoFactorRequirement>) -> Response {
+    tracing::debug!("Got request to set 2FA required = {} for {}", payload.required, payload.email);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+            }
+            if conn.set_totp_required(payload.admin_id, &payload.email, payload.required).await {
+                tracing::debug!("Record updated successfully");
+                (StatusCode::OK, Json("Updated")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response()
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response(),
+    }
+}
+
+async fn admin_users_reset2fa(headers: HeaderMap, Json(payload): Json<AdminEmail>) -> Response {
+    tracing::debug!("Got request to reset 2FA for {}", payload.email);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+            }
+            if conn.reset_totp(payload.admin_id, &payload.email).await {
+                tracing::debug!("Record updated successfully");
+                (StatusCode::OK, Json("Updated")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response()
             }
         }
-    };
-    (code, Json(res)).into_response()
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response(),
+    }
 }
 
-async fn patient_token(Json(payload): Json<DoctorPatientDate>) -> Response {
-    tracing::debug!("Got request to get token booked for patient ID {}", payload.patient_id);
-    let mut code = StatusCode::OK;
-    let res = match database::init().await {
-        Some(conn) => conn.get_patient_token(payload.doctor_id, payload.patient_id, &payload.date).await,
-        None => {
-            code = StatusCode::BAD_REQUEST;
-            TokenNumber {
-                num: 0,
+async fn admin_users_resetpassword(headers: HeaderMap, Json(payload): Json<PasswordReset>) -> Response {
+    tracing::debug!("Got request to reset password for {}", payload.email);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+            }
+            if conn.reset_password(payload.admin_id, &payload.email, &payload.password).await {
+                tracing::debug!("Record updated successfully");
+                (StatusCode::OK, Json("Updated")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response()
             }
         }
-    };
-    (code, Json(res)).into_response()
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response(),
+    }
 }
 
-async fn doctor_newtoken(Json(payload): Json<DoctorDate>) -> Response {
-    tracing::debug!("Got request to predict new token for doctor ID {}", payload.doctor_id);
-    let mut code = StatusCode::OK;
-    let res = match database::init().await {
-        Some(conn) => conn.view_new_token(payload.doctor_id, &payload.date).await,
-        None => {
-            code = StatusCode::BAD_REQUEST;
-            TokenNumberPrimary {
-                num: 0,
+async fn admin_users_role(headers: HeaderMap, Json(payload): Json<RoleChange>) -> Response {
+    tracing::debug!("Got request to change role of {} to {}", payload.email, payload.role);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+            }
+            if conn.change_role(payload.admin_id, &payload.email, &payload.role).await {
+                tracing::debug!("Record updated successfully");
+                (StatusCode::OK, Json("Updated")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response()
             }
         }
-    };
-    (code, Json(res)).into_response()
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response(),
+    }
 }
 
-async fn doctor_timeslots(Json(payload): Json<DoctorDate>) -> Response {
-    tracing::debug!("Got request to view timeslots for doctor ID {}", payload.doctor_id);
+async fn admin_appointments(headers: HeaderMap, Json(payload): Json<AppointmentFilter>) -> Response {
+    tracing::debug!("Got request to view appointments from admin ID {}", payload.admin_id);
+    let request_id = request_id::from_headers(&headers);
     let mut code = StatusCode::OK;
     let res = match database::init().await {
-        Some(conn) => conn.view_doctor_timeslots(payload.doctor_id, &payload.date).await,
+        Some(conn) => {
+            if authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                let res = conn.view_all_appointments(&payload).await;
+                let mut patients: Vec<i64> = res.iter().map(|app| app.patient_id as i64).collect();
+                patients.sort_unstable();
+                patients.dedup();
+                let access = medical_access(&request_id, payload.admin_id, "admin", "appointment.read", None);
+                if conn.record_access(&access, &patients).await {
+                    res
+                } else {
+                    code = StatusCode::INTERNAL_SERVER_ERROR;
+                    Vec::new()
+                }
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                Vec::new()
+            }
+        }
         None => {
             code = StatusCode::INTERNAL_SERVER_ERROR;
-            let res: Vec<Timeslots> = Vec::new();
+            let res: Vec<AdminAppointments> = Vec::new();
             res
         }
     };
@@ -326,25 +2354,21 @@ async fn doctor_timeslots(Json(payload): Json<DoctorDate>) -> Response {
     (code, Json(res)).into_response()
 }
 
-async fn patient(headers: HeaderMap, Json(payload): Json<PatientID>) -> Response {
-    tracing::debug!(
-        "Got request to view patient info corresponding to patient ID {}",
-        payload.patient_id
-    );
+async fn admin_reviews_flagged(headers: HeaderMap, Json(payload): Json<AdminID>) -> Response {
+    tracing::debug!("Got request to view flagged reviews from admin ID {}", payload.admin_id);
     let mut code = StatusCode::OK;
     let res = match database::init().await {
         Some(conn) => {
-            if authenticate(&conn, headers, &payload.patient_id, false).await {
-                conn.view_patient_info(payload.patient_id).await
+            if authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                conn.view_flagged_reviews().await
             } else {
                 code = StatusCode::UNAUTHORIZED;
-                let res: Vec<PatientInfo> = Vec::new();
-                res
+                Vec::new()
             }
         }
         None => {
             code = StatusCode::INTERNAL_SERVER_ERROR;
-            let res: Vec<PatientInfo> = Vec::new();
+            let res: Vec<FlaggedReviews> = Vec::new();
             res
         }
     };
@@ -354,51 +2378,57 @@ async fn patient(headers: HeaderMap, Json(payload): Json<PatientID>) -> Response
     (code, Json(res)).into_response()
 }
 
-async fn patient_update(headers: HeaderMap, Json(payload): Json<PatientInfoInput>) -> Response {
-    tracing::debug!(
-        "Got request to update patient info corresponding to patient ID {}",
-        payload.patient_id
-    );
-    let mut code = StatusCode::OK;
-    let res = match database::init().await {
+async fn admin_reviews_hide(headers: HeaderMap, Json(payload): Json<ReviewVisibility>) -> Response {
+    tracing::debug!("Got request to set hidden = {} for review ID {}", payload.hidden, payload.review_id);
+    match database::init().await {
         Some(conn) => {
-            if authenticate(&conn, headers, &payload.patient_id, false).await {
-                match conn.update_patient(payload.patient_id, &payload.gender, payload.weight, payload.age, &payload.blood_group).await {
-                    true => "Updated",
-                    false => {
-                        code = StatusCode::BAD_REQUEST;
-                        "Error while updating"
-                    }
-                }
+            if !authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+            }
+            if conn.set_review_hidden(payload.admin_id, payload.review_id, payload.hidden).await {
+                tracing::debug!("Record updated successfully");
+                (StatusCode::OK, Json("Updated")).into_response()
             } else {
-                code = StatusCode::UNAUTHORIZED;
-                "Error while updating"
+                (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response()
             }
         }
-        None => {
-            code = StatusCode::INTERNAL_SERVER_ERROR;
-            "Error while updating"
-        }
-    };
-    (code, Json(res)).into_response()
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response(),
+    }
 }
 
+async fn admin_clinics_update(headers: HeaderMap, Json(payload): Json<AdminClinicUpdate>) -> Response {
+    tracing::debug!("Got request to update clinic ID {} from admin ID {}", payload.changes.clinic_id, payload.admin_id);
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+            }
+            if conn.admin_update_clinic(&payload).await {
+                tracing::debug!("Record updated successfully");
+                (StatusCode::OK, Json("Updated")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response()
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response(),
+    }
+}
 
-async fn emergency_find(payload: Query<CityApptype>) -> Response {
-    tracing::debug!(
-        "Got request to view all doctors with appointment type {} in city {} in emergency",
-        payload.apptype,
-        payload.city
-    );
+async fn admin_clinics_requests(headers: HeaderMap, Json(payload): Json<AdminID>) -> Response {
+    tracing::debug!("Got request to view clinic requests from admin ID {}", payload.admin_id);
     let mut code = StatusCode::OK;
     let res = match database::init().await {
         Some(conn) => {
-            conn.view_doctor_prices_emergency(&payload.city, &payload.apptype)
-                .await
+            if authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                conn.view_clinic_requests(None).await
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                Vec::new()
+            }
         }
         None => {
             code = StatusCode::INTERNAL_SERVER_ERROR;
-            let res: Vec<DoctorPrices> = Vec::new();
+            let res: Vec<ClinicRequests> = Vec::new();
             res
         }
     };
@@ -408,21 +2438,42 @@ async fn emergency_find(payload: Query<CityApptype>) -> Response {
     (code, Json(res)).into_response()
 }
 
-async fn find(payload: Query<CityApptype>) -> Response {
+async fn admin_clinics_requests_decide(headers: HeaderMap, Json(payload): Json<AdminClinicRequestDecision>) -> Response {
     tracing::debug!(
-        "Got request to view all doctors with appointment type {} in city {}",
-        payload.apptype,
-        payload.city
+        "Got request to set approved = {} for doctor ID {} at clinic ID {}",
+        payload.approved, payload.requester_id, payload.clinic_id
     );
+    match database::init().await {
+        Some(conn) => {
+            if !authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+            }
+            if conn.admin_decide_clinic_request(&payload).await {
+                tracing::debug!("Record updated successfully");
+                (StatusCode::OK, Json("Updated")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response()
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response(),
+    }
+}
+
+async fn admin_audit(headers: HeaderMap, Json(payload): Json<AuditFilter>) -> Response {
+    tracing::debug!("Got request to view audit log from admin ID {}", payload.admin_id);
     let mut code = StatusCode::OK;
     let res = match database::init().await {
         Some(conn) => {
-            conn.view_doctor_prices(&payload.city, &payload.apptype)
-                .await
+            if authenticate_role(&conn, headers, &payload.admin_id, "admin").await {
+                conn.view_audit_log(&payload).await
+            } else {
+                code = StatusCode::UNAUTHORIZED;
+                Vec::new()
+            }
         }
         None => {
             code = StatusCode::INTERNAL_SERVER_ERROR;
-            let res: Vec<DoctorPrices> = Vec::new();
+            let res: Vec<AuditEntries> = Vec::new();
             res
         }
     };
@@ -437,17 +2488,9 @@ async fn newpatient(Json(payload): Json<Patient>) -> Response {
     match database::init().await {
         Some(conn) => {
             let res = conn
-                .add_new_patient(&payload.name, &payload.email, &payload.phone)
-                .await
-                && conn
-                    .register(&payload.email, &payload.password, false)
-                    .await;
-            if res {
-                tracing::debug!("Record inserted successfully");
-                return (StatusCode::OK, Json("Inserted")).into_response();
-            } else {
-                return (StatusCode::BAD_REQUEST, Json("Error while inserting")).into_response();
-            }
+                .add_new_patient(&payload.name, &payload.email, &payload.phone, &payload.password)
+                .await;
+            signup_response(&conn, res, &payload.email).await
         }
         None => {
             return (
@@ -463,28 +2506,10 @@ async fn newdoctor(Json(payload): Json<Doctor>) -> Response {
     tracing::debug!("Got request to insert new doctor info");
     match database::init().await {
         Some(conn) => {
-            let mut res = conn.register(&payload.email, &payload.password, true).await;
-            if !res {
-                tracing::error!("Record could not be inserted successfully");
-                return (StatusCode::BAD_REQUEST, Json("Error while inserting")).into_response();
-            }
-            res = res && conn
-                .add_new_doctor(
-                    &payload.name,
-                    payload.speciality,
-                    &payload.city,
-                    &payload.address,
-                    &payload.email,
-                    &payload.phone,
-                )
+            let res = conn
+                .add_new_doctor(&payload)
                 .await;
-            if res {
-                tracing::debug!("Record inserted successfully");
-                return (StatusCode::OK, Json("Inserted")).into_response();
-            } else {
-                tracing::error!("Record could not be inserted successfully");
-                return (StatusCode::BAD_REQUEST, Json("Error while inserting")).into_response();
-            }
+            signup_response(&conn, res, &payload.email).await
         }
         None => {
             return (
@@ -496,11 +2521,16 @@ async fn newdoctor(Json(payload): Json<Doctor>) -> Response {
     }
 }
 
-async fn newemergency(headers: HeaderMap, Json(payload): Json<Token>) -> Response {
+async fn newemergency(headers: HeaderMap, Json(payload): Json<Emergency>) -> Response {
     tracing::debug!("Got request to insert new emergency info");
+    let request_id = request_id::from_headers(&headers);
     match database::init().await {
         Some(conn) => {
             if authenticate(&conn, headers, &payload.patient_id, false).await {
+                let access = medical_access(&request_id, payload.patient_id, "patient", "emergency.create", None);
+                if !record_attempt(&conn, &access, &[payload.patient_id]).await {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while inserting")).into_response();
+                }
                 let res = conn
                     .add_new_emergency_app(
                         payload.doctor_id,
@@ -510,6 +2540,7 @@ async fn newemergency(headers: HeaderMap, Json(payload): Json<Token>) -> Respons
                         &payload.symptom
                     )
                     .await;
+                record_outcome(&conn, &access, &[payload.patient_id], res).await;
                 if res {
                     tracing::debug!("Record inserted successfully");
                     return (StatusCode::OK, Json("Inserted")).into_response();
@@ -533,18 +2564,25 @@ async fn newemergency(headers: HeaderMap, Json(payload): Json<Token>) -> Respons
 
 async fn newtoken(headers: HeaderMap, Json(payload): Json<Token>) -> Response {
     tracing::debug!("Got request to insert new token info");
+    let request_id = request_id::from_headers(&headers);
     match database::init().await {
         Some(conn) => {
             if authenticate(&conn, headers, &payload.patient_id, false).await {
+                let access = medical_access(&request_id, payload.patient_id, "patient", "token.create", None);
+                if !record_attempt(&conn, &access, &[payload.patient_id]).await {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while inserting")).into_response();
+                }
                 let res = conn
                     .add_new_token(
                         payload.doctor_id,
+                        payload.clinic_id,
                         payload.patient_id,
                         payload.apptype,
                         &payload.date,
                         &payload.symptom
                     )
                     .await;
+                record_outcome(&conn, &access, &[payload.patient_id], res).await;
                 if res {
                     tracing::debug!("Record inserted successfully");
                     return (StatusCode::OK, Json("Inserted")).into_response();
@@ -568,20 +2606,18 @@ async fn newtoken(headers: HeaderMap, Json(payload): Json<Token>) -> Response {
 
 async fn newappointment(headers: HeaderMap, Json(payload): Json<Appointment>) -> Response {
     tracing::debug!("Got request to insert new appointment info");
+    let request_id = request_id::from_headers(&headers);
     match database::init().await {
         Some(conn) => {
             if authenticate(&conn, headers, &payload.patient_id, false).await {
+                let access = medical_access(&request_id, payload.patient_id, "patient", "appointment.create", None);
+                if !record_attempt(&conn, &access, &[payload.patient_id]).await {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while inserting")).into_response();
+                }
                 let res = conn
-                    .add_new_appointment(
-                        payload.doctor_id,
-                        payload.patient_id,
-                        payload.apptype,
-                        &payload.date,
-                        payload.slot_id,
-                        &payload.phyorvirt,
-                        &payload.symptom
-                    )
+                    .add_new_appointment(&payload)
                     .await;
+                record_outcome(&conn, &access, &[payload.patient_id], res).await;
                 if res {
                     tracing::debug!("Record inserted successfully");
                     return (StatusCode::OK, Json("Inserted")).into_response();
@@ -687,17 +2723,349 @@ async fn specialities() -> Response {
     return (code, Json(res)).into_response();
 }
 
-async fn login(Json(payload): Json<Login>) -> Response {
+async fn verify(payload: Query<TokenQuery>) -> Response {
+    tracing::debug!("Got request to verify an email");
+    match database::init().await {
+        Some(conn) => {
+            if conn.verify_email(&payload.token).await {
+                (StatusCode::OK, Json("Verified")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Invalid or expired token")).into_response()
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while verifying")).into_response(),
+    }
+}
+
+//always answers the same so it can't be used to find out which emails are registered
+async fn verify_resend(Json(payload): Json<Email>) -> Response {
+    tracing::debug!("Got request to resend verification mail");
+    match database::init().await {
+        Some(conn) => {
+            send_verification(&conn, &payload.email).await;
+            (StatusCode::OK, Json("Sent")).into_response()
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while sending")).into_response(),
+    }
+}
+
+//same as verify_resend, the answer doesn't say whether the email is registered
+async fn password_forgot(Json(payload): Json<Email>) -> Response {
+    tracing::debug!("Got request to reset a forgotten password");
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while sending")).into_response();
+    };
+    if let Some(token) = conn.create_auth_token(&payload.email, "reset_password").await {
+        let body = format!(
+            "Use this code with /password/reset to choose a new password, it is valid for 1 hour:\n{}\n\nIf you didn't ask for this you can ignore this mail.",
+            token
+        );
+        if !mailer::from_env().send(&payload.email, "Reset your password", &body).await {
+            tracing::error!("Couldn't send password reset mail to {}", payload.email);
+        }
+    }
+    (StatusCode::OK, Json("Sent")).into_response()
+}
+
+async fn password_reset(Json(payload): Json<NewPassword>) -> Response {
+    tracing::debug!("Got request to reset password with a token");
+    match database::init().await {
+        Some(conn) => {
+            if conn.reset_password_with_token(&payload.token, &payload.password).await {
+                (StatusCode::OK, Json("Updated")).into_response()
+            } else {
+                (StatusCode::BAD_REQUEST, Json("Invalid or expired token")).into_response()
+            }
+        }
+        None => (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response(),
+    }
+}
+
+async fn account_password(headers: HeaderMap, Json(payload): Json<PasswordChange>) -> Response {
+    tracing::debug!("Got request to change password");
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response();
+    };
+    let Some(sid) = authenticated(&conn, &headers).await.and_then(|jwt| jwt.sid) else {
+        return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+    };
+    if conn.change_password(sid, &payload.old_password, &payload.new_password).await {
+        (StatusCode::OK, Json("Updated")).into_response()
+    } else {
+        (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response()
+    }
+}
+
+//mails a verification link to the new address, 409 when it is already registered
+async fn account_email(headers: HeaderMap, Json(payload): Json<EmailChange>) -> Response {
+    tracing::debug!("Got request to change email to {}", payload.email);
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response();
+    };
+    let Some(jwt) = authenticated(&conn, &headers).await else {
+        return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+    };
+    match conn.change_email(&jwt, &payload.password, &payload.email).await {
+        Ok(()) => {
+            send_verification(&conn, &payload.email).await;
+            (StatusCode::OK, Json("Updated")).into_response()
+        }
+        Err(database::SignupError::Duplicate) => {
+            (StatusCode::CONFLICT, Json("Email is already registered")).into_response()
+        }
+        Err(database::SignupError::Failed) => {
+            (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response()
+        }
+    }
+}
+
+async fn account_sessions(headers: HeaderMap) -> Response {
+    tracing::debug!("Got request to view sessions");
+    let mut code = StatusCode::OK;
+    let res = match database::init().await {
+        Some(conn) => match authenticated(&conn, &headers).await.and_then(|jwt| jwt.sid) {
+            Some(sid) => conn.view_sessions(sid).await,
+            None => {
+                code = StatusCode::UNAUTHORIZED;
+                Vec::new()
+            }
+        },
+        None => {
+            code = StatusCode::INTERNAL_SERVER_ERROR;
+            let res: Vec<Sessions> = Vec::new();
+            res
+        }
+    };
+    if res.is_empty() && code == StatusCode::OK {
+        code = StatusCode::BAD_REQUEST;
+    }
+    (code, Json(res)).into_response()
+}
+
+async fn account_sessions_revoke(headers: HeaderMap, Json(payload): Json<SessionID>) -> Response {
+    tracing::debug!("Got request to revoke session ID {}", payload.session_id);
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while removing")).into_response();
+    };
+    let Some(sid) = authenticated(&conn, &headers).await.and_then(|jwt| jwt.sid) else {
+        return (StatusCode::UNAUTHORIZED, Json("Error while removing")).into_response();
+    };
+    if conn.revoke_session(sid, payload.session_id).await {
+        (StatusCode::OK, Json("Removed")).into_response()
+    } else {
+        (StatusCode::BAD_REQUEST, Json("Error while removing")).into_response()
+    }
+}
+
+async fn account_sessions_revokeothers(headers: HeaderMap) -> Response {
+    tracing::debug!("Got request to revoke all other sessions");
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while removing")).into_response();
+    };
+    let Some(sid) = authenticated(&conn, &headers).await.and_then(|jwt| jwt.sid) else {
+        return (StatusCode::UNAUTHORIZED, Json("Error while removing")).into_response();
+    };
+    if conn.revoke_other_sessions(sid).await {
+        (StatusCode::OK, Json("Removed")).into_response()
+    } else {
+        (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while removing")).into_response()
+    }
+}
+
+async fn account_2fa(headers: HeaderMap) -> Response {
+    tracing::debug!("Got request to view 2FA status");
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while retrieving")).into_response();
+    };
+    let Some(sid) = authenticated_for_2fa(&conn, &headers).await.and_then(|jwt| jwt.sid) else {
+        return (StatusCode::UNAUTHORIZED, Json("Error while retrieving")).into_response();
+    };
+    match conn.view_two_factor(sid).await {
+        Some(status) => (StatusCode::OK, Json(status)).into_response(),
+        None => (StatusCode::BAD_REQUEST, Json("Error while retrieving")).into_response(),
+    }
+}
+
+async fn account_2fa_setup(headers: HeaderMap) -> Response {
+    tracing::debug!("Got request to set up 2FA");
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while inserting")).into_response();
+    };
+    let Some(sid) = authenticated_for_2fa(&conn, &headers).await.and_then(|jwt| jwt.sid) else {
+        return (StatusCode::UNAUTHORIZED, Json("Error while inserting")).into_response();
+    };
+    match conn.setup_totp(sid).await {
+        Some(setup) => (StatusCode::OK, Json(setup)).into_response(),
+        None => (StatusCode::BAD_REQUEST, Json("Error while inserting")).into_response(),
+    }
+}
+
+//gives the recovery codes, they aren't shown again
+async fn account_2fa_enable(headers: HeaderMap, Json(payload): Json<TotpCode>) -> Response {
+    tracing::debug!("Got request to turn on 2FA");
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response();
+    };
+    let Some(sid) = authenticated_for_2fa(&conn, &headers).await.and_then(|jwt| jwt.sid) else {
+        return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+    };
+    match conn.enable_totp(sid, &payload.code).await {
+        Some(codes) => (StatusCode::OK, Json(codes)).into_response(),
+        None => (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response(),
+    }
+}
+
+async fn account_2fa_disable(headers: HeaderMap, Json(payload): Json<TotpDisable>) -> Response {
+    tracing::debug!("Got request to turn off 2FA");
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response();
+    };
+    let Some(sid) = authenticated(&conn, &headers).await.and_then(|jwt| jwt.sid) else {
+        return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+    };
+    if conn.disable_totp(sid, &payload.password, &payload.code).await {
+        (StatusCode::OK, Json("Updated")).into_response()
+    } else {
+        (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response()
+    }
+}
+
+async fn account_2fa_recoverycodes(headers: HeaderMap, Json(payload): Json<TotpCode>) -> Response {
+    tracing::debug!("Got request for new recovery codes");
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while updating")).into_response();
+    };
+    let Some(sid) = authenticated(&conn, &headers).await.and_then(|jwt| jwt.sid) else {
+        return (StatusCode::UNAUTHORIZED, Json("Error while updating")).into_response();
+    };
+    match conn.regenerate_recovery_codes(sid, &payload.code).await {
+        Some(codes) => (StatusCode::OK, Json(codes)).into_response(),
+        None => (StatusCode::BAD_REQUEST, Json("Error while updating")).into_response(),
+    }
+}
+
+//always "Sent" for a valid number, the code is also what /phone/signup needs so it doesn't matter whether the phone is registered
+async fn phone_otp(
+    Extension(limiter): Extension<Arc<ratelimit::RateLimiter>>,
+    Json(payload): Json<PhoneNumber>,
+) -> Response {
+    tracing::debug!("Got request to text a login code");
+    let Some(phone) = sms::normalize_phone(&payload.phone) else {
+        return (StatusCode::BAD_REQUEST, Json("Error while sending")).into_response();
+    };
+    //texts cost money and bother whoever owns the number, so they're limited per number too
+    if let Err(retry_after) = limiter
+        .take(&format!("sms:{}", phone), ratelimit::SMS_BURST, ratelimit::SMS_PER_SECOND)
+        .await
+    {
+        return ratelimit::too_many_requests(retry_after);
+    }
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while sending")).into_response();
+    };
+    if let Some(code) = conn.create_phone_otp(&phone).await {
+        let body = format!("Your login code is {}, it is valid for 10 minutes. Don't share it with anyone.", code);
+        if !sms::from_env().send(&phone, &body).await {
+            tracing::error!("Couldn't text login code to {}", phone);
+        }
+    }
+    (StatusCode::OK, Json("Sent")).into_response()
+}
+
+async fn phone_login(headers: HeaderMap, Json(payload): Json<PhoneLogin>) -> Response {
+    tracing::debug!("Got request to login with phone");
+    let Some(phone) = sms::normalize_phone(&payload.phone) else {
+        return (StatusCode::BAD_REQUEST, Json("Error while logging in")).into_response();
+    };
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while logging in")).into_response();
+    };
+    let user_agent = headers
+        .get(USER_AGENT)
+        .and_then(|agent| agent.to_str().ok())
+        .unwrap_or("");
+    match conn.phone_login(&phone, &payload.code, user_agent).await {
+        Ok(database::LoginStep::Token(jwt)) => (StatusCode::OK, Json(jwt)).into_response(),
+        Ok(database::LoginStep::Challenge(challenge)) => {
+            (StatusCode::ACCEPTED, Json(TwoFactorChallenge { challenge })).into_response()
+        }
+        Err(database::LoginError::Locked(seconds)) => ratelimit::too_many_requests(seconds as u64),
+        Err(database::LoginError::Invalid) => (StatusCode::BAD_REQUEST, Json("Error while logging in")).into_response(),
+    }
+}
+
+async fn phone_signup(headers: HeaderMap, Json(payload): Json<PhoneSignup>) -> Response {
+    tracing::debug!("Got request to sign up with phone");
+    let Some(phone) = sms::normalize_phone(&payload.phone) else {
+        return (StatusCode::BAD_REQUEST, Json("Error while inserting")).into_response();
+    };
+    if payload.name.trim().is_empty() {
+        return (StatusCode::BAD_REQUEST, Json("Error while inserting")).into_response();
+    }
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while inserting")).into_response();
+    };
+    let user_agent = headers
+        .get(USER_AGENT)
+        .and_then(|agent| agent.to_str().ok())
+        .unwrap_or("");
+    match conn.phone_signup(payload.name.trim(), &phone, &payload.code, user_agent).await {
+        Ok(jwt) => (StatusCode::OK, Json(jwt)).into_response(),
+        Err(database::SignupError::Duplicate) => {
+            (StatusCode::CONFLICT, Json("Phone number is already registered")).into_response()
+        }
+        Err(database::SignupError::Failed) => (StatusCode::BAD_REQUEST, Json("Error while inserting")).into_response(),
+    }
+}
+
+//second step of /login for logins with 2FA on
+async fn login_2fa(headers: HeaderMap, Json(payload): Json<SecondFactor>) -> Response {
+    tracing::debug!("Got request to finish login with second factor");
+    let Some(conn) = database::init().await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json("Error while logging in")).into_response();
+    };
+    let user_agent = headers
+        .get(USER_AGENT)
+        .and_then(|agent| agent.to_str().ok())
+        .unwrap_or("");
+    match conn.login_second_factor(&payload.challenge, &payload.code, user_agent).await {
+        Ok(jwt) => (StatusCode::OK, Json(jwt)).into_response(),
+        Err(database::LoginError::Locked(seconds)) => ratelimit::too_many_requests(seconds as u64),
+        Err(database::LoginError::Invalid) => (StatusCode::BAD_REQUEST, Json("Error while logging in")).into_response(),
+    }
+}
+
+async fn login(
+    Extension(limiter): Extension<Arc<ratelimit::RateLimiter>>,
+    headers: HeaderMap,
+    Json(payload): Json<Login>,
+) -> Response {
     tracing::debug!("Got request to login");
+    //on top of the per IP limit, so spreading guesses over many IPs doesn't help against one account
+    let account = format!("login:{}", payload.email.trim().to_lowercase());
+    if let Err(retry_after) = limiter
+        .take(&account, ratelimit::ACCOUNT_BURST, ratelimit::ACCOUNT_PER_SECOND)
+        .await
+    {
+        return ratelimit::too_many_requests(retry_after);
+    }
     match database::init().await {
         Some(conn) => {
-            let res = conn.login(&payload.email, &payload.password).await;
+            let user_agent = headers
+                .get(USER_AGENT)
+                .and_then(|agent| agent.to_str().ok())
+                .unwrap_or("");
+            let res = conn.login(&payload.email, &payload.password, user_agent).await;
             match res {
-                Some(jwt) => {
+                Ok(database::LoginStep::Token(jwt)) => {
                     tracing::debug!("Generated JWT successfully! {}", jwt);
                     return (StatusCode::OK, Json(jwt)).into_response();
                 }
-                None => {
+                Ok(database::LoginStep::Challenge(challenge)) => {
+                    (StatusCode::ACCEPTED, Json(TwoFactorChallenge { challenge })).into_response()
+                }
+                Err(database::LoginError::Locked(seconds)) => ratelimit::too_many_requests(seconds as u64),
+                Err(database::LoginError::Invalid) => {
                     return (StatusCode::BAD_REQUEST, Json("Error while logging in"))
                         .into_response();
                 }
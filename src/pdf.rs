@@ -0,0 +1,164 @@
+//render documents handed out to patients as PDFs
+use printpdf::{BuiltinFont, IndirectFontRef, Mm, PdfDocument, PdfDocumentReference, PdfLayerReference, Rect};
+use qrcode::{Color, QrCode};
+
+use crate::db_structs::PrescriptionDocument;
+
+const PAGE_WIDTH: f32 = 210.0;
+const PAGE_HEIGHT: f32 = 297.0;
+const MARGIN: f32 = 20.0;
+const LINE_HEIGHT: f32 = 6.0;
+//characters per line for 11pt Helvetica within the margins, roughly
+const WRAP_AT: usize = 85;
+const QR_SIZE: f32 = 35.0;
+
+//splits text on newlines and then on words so every line fits on the page
+fn wrap(text: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            if !line.is_empty() && line.len() + word.len() + 1 > WRAP_AT {
+                lines.push(line);
+                line = String::new();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+//draws the QR code with its bottom left corner at (x, y)
+fn draw_qr(layer: &PdfLayerReference, data: &str, x: f32, y: f32) -> bool {
+    let Ok(qr) = QrCode::new(data.as_bytes()) else {
+        tracing::error!("Couldn't generate QR code for {}", data);
+        return false;
+    };
+    let width = qr.width();
+    let module = QR_SIZE / width as f32;
+    for (i, color) in qr.to_colors().iter().enumerate() {
+        if *color == Color::Dark {
+            let col = (i % width) as f32;
+            let row = (i / width) as f32;
+            let llx = x + col * module;
+            let lly = y + QR_SIZE - (row + 1.0) * module;
+            layer.add_rect(Rect::new(Mm(llx), Mm(lly), Mm(llx + module), Mm(lly + module)));
+        }
+    }
+    true
+}
+
+fn write_line(layer: &PdfLayerReference, font: &IndirectFontRef, text: &str, size: f32, y: &mut f32) {
+    layer.use_text(text, size, Mm(MARGIN), Mm(*y), font);
+    *y -= LINE_HEIGHT;
+}
+
+//pages after the first say whose prescription they continue
+fn continuation_page(
+    pdf: &PdfDocumentReference,
+    font: &IndirectFontRef,
+    doc: &PrescriptionDocument,
+    y: &mut f32,
+) -> PdfLayerReference {
+    let (page, layer) = pdf.add_page(Mm(PAGE_WIDTH), Mm(PAGE_HEIGHT), "Prescription");
+    let layer = pdf.get_page(page).get_layer(layer);
+    *y = PAGE_HEIGHT - MARGIN;
+    write_line(
+        &layer,
+        font,
+        &format!("{} for {}, {} (continued)", doc.docname, doc.patname, doc.date),
+        10.0,
+        y,
+    );
+    *y -= LINE_HEIGHT;
+    layer
+}
+
+pub fn prescription(doc: &PrescriptionDocument, code: &str, verify_url: &str) -> Option<Vec<u8>> {
+    let (pdf, page, layer) = PdfDocument::new(
+        format!("Prescription {}", doc.id),
+        Mm(PAGE_WIDTH),
+        Mm(PAGE_HEIGHT),
+        "Prescription",
+    );
+    let mut layer = pdf.get_page(page).get_layer(layer);
+    let (Ok(regular), Ok(bold)) = (
+        pdf.add_builtin_font(BuiltinFont::Helvetica),
+        pdf.add_builtin_font(BuiltinFont::HelveticaBold),
+    ) else {
+        tracing::error!("Couldn't load fonts for prescription PDF");
+        return None;
+    };
+
+    let mut y = PAGE_HEIGHT - MARGIN;
+    write_line(&layer, &bold, &doc.docname, 16.0, &mut y);
+    if let Some(number) = &doc.registration_number {
+        let council = doc.registration_council.as_deref().unwrap_or("");
+        write_line(&layer, &regular, &format!("Reg. No. {} {}", number, council), 10.0, &mut y);
+    }
+    write_line(&layer, &regular, &format!("{}, {}", doc.address, doc.city), 10.0, &mut y);
+    write_line(&layer, &regular, &format!("Phone: {}", doc.docphone), 10.0, &mut y);
+    y -= LINE_HEIGHT;
+
+    write_line(&layer, &bold, &format!("Patient: {}", doc.patname), 12.0, &mut y);
+    let mut details = Vec::new();
+    if let Some(age) = doc.age {
+        details.push(format!("Age: {}", age));
+    }
+    if let Some(gender) = &doc.gender {
+        details.push(format!("Gender: {}", gender));
+    }
+    if let Some(weight) = doc.weight {
+        details.push(format!("Weight: {} kg", weight));
+    }
+    if !details.is_empty() {
+        write_line(&layer, &regular, &details.join("    "), 10.0, &mut y);
+    }
+    write_line(&layer, &regular, &format!("Date: {}", doc.date), 10.0, &mut y);
+    y -= LINE_HEIGHT;
+
+    write_line(&layer, &bold, "Rx", 14.0, &mut y);
+    //long prescriptions go on over more pages, every page is numbered so a missing one is noticed
+    let mut pages = vec![layer.clone()];
+    for line in wrap(doc.prescription.as_deref().map_or("", String::as_str)) {
+        if y < MARGIN {
+            layer = continuation_page(&pdf, &regular, doc, &mut y);
+            pages.push(layer.clone());
+        }
+        write_line(&layer, &regular, &line, 11.0, &mut y);
+    }
+    //the verification block goes at the bottom of the last page, on a page of its own when there is no room left
+    if y < MARGIN + QR_SIZE + LINE_HEIGHT {
+        layer = continuation_page(&pdf, &regular, doc, &mut y);
+        pages.push(layer.clone());
+    }
+    for (i, page) in pages.iter().enumerate() {
+        let footer = format!("Prescription {}, page {} of {}", doc.id, i + 1, pages.len());
+        page.use_text(footer, 8.0, Mm(MARGIN), Mm(MARGIN / 2.0), &regular);
+    }
+
+    if !draw_qr(&layer, verify_url, MARGIN, MARGIN) {
+        return None;
+    }
+    let mut y = MARGIN + QR_SIZE - LINE_HEIGHT;
+    let textx = MARGIN + QR_SIZE + 5.0;
+    layer.use_text("Verification code", 10.0, Mm(textx), Mm(y), &bold);
+    y -= LINE_HEIGHT;
+    layer.use_text(code, 12.0, Mm(textx), Mm(y), &regular);
+    y -= LINE_HEIGHT;
+    layer.use_text("Scan the QR code or enter the code at", 9.0, Mm(textx), Mm(y), &regular);
+    y -= LINE_HEIGHT;
+    layer.use_text(verify_url, 7.0, Mm(textx), Mm(y), &regular);
+
+    match pdf.save_to_bytes() {
+        Ok(bytes) => Some(bytes),
+        Err(e) => {
+            tracing::error!("Error while saving prescription PDF: {}", e);
+            None
+        }
+    }
+}
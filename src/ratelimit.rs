@@ -0,0 +1,240 @@
+//token bucket rate limiting for the endpoints that can be brute forced; RATE_LIMIT_STORE picks where
+//the buckets live: memory (default) only works for a single instance, postgres shares them through Rate_Limits
+use async_trait::async_trait;
+use axum::{
+    extract::ConnectInfo,
+    http::{header::RETRY_AFTER, HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use dotenvy::dotenv;
+use rand::Rng;
+use sqlx::{postgres::PgPoolOptions, Pool, Postgres, Row};
+use std::collections::HashMap;
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::database::escape;
+
+//requests a single IP can make to the auth endpoints in a burst, and how fast they come back
+pub const IP_BURST: f64 = 20.0;
+pub const IP_PER_SECOND: f64 = 20.0 / 60.0;
+//login attempts for a single account, whichever IPs they come from
+pub const ACCOUNT_BURST: f64 = 10.0;
+pub const ACCOUNT_PER_SECOND: f64 = 1.0 / 30.0;
+//login codes texted to a single phone number
+pub const SMS_BURST: f64 = 3.0;
+pub const SMS_PER_SECOND: f64 = 1.0 / 300.0;
+//the in memory store drops full buckets once it holds this many
+const MAX_MEMORY_BUCKETS: usize = 10_000;
+
+#[async_trait]
+pub trait Store: Send + Sync {
+    //takes a token from the bucket at key, or says how many seconds until one is there
+    async fn take(&self, key: &str, burst: f64, per_second: f64) -> Result<(), u64>;
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+pub struct MemoryStore {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl MemoryStore {
+    fn take_at(&self, key: &str, burst: f64, per_second: f64, now: Instant) -> Result<(), u64> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_MEMORY_BUCKETS {
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated_at).as_secs_f64() * per_second < burst
+            });
+        }
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: burst,
+            updated_at: now,
+        });
+        bucket.tokens =
+            (bucket.tokens + now.duration_since(bucket.updated_at).as_secs_f64() * per_second).min(burst);
+        bucket.updated_at = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(retry_after(bucket.tokens, per_second))
+        }
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn take(&self, key: &str, burst: f64, per_second: f64) -> Result<(), u64> {
+        self.take_at(key, burst, per_second, Instant::now())
+    }
+}
+
+pub struct PostgresStore {
+    connection: Pool<Postgres>,
+}
+
+#[async_trait]
+impl Store for PostgresStore {
+    async fn take(&self, key: &str, burst: f64, per_second: f64) -> Result<(), u64> {
+        //refilled and taken from in one statement so instances racing on a bucket can't both get the last token
+        let refilled = format!(
+            "least({burst}, rate_limits.tokens + extract(epoch from clock_timestamp() - rate_limits.updated_at) * {per_second})"
+        );
+        let query = format!(
+            "
+                insert into rate_limits(key, tokens, allowed) values ('{}', {burst} - 1, true)
+                on conflict (key) do update set
+                tokens = case when {refilled} >= 1 then {refilled} - 1 else {refilled} end,
+                allowed = {refilled} >= 1,
+                updated_at = clock_timestamp()
+                returning tokens, allowed;
+            ",
+            escape(key)
+        );
+        if rand::thread_rng().gen_ratio(1, 100) {
+            let _ = sqlx::query("delete from rate_limits where updated_at < clock_timestamp() - interval '1 day';")
+                .execute(&self.connection)
+                .await;
+        }
+        match sqlx::query(&query).fetch_one(&self.connection).await {
+            Ok(row) => {
+                let tokens: f64 = row.try_get("tokens").unwrap_or(0.0);
+                if row.try_get("allowed").unwrap_or(true) {
+                    Ok(())
+                } else {
+                    Err(retry_after(tokens, per_second))
+                }
+            }
+            Err(e) => {
+                //an outage of the store shouldn't lock everyone out, the login lockout still applies
+                tracing::error!("Error while rate limiting {}: {}", key, e);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn retry_after(tokens: f64, per_second: f64) -> u64 {
+    ((1.0 - tokens) / per_second).ceil().max(1.0) as u64
+}
+
+pub struct RateLimiter {
+    store: Box<dyn Store>,
+    //TRUST_FORWARDED_FOR, take the client IP from X-Forwarded-For when running behind a proxy
+    trust_forwarded_for: bool,
+}
+
+impl RateLimiter {
+    pub async fn take(&self, key: &str, burst: f64, per_second: f64) -> Result<(), u64> {
+        self.store.take(key, burst, per_second).await
+    }
+
+    fn client_ip(&self, headers: &HeaderMap, peer: SocketAddr) -> IpAddr {
+        if self.trust_forwarded_for {
+            let forwarded = headers
+                .get("x-forwarded-for")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(',').next())
+                .and_then(|ip| ip.trim().parse().ok());
+            if let Some(ip) = forwarded {
+                return ip;
+            }
+        }
+        peer.ip()
+    }
+}
+
+pub async fn from_env() -> Arc<RateLimiter> {
+    dotenv().ok();
+    let store: Box<dyn Store> = match env::var("RATE_LIMIT_STORE").as_deref() {
+        Ok("postgres") => match env::var("DATABASE_URL").map(|url| PgPoolOptions::new().connect_lazy(&url)) {
+            Ok(Ok(pool)) => Box::new(PostgresStore { connection: pool }),
+            _ => {
+                tracing::error!("Couldn't set up the postgres rate limit store, keeping buckets in memory");
+                Box::new(MemoryStore { buckets: Mutex::new(HashMap::new()) })
+            }
+        },
+        _ => Box::new(MemoryStore { buckets: Mutex::new(HashMap::new()) }),
+    };
+    Arc::new(RateLimiter {
+        store,
+        trust_forwarded_for: matches!(env::var("TRUST_FORWARDED_FOR").as_deref(), Ok("true") | Ok("1")),
+    })
+}
+
+pub fn too_many_requests(retry_after: u64) -> Response {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(RETRY_AFTER, retry_after.to_string())],
+        Json("Too many requests, try again later"),
+    )
+        .into_response()
+}
+
+//middleware for the auth routes, limits each client IP across all of them
+pub async fn limit_by_ip<B>(
+    Extension(limiter): Extension<Arc<RateLimiter>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let ip = limiter.client_ip(request.headers(), peer);
+    match limiter.take(&format!("ip:{}", ip), IP_BURST, IP_PER_SECOND).await {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            tracing::debug!("Rate limited {}", ip);
+            too_many_requests(retry_after)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn store() -> MemoryStore {
+        MemoryStore { buckets: Mutex::new(HashMap::new()) }
+    }
+
+    #[test]
+    fn burst_is_allowed_then_limited() {
+        let (store, now) = (store(), Instant::now());
+        for _ in 0..3 {
+            assert_eq!(store.take_at("a", 3.0, 1.0 / 30.0, now), Ok(()));
+        }
+        assert_eq!(store.take_at("a", 3.0, 1.0 / 30.0, now), Err(30));
+        //buckets are per key
+        assert_eq!(store.take_at("b", 3.0, 1.0 / 30.0, now), Ok(()));
+    }
+
+    #[test]
+    fn tokens_come_back_over_time_up_to_the_burst() {
+        let (store, now) = (store(), Instant::now());
+        store.take_at("a", 2.0, 1.0 / 30.0, now).unwrap();
+        store.take_at("a", 2.0, 1.0 / 30.0, now).unwrap();
+        //half a token back, the other half takes 15 more seconds
+        assert_eq!(store.take_at("a", 2.0, 1.0 / 30.0, now + Duration::from_secs(15)), Err(15));
+        assert_eq!(store.take_at("a", 2.0, 1.0 / 30.0, now + Duration::from_secs(30)), Ok(()));
+        //a long wait refills no more than the burst
+        let later = now + Duration::from_secs(3600);
+        assert_eq!(store.take_at("a", 2.0, 1.0 / 30.0, later), Ok(()));
+        assert_eq!(store.take_at("a", 2.0, 1.0 / 30.0, later), Ok(()));
+        assert_eq!(store.take_at("a", 2.0, 1.0 / 30.0, later), Err(30));
+    }
+
+    #[test]
+    fn retry_after_is_at_least_a_second() {
+        assert_eq!(retry_after(0.0, 1.0 / 300.0), 300);
+        assert_eq!(retry_after(0.9, 1.0 / 30.0), 3);
+        assert_eq!(retry_after(0.999, 20.0 / 60.0), 1);
+    }
+}
@@ -0,0 +1,53 @@
+//every request gets an ID, taken from X-Request-Id when the client or a proxy already set a sane one,
+//so the log lines and audit log entries it caused can be found together
+use axum::{
+    http::{
+        header::{HeaderMap, HeaderName, HeaderValue},
+        Request,
+    },
+    middleware::Next,
+    response::Response,
+};
+use rand::{rngs::OsRng, RngCore};
+use tracing::Instrument;
+
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+const MAX_LENGTH: usize = 64;
+const GENERATED_BYTES: usize = 16;
+
+fn acceptable(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_LENGTH && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+//handlers read it back from their headers with from_headers, the response carries it too
+pub async fn assign<B>(mut request: Request<B>, next: Next<B>) -> Response {
+    let given = request
+        .headers()
+        .get(REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| acceptable(id))
+        .map(String::from);
+    let id = given.unwrap_or_else(|| {
+        let mut bytes = [0u8; GENERATED_BYTES];
+        OsRng.fill_bytes(&mut bytes);
+        hex::encode(bytes)
+    });
+    let Ok(value) = HeaderValue::from_str(&id) else {
+        return next.run(request).await;
+    };
+    request.headers_mut().insert(REQUEST_ID, value.clone());
+    let mut response = next
+        .run(request)
+        .instrument(tracing::info_span!("request", id = %id))
+        .await;
+    response.headers_mut().insert(REQUEST_ID, value);
+    response
+}
+
+pub fn from_headers(headers: &HeaderMap) -> String {
+    headers
+        .get(REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("")
+        .to_string()
+}
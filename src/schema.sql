@@ -1,6 +1,9 @@
 -- - set timezone
 SET TIMEZONE TO 'Asia/Kolkata';
 
+-- - trigram matching for typo tolerant doctor search
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
 -- - info about various specialities
 CREATE TABLE IF NOT EXISTS Specialities (
     id BIGSERIAL PRIMARY KEY ,
@@ -12,14 +15,121 @@ CREATE TABLE IF NOT EXISTS Specialities (
 CREATE TABLE IF NOT EXISTS Doctors (
     id BIGSERIAL PRIMARY KEY,
     name VARCHAR(255) NOT NULL,
-    speciality_id INT NOT NULL,
     city VARCHAR(255) NOT NULL,
     address VARCHAR(255) NOT NULL,
     email VARCHAR(255) NOT NULL UNIQUE,
     phone VARCHAR(255) NOT NULL,
-    FOREIGN KEY (speciality_id) REFERENCES Specialities(id)
+    registration_number VARCHAR(255),
+    registration_council VARCHAR(255),
+    gender CHAR(1),
+    languages VARCHAR(255)[] NOT NULL DEFAULT '{}',
+    consultation_modes VARCHAR(255)[] NOT NULL DEFAULT '{physical}',
+    qualifications TEXT,
+    years_of_experience INT,
+    bio TEXT,
+    photo_url VARCHAR(255),
+    -- new doctors stay out of search, booking and prescribing until an admin verifies their documents
+    verification_status VARCHAR(255) NOT NULL DEFAULT 'pending_verification',
+    verification_notes TEXT,
+    verified_at TIMESTAMP,
+    CONSTRAINT chk_modes CHECK (consultation_modes <@ ARRAY['physical', 'virtual']::VARCHAR(255)[]),
+    CONSTRAINT chk_experience CHECK (years_of_experience >= 0),
+    CONSTRAINT chk_verification CHECK (verification_status IN ('pending_verification', 'verified', 'rejected'))
+);
+
+-- columns added after the table was first created, for databases set up before them
+ALTER TABLE Doctors ADD COLUMN IF NOT EXISTS registration_number VARCHAR(255);
+ALTER TABLE Doctors ADD COLUMN IF NOT EXISTS registration_council VARCHAR(255);
+ALTER TABLE Doctors ADD COLUMN IF NOT EXISTS gender CHAR(1);
+ALTER TABLE Doctors ADD COLUMN IF NOT EXISTS languages VARCHAR(255)[] NOT NULL DEFAULT '{}';
+ALTER TABLE Doctors ADD COLUMN IF NOT EXISTS consultation_modes VARCHAR(255)[] NOT NULL DEFAULT '{physical}'
+    CHECK (consultation_modes <@ ARRAY['physical', 'virtual']::VARCHAR(255)[]);
+ALTER TABLE Doctors ADD COLUMN IF NOT EXISTS qualifications TEXT;
+ALTER TABLE Doctors ADD COLUMN IF NOT EXISTS years_of_experience INT CHECK (years_of_experience >= 0);
+ALTER TABLE Doctors ADD COLUMN IF NOT EXISTS bio TEXT;
+ALTER TABLE Doctors ADD COLUMN IF NOT EXISTS photo_url VARCHAR(255);
+
+-- doctors that signed up before verification existed are treated as verified
+ALTER TABLE Doctors ADD COLUMN IF NOT EXISTS verification_status VARCHAR(255) NOT NULL DEFAULT 'verified'
+    CHECK (verification_status IN ('pending_verification', 'verified', 'rejected'));
+ALTER TABLE Doctors ALTER COLUMN verification_status SET DEFAULT 'pending_verification';
+ALTER TABLE Doctors ADD COLUMN IF NOT EXISTS verification_notes TEXT;
+ALTER TABLE Doctors ADD COLUMN IF NOT EXISTS verified_at TIMESTAMP;
+
+-- - license/registration documents uploaded by doctors for verification
+CREATE TABLE IF NOT EXISTS Doctor_Documents (
+    id BIGSERIAL PRIMARY KEY,
+    doctor_id INT NOT NULL,
+    kind VARCHAR(255) NOT NULL,
+    filename VARCHAR(255) NOT NULL,
+    content_type VARCHAR(255) NOT NULL,
+    content BYTEA NOT NULL,
+    uploaded_at TIMESTAMP NOT NULL DEFAULT now(),
+    FOREIGN KEY (doctor_id) REFERENCES Doctors(id)
+);
+
+CREATE INDEX IF NOT EXISTS doctor_documents_doctor ON Doctor_Documents (doctor_id);
+
+CREATE INDEX IF NOT EXISTS doctors_name_trgm ON Doctors USING GIN (name gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS doctors_city_trgm ON Doctors USING GIN (city gin_trgm_ops);
+
+-- - places doctors practise at, a doctor can work at more than one and in more than one city
+CREATE TABLE IF NOT EXISTS Clinics (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    address VARCHAR(255) NOT NULL,
+    city VARCHAR(255) NOT NULL,
+    latitude DOUBLE PRECISION,
+    longitude DOUBLE PRECISION,
+    -- - free form, like "Mon-Sat 09:00-18:00"
+    hours VARCHAR(255),
+    phone VARCHAR(255),
+    -- - the doctor who added it, only they (or an admin) can edit it and let other doctors join
+    owner_id INT REFERENCES Doctors(id),
+    CONSTRAINT chk_coordinates CHECK (latitude BETWEEN -90 AND 90 AND longitude BETWEEN -180 AND 180)
+);
+
+CREATE INDEX IF NOT EXISTS clinics_city ON Clinics (city);
+CREATE INDEX IF NOT EXISTS clinics_location ON Clinics (latitude, longitude);
+
+CREATE TABLE IF NOT EXISTS Doctor_Clinics (
+    doctor_id INT NOT NULL,
+    clinic_id INT NOT NULL,
+    FOREIGN KEY (doctor_id) REFERENCES Doctors(id),
+    FOREIGN KEY (clinic_id) REFERENCES Clinics(id),
+    PRIMARY KEY (doctor_id, clinic_id)
+);
+
+ALTER TABLE Clinics ADD COLUMN IF NOT EXISTS owner_id INT REFERENCES Doctors(id);
+
+-- - doctors asking to practise at a clinic, approving one moves it to Doctor_Clinics
+CREATE TABLE IF NOT EXISTS Clinic_Requests (
+    doctor_id INT NOT NULL,
+    clinic_id INT NOT NULL,
+    requested_at TIMESTAMP NOT NULL DEFAULT now(),
+    FOREIGN KEY (doctor_id) REFERENCES Doctors(id),
+    FOREIGN KEY (clinic_id) REFERENCES Clinics(id),
+    PRIMARY KEY (doctor_id, clinic_id)
 );
 
+-- - specialities a doctor practises, a doctor can have more than one
+CREATE TABLE IF NOT EXISTS Doctor_Specialities (
+    doctor_id INT NOT NULL,
+    speciality_id INT NOT NULL,
+    FOREIGN KEY (doctor_id) REFERENCES Doctors(id),
+    FOREIGN KEY (speciality_id) REFERENCES Specialities(id),
+    PRIMARY KEY (doctor_id, speciality_id)
+);
+
+-- - doctors used to have a single speciality_id column, move it over on databases created before that
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM information_schema.columns WHERE table_name = 'doctors' AND column_name = 'speciality_id') THEN
+        INSERT INTO Doctor_Specialities (doctor_id, speciality_id) SELECT id, speciality_id FROM Doctors ON CONFLICT DO NOTHING;
+        ALTER TABLE Doctors DROP COLUMN speciality_id;
+    END IF;
+END $$;
+
 -- - doctor and emergency stuff, this is mostly beta rn
 CREATE TABLE IF NOT EXISTS Doctors_Emergency (
     id BIGSERIAL PRIMARY KEY,
@@ -49,32 +159,98 @@ CREATE TABLE IF NOT EXISTS Appointment_Prices (
 );
 
 -- - info about patients
+-- - email is left out for walk-ins registered at the front desk
 CREATE TABLE IF NOT EXISTS Patients (
     id BIGSERIAL PRIMARY KEY,
     name VARCHAR(255) NOT NULL,
-    email VARCHAR(255) NOT NULL UNIQUE,
+    email VARCHAR(255) UNIQUE,
     phone VARCHAR(255) NOT NULL,
-    gender CHAR(1),
-    weight INT,
-    age INT,
-    blood_group VARCHAR(255)
+    gender TEXT,
+    weight TEXT,
+    age TEXT,
+    blood_group TEXT,
+    -- - set when the patient deleted their account, the row is kept anonymized for the records pointing at it
+    deleted_at TIMESTAMP
 );
 
+ALTER TABLE Patients ALTER COLUMN email DROP NOT NULL;
+ALTER TABLE Patients ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP;
+-- - health fields are sealed by the backend (see src/crypto.rs), which doesn't fit their old types
+ALTER TABLE Patients ALTER COLUMN gender TYPE TEXT, ALTER COLUMN weight TYPE TEXT USING weight::TEXT,
+    ALTER COLUMN age TYPE TEXT USING age::TEXT, ALTER COLUMN blood_group TYPE TEXT;
+
 CREATE TABLE IF NOT EXISTS Prescriptions (
     id BIGSERIAL PRIMARY KEY,
     patient_id INT NOT NULL,
     doctor_id INT NOT NULL,
     prescription TEXT,
     appointment_date TIMESTAMP NOT NULL,
+    refills_allowed INT NOT NULL DEFAULT 0,
+    parent_id INT,
     FOREIGN KEY (patient_id) REFERENCES Patients(id),
-    FOREIGN KEY (doctor_id) REFERENCES Doctors(id)
+    FOREIGN KEY (doctor_id) REFERENCES Doctors(id),
+    FOREIGN KEY (parent_id) REFERENCES Prescriptions(id)
+);
+
+ALTER TABLE Prescriptions ADD COLUMN IF NOT EXISTS refills_allowed INT NOT NULL DEFAULT 0;
+ALTER TABLE Prescriptions ADD COLUMN IF NOT EXISTS parent_id INT REFERENCES Prescriptions(id);
+
+-- - patients asking for more of an existing prescription without booking an appointment,
+-- - approving one creates a new prescription with parent_id pointing at the original
+CREATE TABLE IF NOT EXISTS Refill_Requests (
+    id BIGSERIAL PRIMARY KEY,
+    prescription_id INT NOT NULL,
+    patient_id INT NOT NULL,
+    doctor_id INT NOT NULL,
+    status VARCHAR(255) NOT NULL,
+    note TEXT,
+    reason TEXT,
+    new_prescription_id INT,
+    requested_at TIMESTAMP NOT NULL DEFAULT now(),
+    decided_at TIMESTAMP,
+    FOREIGN KEY (prescription_id) REFERENCES Prescriptions(id),
+    FOREIGN KEY (patient_id) REFERENCES Patients(id),
+    FOREIGN KEY (doctor_id) REFERENCES Doctors(id),
+    FOREIGN KEY (new_prescription_id) REFERENCES Prescriptions(id),
+    CONSTRAINT chk_status CHECK (status IN ('pending', 'approved', 'declined'))
+);
+
+-- - local drug catalog doctors pick medications from, imported from CSV
+CREATE TABLE IF NOT EXISTS Drugs (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL UNIQUE,
+    generic_name VARCHAR(255) NOT NULL,
+    drug_class VARCHAR(255),
+    form VARCHAR(255),
+    strength VARCHAR(255)
+);
+
+-- - known interactions, keyed by generic name so every brand of a drug is covered
+CREATE TABLE IF NOT EXISTS Drug_Interactions (
+    generic_a VARCHAR(255) NOT NULL,
+    generic_b VARCHAR(255) NOT NULL,
+    severity VARCHAR(255) NOT NULL,
+    description TEXT NOT NULL,
+    PRIMARY KEY (generic_a, generic_b),
+    CONSTRAINT chk_severity CHECK (severity IN ('minor', 'moderate', 'major'))
+);
+
+-- - drugs from the catalog that a prescription contains
+CREATE TABLE IF NOT EXISTS Prescription_Drugs (
+    prescription_id INT NOT NULL,
+    drug_id INT NOT NULL,
+    FOREIGN KEY (prescription_id) REFERENCES Prescriptions(id),
+    FOREIGN KEY (drug_id) REFERENCES Drugs(id),
+    PRIMARY KEY (prescription_id, drug_id)
 );
 
 -- - stores slots the doctor sets
 CREATE TABLE IF NOT EXISTS Doctor_Slots (
     id BIGSERIAL PRIMARY KEY,
     doctor_id INT NOT NULL,
-    time_start TIMESTAMPTZ NOT NULL
+    clinic_id INT NOT NULL,
+    time_start TIMESTAMPTZ NOT NULL,
+    FOREIGN KEY (clinic_id) REFERENCES Clinics(id)
 );
 
 -- - help doctors keep track of their appointments with patients
@@ -86,9 +262,10 @@ CREATE TABLE IF NOT EXISTS Appointments (
     appointment_date TIMESTAMP NOT NULL,
     slot_id INT NOT NULL,
     status VARCHAR(255) NOT NULL,
-    symptom VARCHAR(255) NOT NULL,
+    symptom TEXT NOT NULL,
     prescription_id INT,
     type VARCHAR(255) NOT NULL,
+    checked_in_at TIMESTAMP,
     FOREIGN KEY (doctor_id) REFERENCES Doctors(id),
     FOREIGN KEY (patient_id) REFERENCES Patients(id),
     FOREIGN KEY (appointment_type) REFERENCES Appointment_Types(id),
@@ -105,18 +282,52 @@ CREATE TABLE IF NOT EXISTS Tokens (
     patient_id INT NOT NULL,
     appointment_type INT NOT NULL,
     appointment_date TIMESTAMP NOT NULL,
+    clinic_id INT NOT NULL,
     token_number INT NOT NULL,
     status VARCHAR(255) NOT NULL,
     prescription_id INT,
-    symptom VARCHAR(255) NOT NULL,
+    symptom TEXT NOT NULL,
+    checked_in_at TIMESTAMP,
     FOREIGN KEY (doctor_id) REFERENCES Doctors(id),
     FOREIGN KEY (patient_id) REFERENCES Patients(id),
     FOREIGN KEY (appointment_type) REFERENCES Appointment_Types(id),
+    FOREIGN KEY (clinic_id) REFERENCES Clinics(id),
     FOREIGN KEY (prescription_id) REFERENCES Prescriptions(id),
     CONSTRAINT chk_status CHECK (status IN ('scheduled', 'fulfilled', 'cancelled', 'ongoing'))
 );
 
-ALTER TABLE Tokens ADD CONSTRAINT unique_token_per_day_doctor UNIQUE (doctor_id, token_number, appointment_date);
+-- - doctors used to have their address and coordinates baked in, databases created before clinics
+-- - get one clinic per doctor with their slots and tokens moved to it
+DO $$
+DECLARE
+    doc RECORD;
+    clinic BIGINT;
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM information_schema.columns WHERE table_name = 'doctor_slots' AND column_name = 'clinic_id') THEN
+        ALTER TABLE Doctor_Slots ADD COLUMN IF NOT EXISTS clinic_id INT REFERENCES Clinics(id);
+        ALTER TABLE Tokens ADD COLUMN IF NOT EXISTS clinic_id INT REFERENCES Clinics(id);
+        -- - coordinates only existed for a while, older databases get clinics without them
+        ALTER TABLE Doctors ADD COLUMN IF NOT EXISTS latitude DOUBLE PRECISION, ADD COLUMN IF NOT EXISTS longitude DOUBLE PRECISION;
+        FOR doc IN SELECT id, name, address, city, latitude, longitude FROM Doctors LOOP
+            INSERT INTO Clinics (name, address, city, latitude, longitude, owner_id)
+            VALUES (doc.name, doc.address, doc.city, doc.latitude, doc.longitude, doc.id) RETURNING id INTO clinic;
+            INSERT INTO Doctor_Clinics (doctor_id, clinic_id) VALUES (doc.id, clinic);
+            UPDATE Doctor_Slots SET clinic_id = clinic WHERE doctor_id = doc.id;
+            UPDATE Tokens SET clinic_id = clinic WHERE doctor_id = doc.id;
+        END LOOP;
+        ALTER TABLE Doctor_Slots ALTER COLUMN clinic_id SET NOT NULL;
+        ALTER TABLE Tokens ALTER COLUMN clinic_id SET NOT NULL;
+        ALTER TABLE Tokens DROP CONSTRAINT IF EXISTS unique_token_per_day_doctor;
+        ALTER TABLE Doctors DROP COLUMN latitude, DROP COLUMN longitude;
+    END IF;
+END $$;
+
+-- - clinics added before owners existed belong to the first doctor that practised there
+UPDATE Clinics c SET owner_id = (SELECT min(doctor_id) FROM Doctor_Clinics dc WHERE dc.clinic_id = c.id) WHERE owner_id IS NULL;
+
+-- - every clinic the doctor sits at has its own token queue
+ALTER TABLE Tokens DROP CONSTRAINT IF EXISTS unique_token_per_day_doctor;
+ALTER TABLE Tokens ADD CONSTRAINT unique_token_per_day_doctor UNIQUE (doctor_id, clinic_id, token_number, appointment_date);
 
 -- - help keep track of emergency appointments
 CREATE TABLE IF NOT EXISTS Emergency_Appointments (
@@ -127,15 +338,58 @@ CREATE TABLE IF NOT EXISTS Emergency_Appointments (
     appointment_date TIMESTAMP NOT NULL,
     emergency_no INT NOT NULL,
     prescription_id INT,
-    symptom VARCHAR(255) NOT NULL,
+    symptom TEXT NOT NULL,
     FOREIGN KEY (doctor_id) REFERENCES Doctors(id),
     FOREIGN KEY (patient_id) REFERENCES Patients(id),
     FOREIGN KEY (appointment_type) REFERENCES Appointment_Types(id),
     FOREIGN KEY (prescription_id) REFERENCES Prescriptions(id)
 );
 
+ALTER TABLE Appointments ADD COLUMN IF NOT EXISTS checked_in_at TIMESTAMP;
+ALTER TABLE Tokens ADD COLUMN IF NOT EXISTS checked_in_at TIMESTAMP;
+
+ALTER TABLE Appointments ALTER COLUMN symptom TYPE TEXT;
+ALTER TABLE Tokens ALTER COLUMN symptom TYPE TEXT;
+ALTER TABLE Emergency_Appointments ALTER COLUMN symptom TYPE TEXT;
+
+ALTER TABLE Emergency_Appointments DROP CONSTRAINT IF EXISTS unique_emergency_per_day_doctor;
 ALTER TABLE Emergency_Appointments ADD CONSTRAINT unique_emergency_per_day_doctor UNIQUE (doctor_id, emergency_no, appointment_date);
 
+-- - one rating and review per fulfilled appointment or token, doctors can reply and flag reviews
+-- - for moderation, hidden ones are left out of listings and averages
+CREATE TABLE IF NOT EXISTS Reviews (
+    id BIGSERIAL PRIMARY KEY,
+    doctor_id INT NOT NULL,
+    patient_id INT NOT NULL,
+    appointment_id INT UNIQUE,
+    token_id INT UNIQUE,
+    rating INT NOT NULL,
+    review TEXT,
+    reply TEXT,
+    flagged BOOLEAN NOT NULL DEFAULT false,
+    flag_reason TEXT,
+    hidden BOOLEAN NOT NULL DEFAULT false,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    replied_at TIMESTAMP,
+    FOREIGN KEY (doctor_id) REFERENCES Doctors(id),
+    FOREIGN KEY (patient_id) REFERENCES Patients(id),
+    FOREIGN KEY (appointment_id) REFERENCES Appointments(id),
+    FOREIGN KEY (token_id) REFERENCES Tokens(id),
+    CONSTRAINT chk_rating CHECK (rating BETWEEN 1 AND 5),
+    CONSTRAINT chk_visit CHECK ((appointment_id IS NULL) <> (token_id IS NULL))
+);
+
+CREATE INDEX IF NOT EXISTS reviews_doctor ON Reviews (doctor_id);
+
+-- - allergies recorded by the patient, matched against drug names, generic names and classes
+CREATE TABLE IF NOT EXISTS Patient_Allergies (
+    id BIGSERIAL PRIMARY KEY,
+    patient_id INT NOT NULL,
+    allergen VARCHAR(255) NOT NULL,
+    FOREIGN KEY (patient_id) REFERENCES Patients(id),
+    UNIQUE (patient_id, allergen)
+);
+
 -- - keep track of notifications to deliver
 CREATE TABLE IF NOT EXISTS Notifications (
     id BIGSERIAL PRIMARY KEY ,
@@ -148,8 +402,175 @@ CREATE TABLE IF NOT EXISTS Notifications (
 -- - keep login info here
 CREATE TABLE IF NOT EXISTS Login (
     id BIGSERIAL PRIMARY KEY,
-    email VARCHAR(255) NOT NULL UNIQUE,
-    password VARCHAR(255) NOT NULL,
+    -- - patients who signed up with their phone number have neither an email nor a password
+    email VARCHAR(255) UNIQUE,
+    password VARCHAR(255),
     isdoctor BOOLEAN,
-    SALT VARCHAR(255) NOT NULL UNIQUE
+    SALT VARCHAR(255) UNIQUE,
+    phone VARCHAR(32) UNIQUE,
+    role VARCHAR(255) NOT NULL DEFAULT 'patient',
+    disabled BOOLEAN NOT NULL DEFAULT false,
+    email_verified BOOLEAN NOT NULL DEFAULT false,
+    -- - wrong passwords in a row, past LOCKOUT_AFTER of them logins are refused until locked_until
+    failed_logins INT NOT NULL DEFAULT 0,
+    locked_until TIMESTAMP,
+    -- - base32 TOTP secret, set up with /account/2fa/setup and only asked for once totp_enabled
+    totp_secret VARCHAR(255),
+    totp_enabled BOOLEAN NOT NULL DEFAULT false,
+    -- - set by an admin, the login can't do anything but set up 2FA until it has
+    totp_required BOOLEAN NOT NULL DEFAULT false,
+    -- - time step of the last code used, so a code can't be used twice
+    totp_last_step BIGINT,
+    -- - the profile of the role, admins have no profile table and their ID is the ID of this row
+    patient_id INT UNIQUE,
+    doctor_id INT UNIQUE,
+    FOREIGN KEY (patient_id) REFERENCES Patients(id),
+    FOREIGN KEY (doctor_id) REFERENCES Doctors(id),
+    CONSTRAINT chk_role CHECK (role IN ('patient', 'doctor', 'receptionist', 'admin'))
+);
+
+-- - logins created before roles only had isdoctor
+ALTER TABLE Login ADD COLUMN IF NOT EXISTS role VARCHAR(255) NOT NULL DEFAULT 'patient';
+ALTER TABLE Login ADD COLUMN IF NOT EXISTS disabled BOOLEAN NOT NULL DEFAULT false;
+-- - logins from before email verification aren't locked out
+ALTER TABLE Login ADD COLUMN IF NOT EXISTS email_verified BOOLEAN NOT NULL DEFAULT true;
+ALTER TABLE Login ALTER COLUMN email_verified SET DEFAULT false;
+ALTER TABLE Login ADD COLUMN IF NOT EXISTS failed_logins INT NOT NULL DEFAULT 0;
+ALTER TABLE Login ADD COLUMN IF NOT EXISTS locked_until TIMESTAMP;
+ALTER TABLE Login ADD COLUMN IF NOT EXISTS totp_secret VARCHAR(255);
+ALTER TABLE Login ADD COLUMN IF NOT EXISTS totp_enabled BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE Login ADD COLUMN IF NOT EXISTS totp_required BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE Login ADD COLUMN IF NOT EXISTS totp_last_step BIGINT;
+ALTER TABLE Login ADD COLUMN IF NOT EXISTS phone VARCHAR(32) UNIQUE;
+ALTER TABLE Login ALTER COLUMN email DROP NOT NULL;
+ALTER TABLE Login ALTER COLUMN password DROP NOT NULL;
+ALTER TABLE Login ALTER COLUMN SALT DROP NOT NULL;
+ALTER TABLE Login DROP CONSTRAINT IF EXISTS chk_identity;
+ALTER TABLE Login ADD CONSTRAINT chk_identity CHECK (
+    (email IS NOT NULL AND password IS NOT NULL AND SALT IS NOT NULL) OR (role = 'patient' AND phone IS NOT NULL)
 );
+UPDATE Login SET role = 'doctor' WHERE isdoctor AND role = 'patient';
+ALTER TABLE Login ADD COLUMN IF NOT EXISTS patient_id INT UNIQUE REFERENCES Patients(id);
+ALTER TABLE Login ADD COLUMN IF NOT EXISTS doctor_id INT UNIQUE REFERENCES Doctors(id);
+
+-- - front desk staff of a clinic, they book and check in patients for the doctors there
+CREATE TABLE IF NOT EXISTS Receptionists (
+    id BIGSERIAL PRIMARY KEY,
+    name VARCHAR(255) NOT NULL,
+    email VARCHAR(255) NOT NULL UNIQUE,
+    phone VARCHAR(255) NOT NULL,
+    clinic_id INT NOT NULL,
+    FOREIGN KEY (clinic_id) REFERENCES Clinics(id)
+);
+
+ALTER TABLE Login ADD COLUMN IF NOT EXISTS receptionist_id INT UNIQUE REFERENCES Receptionists(id);
+
+-- - logins used to be matched to their profile by email, link them up and drop the ones left over from half finished signups
+UPDATE Login l SET patient_id = p.id FROM Patients p WHERE l.patient_id IS NULL AND l.role = 'patient' AND p.email = l.email;
+UPDATE Login l SET doctor_id = d.id FROM Doctors d WHERE l.doctor_id IS NULL AND l.role = 'doctor' AND d.email = l.email;
+UPDATE Login l SET receptionist_id = r.id FROM Receptionists r WHERE l.receptionist_id IS NULL AND l.role = 'receptionist' AND r.email = l.email;
+DELETE FROM Login WHERE (role = 'patient' AND patient_id IS NULL) OR (role = 'doctor' AND doctor_id IS NULL)
+    OR (role = 'receptionist' AND receptionist_id IS NULL);
+
+ALTER TABLE Login DROP CONSTRAINT IF EXISTS chk_profile;
+ALTER TABLE Login ADD CONSTRAINT chk_profile CHECK (
+    (role <> 'patient' OR patient_id IS NOT NULL)
+    AND (role <> 'doctor' OR doctor_id IS NOT NULL)
+    AND (role <> 'receptionist' OR receptionist_id IS NOT NULL)
+);
+
+-- - single use tokens mailed out for verifying emails and resetting passwords, only their HMAC is stored
+CREATE TABLE IF NOT EXISTS Auth_Tokens (
+    id BIGSERIAL PRIMARY KEY,
+    login_id INT NOT NULL,
+    purpose VARCHAR(255) NOT NULL,
+    token_hash VARCHAR(255) NOT NULL UNIQUE,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP,
+    FOREIGN KEY (login_id) REFERENCES Login(id),
+    CONSTRAINT chk_purpose CHECK (purpose IN ('verify_email', 'reset_password', 'login_challenge'))
+);
+
+-- - login_challenge is handed out by /login when the second factor is still needed
+ALTER TABLE Auth_Tokens DROP CONSTRAINT IF EXISTS chk_purpose;
+ALTER TABLE Auth_Tokens ADD CONSTRAINT chk_purpose CHECK (purpose IN ('verify_email', 'reset_password', 'login_challenge'));
+
+-- - 2FA recovery codes, only their HMACs are kept and each can be used once
+CREATE TABLE IF NOT EXISTS Recovery_Codes (
+    id BIGSERIAL PRIMARY KEY,
+    login_id INT NOT NULL,
+    code_hash VARCHAR(255) NOT NULL UNIQUE,
+    used_at TIMESTAMP,
+    FOREIGN KEY (login_id) REFERENCES Login(id)
+);
+
+CREATE INDEX IF NOT EXISTS recovery_codes_login ON Recovery_Codes (login_id);
+
+-- - one time codes texted for signing up and logging in with a phone number, only the newest one for a phone works
+CREATE TABLE IF NOT EXISTS Phone_Otps (
+    id BIGSERIAL PRIMARY KEY,
+    phone VARCHAR(32) NOT NULL,
+    code_hash VARCHAR(255) NOT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS phone_otps_phone ON Phone_Otps (phone);
+
+-- - every login hands out a JWT tied to one of these, revoking it logs that device out
+CREATE TABLE IF NOT EXISTS Sessions (
+    id BIGSERIAL PRIMARY KEY,
+    login_id INT NOT NULL,
+    user_agent VARCHAR(255),
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    last_seen_at TIMESTAMP NOT NULL DEFAULT now(),
+    revoked_at TIMESTAMP,
+    FOREIGN KEY (login_id) REFERENCES Login(id)
+);
+
+CREATE INDEX IF NOT EXISTS sessions_login ON Sessions (login_id);
+
+-- - token buckets for rate limiting when RATE_LIMIT_STORE=postgres, so every instance shares them
+CREATE TABLE IF NOT EXISTS Rate_Limits (
+    key VARCHAR(255) PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    allowed BOOLEAN NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT clock_timestamp()
+);
+
+-- - every change made through the admin API, who made it and to what
+CREATE TABLE IF NOT EXISTS Audit_Log (
+    id BIGSERIAL PRIMARY KEY,
+    actor_id INT NOT NULL,
+    actor_role VARCHAR(255) NOT NULL,
+    action VARCHAR(255) NOT NULL,
+    target VARCHAR(255) NOT NULL,
+    details TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    -- - set for reads and writes of a patient's health data
+    patient_id INT,
+    resource_id BIGINT,
+    -- - X-Request-Id of the request that caused it
+    request_id VARCHAR(64)
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_created ON Audit_Log (created_at);
+
+ALTER TABLE Audit_Log ADD COLUMN IF NOT EXISTS patient_id INT;
+ALTER TABLE Audit_Log ADD COLUMN IF NOT EXISTS resource_id BIGINT;
+ALTER TABLE Audit_Log ADD COLUMN IF NOT EXISTS request_id VARCHAR(64);
+CREATE INDEX IF NOT EXISTS audit_log_patient ON Audit_Log (patient_id, created_at);
+
+-- - nothing written to the audit log can be changed or removed afterwards
+CREATE OR REPLACE FUNCTION audit_log_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'Audit_Log is append only';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS audit_log_append_only ON Audit_Log;
+CREATE TRIGGER audit_log_append_only BEFORE UPDATE OR DELETE OR TRUNCATE ON Audit_Log
+    FOR EACH STATEMENT EXECUTE FUNCTION audit_log_append_only();
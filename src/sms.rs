@@ -0,0 +1,44 @@
+//sending text messages to users; SMS_SENDER picks how, for now only log (the default) exists which
+//writes them to the log, meant for local development until an SMS gateway is plugged in
+use async_trait::async_trait;
+use std::env;
+
+#[async_trait]
+pub trait SmsSender: Send + Sync {
+    async fn send(&self, to: &str, body: &str) -> bool;
+}
+
+pub struct LogSender;
+
+#[async_trait]
+impl SmsSender for LogSender {
+    async fn send(&self, to: &str, body: &str) -> bool {
+        tracing::info!("SMS to {}\n\n{}", to, body);
+        true
+    }
+}
+
+pub fn from_env() -> Box<dyn SmsSender> {
+    if let Ok(sender) = env::var("SMS_SENDER") {
+        if !sender.is_empty() && sender != "log" {
+            tracing::error!("No such SMS sender {}, writing messages to the log", sender);
+        }
+    }
+    Box::new(LogSender)
+}
+
+//the number as it is stored and texted to: digits with an optional leading +, spaces, dashes,
+//dots and brackets people type in between are dropped
+pub fn normalize_phone(phone: &str) -> Option<String> {
+    let phone: String = phone
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '.' | '(' | ')'))
+        .collect();
+    let digits = phone.strip_prefix('+').unwrap_or(&phone);
+    if (7..=15).contains(&digits.len()) && digits.chars().all(|c| c.is_ascii_digit()) {
+        Some(phone)
+    } else {
+        None
+    }
+}
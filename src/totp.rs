@@ -0,0 +1,154 @@
+//time based one time passwords (RFC 6238) for two factor authentication, with the settings every
+//authenticator app defaults to: HMAC-SHA1, 6 digits and a new code every 30 seconds
+use hmac::{Hmac, Mac};
+use qrcode::{Color, QrCode};
+use rand::{rngs::OsRng, RngCore};
+use sha1::Sha1;
+use std::env;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECRET_BYTES: usize = 20;
+const DIGITS: u32 = 6;
+const STEP_SECONDS: u64 = 30;
+//codes from one step before or after are accepted too, for phones whose clock is a bit off
+const ALLOWED_DRIFT: i64 = 1;
+pub const RECOVERY_CODES: usize = 10;
+const RECOVERY_CODE_BYTES: usize = 5;
+//size of a QR code module in the SVG, and the white border around it in modules
+const QR_MODULE: usize = 4;
+const QR_QUIET_ZONE: usize = 4;
+
+pub fn new_secret() -> String {
+    let mut bytes = [0u8; SECRET_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    base32::encode(base32::Alphabet::RFC4648 { padding: false }, &bytes)
+}
+
+fn current_step() -> i64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    (now / STEP_SECONDS) as i64
+}
+
+fn code_at(key: &[u8], step: i64) -> Option<u32> {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).ok()?;
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    //dynamic truncation, the last nibble says where the 31 bits of the code start
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let bits = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]) & 0x7fff_ffff;
+    Some(bits % 10u32.pow(DIGITS))
+}
+
+//the time step the code belongs to when it is valid, only steps after the last used one count
+//so a code can't be used a second time
+pub fn matching_step(secret: &str, code: &str, last_step: Option<i64>) -> Option<i64> {
+    matching_step_at(secret, code, last_step, current_step())
+}
+
+fn matching_step_at(secret: &str, code: &str, last_step: Option<i64>, now: i64) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let key = base32::decode(base32::Alphabet::RFC4648 { padding: false }, secret)?;
+    (now - ALLOWED_DRIFT..=now + ALLOWED_DRIFT)
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| code_at(&key, *step) == Some(code))
+}
+
+//TOTP_ISSUER is the name authenticator apps show next to the account
+pub fn provisioning_uri(secret: &str, email: &str) -> String {
+    let issuer = env::var("TOTP_ISSUER").unwrap_or_else(|_| String::from("Hackshetra"));
+    format!(
+        "otpauth://totp/{0}:{1}?secret={2}&issuer={0}&algorithm=SHA1&digits={3}&period={4}",
+        urlencoding::encode(&issuer),
+        urlencoding::encode(email),
+        secret,
+        DIGITS,
+        STEP_SECONDS
+    )
+}
+
+pub fn qr_svg(data: &str) -> Option<String> {
+    let Ok(qr) = QrCode::new(data.as_bytes()) else {
+        tracing::error!("Couldn't generate QR code for provisioning URI");
+        return None;
+    };
+    let width = qr.width();
+    let size = (width + 2 * QR_QUIET_ZONE) * QR_MODULE;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" viewBox=\"0 0 {0} {0}\"><rect width=\"{0}\" height=\"{0}\" fill=\"#fff\"/><path fill=\"#000\" d=\"",
+        size
+    );
+    for (i, color) in qr.to_colors().iter().enumerate() {
+        if *color == Color::Dark {
+            let x = (i % width + QR_QUIET_ZONE) * QR_MODULE;
+            let y = (i / width + QR_QUIET_ZONE) * QR_MODULE;
+            svg.push_str(&format!("M{} {}h{}v{}h-{}z", x, y, QR_MODULE, QR_MODULE, QR_MODULE));
+        }
+    }
+    svg.push_str("\"/></svg>");
+    Some(svg)
+}
+
+//single use codes for when the authenticator is lost, shown once and only their hashes are kept
+pub fn recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let mut bytes = [0u8; RECOVERY_CODE_BYTES];
+            OsRng.fill_bytes(&mut bytes);
+            hex::encode(bytes)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //the SHA-1 key of the RFC 6238 test vectors
+    const RFC_KEY: &[u8] = b"12345678901234567890";
+
+    fn secret() -> String {
+        base32::encode(base32::Alphabet::RFC4648 { padding: false }, RFC_KEY)
+    }
+
+    fn code(step: i64) -> String {
+        format!("{:06}", code_at(RFC_KEY, step).unwrap())
+    }
+
+    #[test]
+    fn codes_match_rfc_vectors() {
+        //the RFC lists 8 digit codes, these are their last 6 digits
+        for (time, expected) in [(59, 287082), (1111111109, 81804), (1234567890, 5924), (2000000000, 279037)] {
+            assert_eq!(code_at(RFC_KEY, time / STEP_SECONDS as i64), Some(expected), "at T={}", time);
+        }
+    }
+
+    #[test]
+    fn codes_within_drift_are_accepted() {
+        let now = 1000;
+        for step in [now - 1, now, now + 1] {
+            assert_eq!(matching_step_at(&secret(), &code(step), None, now), Some(step));
+        }
+        for step in [now - 2, now + 2] {
+            assert_eq!(matching_step_at(&secret(), &code(step), None, now), None);
+        }
+        assert_eq!(matching_step_at(&secret(), &format!(" {} ", code(now)), None, now), Some(now));
+        assert_eq!(matching_step_at(&secret(), &code(now)[1..], None, now), None);
+        assert_eq!(matching_step_at(&secret(), "abcdef", None, now), None);
+    }
+
+    #[test]
+    fn used_steps_are_rejected() {
+        let now = 1000;
+        assert_eq!(matching_step_at(&secret(), &code(now), Some(now), now), None);
+        assert_eq!(matching_step_at(&secret(), &code(now - 1), Some(now - 1), now), None);
+        assert_eq!(matching_step_at(&secret(), &code(now), Some(now - 1), now), Some(now));
+        assert_eq!(matching_step_at(&secret(), &code(now + 1), Some(now), now), Some(now + 1));
+    }
+}
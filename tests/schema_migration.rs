@@ -0,0 +1,87 @@
+//src/schema.sql has to bring a database created with the very first schema up to date, and has to be
+//safe to run again. The baseline files are the schema and dummy data from before any migrations existed.
+//Needs a Postgres server, TEST_DATABASE_URL is a connection string for a user that can create databases
+//and the test is skipped when it isn't set
+use sqlx::{Connection, Executor, PgConnection, Row};
+
+const BASELINE_SCHEMA: &str = include_str!("baseline_schema.sql");
+const BASELINE_DATA: &str = include_str!("baseline_dummydata.sql");
+const SCHEMA: &str = include_str!("../src/schema.sql");
+
+//the same server with another database name
+fn database_url(url: &str, name: &str) -> String {
+    let (base, params) = match url.split_once('?') {
+        Some((base, params)) => (base, format!("?{}", params)),
+        None => (url, String::new()),
+    };
+    let server = base.rsplit_once('/').map_or(base, |(server, _)| server);
+    format!("{}/{}{}", server, name, params)
+}
+
+async fn create_database(admin: &mut PgConnection, url: &str, name: &str) -> PgConnection {
+    drop_database(admin, name).await;
+    admin.execute(format!("create database {}", name).as_str()).await.unwrap();
+    PgConnection::connect(&database_url(url, name)).await.unwrap()
+}
+
+async fn drop_database(admin: &mut PgConnection, name: &str) {
+    admin
+        .execute(format!("drop database if exists {} with (force)", name).as_str())
+        .await
+        .unwrap();
+}
+
+async fn columns(conn: &mut PgConnection) -> Vec<String> {
+    conn.fetch_all(
+        "select table_name || '.' || column_name || ' ' || data_type || ' ' || is_nullable as col
+        from information_schema.columns where table_schema = 'public' order by 1",
+    )
+    .await
+    .unwrap()
+    .iter()
+    .map(|row| row.get("col"))
+    .collect()
+}
+
+#[tokio::test]
+async fn baseline_database_upgrades_to_current_schema() {
+    let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+        eprintln!("TEST_DATABASE_URL isn't set, skipping");
+        return;
+    };
+    let mut admin = PgConnection::connect(&url).await.unwrap();
+
+    let mut fresh = create_database(&mut admin, &url, "schema_test_fresh").await;
+    fresh.execute(SCHEMA).await.unwrap();
+    fresh.execute(SCHEMA).await.unwrap();
+
+    let mut upgraded = create_database(&mut admin, &url, "schema_test_upgraded").await;
+    upgraded.execute(BASELINE_SCHEMA).await.unwrap();
+    upgraded.execute(BASELINE_DATA).await.unwrap();
+    upgraded.execute(SCHEMA).await.unwrap();
+    upgraded.execute(SCHEMA).await.unwrap();
+
+    let (upgraded_columns, fresh_columns) = (columns(&mut upgraded).await, columns(&mut fresh).await);
+    let missing: Vec<&String> = fresh_columns.iter().filter(|c| !upgraded_columns.contains(c)).collect();
+    let extra: Vec<&String> = upgraded_columns.iter().filter(|c| !fresh_columns.contains(c)).collect();
+    assert!(missing.is_empty() && extra.is_empty(), "missing after upgrade: {:?}, left over: {:?}", missing, extra);
+    //every doctor got a clinic of their own and their slots and tokens moved to it
+    let row = upgraded
+        .fetch_one(
+            "select (select count(*) from doctors) as doctors,
+            (select count(*) from clinics c join doctor_clinics dc on dc.clinic_id = c.id and dc.doctor_id = c.owner_id) as owned,
+            (select count(*) from doctor_slots) as slots,
+            (select count(*) from doctor_slots s join doctor_clinics dc on dc.clinic_id = s.clinic_id and dc.doctor_id = s.doctor_id) as moved",
+        )
+        .await
+        .unwrap();
+    assert!(row.get::<i64, _>("doctors") > 0);
+    assert_eq!(row.get::<i64, _>("owned"), row.get::<i64, _>("doctors"));
+    assert!(row.get::<i64, _>("slots") > 0);
+    assert_eq!(row.get::<i64, _>("moved"), row.get::<i64, _>("slots"));
+
+    fresh.close().await.unwrap();
+    upgraded.close().await.unwrap();
+    drop_database(&mut admin, "schema_test_fresh").await;
+    drop_database(&mut admin, "schema_test_upgraded").await;
+}